@@ -1,6 +1,22 @@
 import socket
 import ssl
-
+import struct
+
+ESCAPES={"\\":"\\","q":"?","c":"^","w":"~","b":"[","l":"\n"}
+
+def unescape_text(text)->str:
+    # the server escapes marker characters found in command output
+    # (\q -> ?, \c -> ^, \w -> ~, \b -> [, \l -> newline, \\ -> \)
+    result=""
+    i=0
+    while i<len(text):
+        if text[i]=="\\" and i+1<len(text) and text[i+1] in ESCAPES:
+            result+=ESCAPES[text[i+1]]
+            i+=2
+            continue
+        result+=text[i]
+        i+=1
+    return result
 
 class TlsClient:
     def __init__(self):
@@ -38,31 +54,22 @@ class TlsClient:
         self.server_cert_path = None
 
     def sent(self,message):
-        self.ssock.sendall(message.encode("utf-8"))
-    def receive4096(self)->str:
-        answer = self.ssock.recv(4096).decode("utf-8")
-        return answer
-    def receive(self)->str:
+        data=message.encode("utf-8")
+        self.ssock.sendall(struct.pack(">I",len(data))+data)
+    def receive_exact(self,size)->bytes:
         buffer = b""
-        terminator = b"\r\n\r\n"
-        while True:
-            chunk = self.ssock.recv(4096)
+        while len(buffer) < size:
+            chunk = self.ssock.recv(size-len(buffer))
             if not chunk:
                 break
             buffer += chunk
-            if terminator in buffer:
-                break
-
-        answer = buffer.decode("utf-8", errors="ignore")
+        return buffer
+    def receive4096(self)->str:
+        return self.receive()
+    def receive(self)->str:
+        header = self.receive_exact(4)
+        if len(header) < 4:
+            return ""
+        (size,) = struct.unpack(">I",header)
+        answer = self.receive_exact(size).decode("utf-8", errors="ignore")
         return answer
-
-
-
-
-
-
-
-
-
-
-
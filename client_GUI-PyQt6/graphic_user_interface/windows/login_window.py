@@ -1,5 +1,6 @@
 from PyQt6.QtWidgets import (
-    QWidget, QPushButton, QLabel, QLineEdit, QGridLayout, QWidgetAction, QFileDialog
+    QWidget, QPushButton, QLabel, QLineEdit, QGridLayout, QWidgetAction, QFileDialog,
+    QInputDialog
 )
 from PyQt6.QtGui import QCursor
 from PyQt6.QtCore import Qt
@@ -71,6 +72,11 @@ class LoginWindow(QWidget):
         self.parent.client.sent(login)
         message=self.parent.client.receive()
         stript_message=message.strip()
+        if "A one-time code is required" in stript_message:
+            code, ok = QInputDialog.getText(self, "Two-factor login", "One-time code:")
+            if ok:
+                self.parent.client.sent(login+" "+code.strip())
+                stript_message=self.parent.client.receive().strip()
         if stript_message == "?&NSuccesful login[-]:[-]":
             self.parent.show_secure_shell_window()
         else:
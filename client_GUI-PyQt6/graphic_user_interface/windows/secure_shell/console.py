@@ -3,6 +3,7 @@ from PyQt6.QtWidgets import (
     QVBoxLayout, QHBoxLayout, QTextEdit
 )
 from PyQt6.QtCore import Qt
+from backend.client import unescape_text
 
 
 class Console:
@@ -53,7 +54,7 @@ class Console:
             self.output.append(f"Server{self.ssh.current_path}> {command}")
             self.ssh.parent.client.sent(command)
             output=self.ssh.parent.client.receive().split("[-]")
-            self.ssh.current_path = output[1]
+            self.ssh.current_path = unescape_text(output[1])
             self.output.append(self.get_unformated_text(output[0]))
             self.input_field.clear()
             self.ssh.primary_menu.refresh_function()
@@ -74,6 +75,7 @@ class Console:
                 for e in word:
                     i = 0
                     chars = list(e)
+                    clean = ""
                     while i < len(chars):
                         c = chars[i]
 
@@ -88,22 +90,23 @@ class Console:
                             continue
 
                         # Append character
-                        new_text += c
+                        clean += c
                         i += 1
+                    new_text += unescape_text(clean)
 
             elif first_char is not None:
                 for e in word:
                     if e.startswith('^'):
                         # Skip first 2 characters
-                        tail = e[2:]
+                        tail = unescape_text(e[2:])
                         if not new_text:
                             new_text = tail
                         else:
                             new_text = f"{new_text} {tail}"
                     else:
                         if not new_text:
-                            new_text = e
+                            new_text = unescape_text(e)
                         else:
-                            new_text = f"{new_text} {e}"
+                            new_text = f"{new_text} {unescape_text(e)}"
 
         return new_text
\ No newline at end of file
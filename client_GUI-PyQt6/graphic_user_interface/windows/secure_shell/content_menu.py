@@ -3,6 +3,7 @@ from PyQt6.QtWidgets import (
 )
 from PyQt6.QtGui import QIcon,QCursor
 from PyQt6.QtCore import Qt,QSize
+from backend.client import unescape_text
 
 class Content:
     def __init__(self,ssh):
@@ -23,7 +24,7 @@ class Content:
 
         scroll_grid = QGridLayout(container)
         self.ssh.parent.client.sent(f"cat \"{text}\"")
-        content = self.ssh.parent.client.receive().split("[-]")[0][4:]
+        content = unescape_text(self.ssh.parent.client.receive().split("[-]")[0][4:])
         if len(content.strip())==0:
             content="No data could be read from the file. It may be empty or access is restricted."
         content_lbn = QTextEdit()
@@ -4,6 +4,7 @@ from PyQt6.QtWidgets import (
 from graphic_user_interface.windows.secure_shell.content_menu import Content
 from PyQt6.QtGui import QIcon,QCursor,QAction
 from PyQt6.QtCore import Qt,QSize
+from backend.client import unescape_text
 
 class FileArea:
     def __init__(self,ssh):
@@ -46,7 +47,7 @@ class FileArea:
             if len(i) == 0:
                 continue
             if i.startswith("^!"):
-                i = i[2:]
+                i = unescape_text(i[2:])
                 file = QGridLayout()
                 file.setContentsMargins(10, 10, 10, 10)
 
@@ -78,9 +79,10 @@ class FileArea:
 
                 btn = QPushButton()
                 if not i.startswith("^#"):
+                    i = unescape_text(i)
                     btn.setIcon(QIcon("graphic_user_interface/Assets/Icons/file.png"))
                 else:
-                    i = i[2:]
+                    i = unescape_text(i[2:])
                     btn.setIcon(QIcon("graphic_user_interface/Assets/Icons/exe.png"))
                 btn.setIconSize(QSize(64, 64))
                 btn.clicked.connect(lambda _, val=i: self.content.toggle_content_menu(val))
@@ -6,6 +6,7 @@ from graphic_user_interface.windows.secure_shell.file_area import FileArea
 from  graphic_user_interface.windows.secure_shell.left_menu import SecunaryMenu
 from  graphic_user_interface.windows.secure_shell.top_menu import PrimaryMenu
 from  graphic_user_interface.windows.secure_shell.console import Console
+from backend.client import unescape_text
 
 class SecureShellWindow(QWidget):
     def __init__(self, parent_window=None):
@@ -46,6 +47,6 @@ class SecureShellWindow(QWidget):
         self.parent.client.sent("ls")
         text=self.parent.client.receive().strip()
         output=text.split("[-]")
-        self.current_path=output[1]
+        self.current_path=unescape_text(output[1])
         self.files=output[0]
 
@@ -6,6 +6,7 @@ QVBoxLayout
 
 from PyQt6.QtGui import QIcon
 from PyQt6.QtCore import Qt,QSize
+from backend.client import unescape_text
 
 
 class PrimaryMenu:
@@ -79,14 +80,14 @@ class PrimaryMenu:
         scroll_area = QScrollArea()
         scroll_area.setWidgetResizable(True)
         scroll_area.setMinimumHeight(200)
-        scroll_area.setMinimumWidth(250)
+        scroll_area.setMinimumWidth(600)
         container = QWidget()
         container_layout = QVBoxLayout(container)
         self.ssh.parent.client.sent("who")
         active_users = self.ssh.parent.client.receive().split("[-]")[0][4:].split("\n\n")
         for user in active_users:
-            user_label = QLabel(user)
-            user_label.setStyleSheet("padding: 5px; border-bottom: 1px solid #ccc;")
+            user_label = QLabel(unescape_text(user))
+            user_label.setStyleSheet("padding: 5px; border-bottom: 1px solid #ccc; font-family: monospace;")
             container_layout.addWidget(user_label)
 
         scroll_area.setWidget(container)
@@ -1,16 +1,31 @@
+use crate::client_configure::Configure;
+use crate::line_editor::{EditorEvent, LineEditor};
 use crate::response_handlers::response_formatter::ShowResponse;
 use colored::*;
 use core::panic;
-use std::io::Write;
+use protocol::auth::{CODE_REQUIRED, Challenge, KeyLogin, signed_message};
+use protocol::complete::{CompletionRequest, Completions};
+use protocol::format::{Format, get_unformated_text, split_sections};
+use protocol::frame::{read_frame, write_frame};
+use protocol::handshake::{Capability, Hello};
+use protocol::message::{PING_MARKER, Request, Response};
+use ring::signature::Ed25519KeyPair;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{fs::File, io::BufReader, sync::Arc};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
 use tokio_rustls::{
     TlsConnector,
     client::TlsStream,
-    rustls::{Certificate, ClientConfig, RootCertStore, ServerName},
+    rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName},
 };
 
+/// Gives the one-time code of a login, instead of asking for it on stdin.
+type CodeSource<'a> = dyn FnMut() -> Result<String, Box<dyn std::error::Error>> + 'a;
+
 /// A secure shell client for connecting to and interacting with TLS-encrypted shell servers.
 /// 
 /// `Client` provides a complete client implementation for secure shell sessions over TLS.
@@ -35,9 +50,10 @@ use tokio_rustls::{
 /// 
 /// # Communication Protocol
 /// 
-/// Messages follow a structured format:
+/// Every message is sent as a length-prefixed frame (4-byte big-endian length
+/// followed by the payload). Server replies carry a structured payload:
 /// ```text
-/// response_content[-]:current_path[-]\r\n\r\n
+/// response_content[-]:current_path[-]
 /// ```
 /// 
 /// # Examples
@@ -69,9 +85,16 @@ use tokio_rustls::{
 /// ```
 pub struct Client {
     cert: Certificate,
+    client_cert: Option<(Vec<Certificate>, PrivateKey)>,
+    identity: Option<Ed25519KeyPair>,
     ip_port: String,
     server_name: ServerName,
     tls_stream: Option<TlsStream<TcpStream>>,
+    welcome: Option<Response>,
+    capabilities: Vec<Capability>,
+    keepalive: Option<Duration>,
+    lost: Option<String>,
+    history_path: Option<PathBuf>,
 }
 impl Client {
         /// Creates a new `Client` instance with the specified connection parameters.
@@ -112,18 +135,89 @@ impl Client {
         });
         Client {
             cert,
+            client_cert: None,
+            identity: None,
             ip_port: ip_port.to_string(),
             server_name,
             tls_stream: None,
+            welcome: None,
+            capabilities: Vec::new(),
+            keepalive: None,
+            lost: None,
+            history_path: None,
+        }
+    }
+    /// Loads the client certificate presented to servers that use mutual TLS.
+    ///
+    /// # Parameters
+    ///
+    /// - `cert_path`: Client certificate chain in PEM format
+    /// - `key_path`: Private key of the certificate in PKCS#8 PEM format
+    ///
+    /// # Errors
+    ///
+    /// Fails if the certificate or the key cannot be loaded.
+    pub fn set_client_certificate(
+        &mut self,
+        cert_path: &str,
+        key_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut reader = BufReader::new(File::open(cert_path)?);
+        let certs: Vec<Certificate> = rustls_pemfile::certs(&mut reader)?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+        if certs.is_empty() {
+            return Err("Certificat invalid".into());
         }
+        let mut reader = BufReader::new(File::open(key_path)?);
+        let keys = rustls_pemfile::pkcs8_private_keys(&mut reader)?;
+        let key = keys.first().ok_or("Private key not found")?;
+        self.client_cert = Some((certs, PrivateKey(key.clone())));
+        Ok(())
+    }
+    /// Returns `true` if a client certificate is presented to the server.
+    pub fn has_client_certificate(&self) -> bool {
+        self.client_cert.is_some()
+    }
+    /// Loads the Ed25519 private key used for public key logins.
+    ///
+    /// Once set, logins without a password use the challenge/response of
+    /// `protocol::auth` with servers that support it, and `login [USERNAME]` typed
+    /// in the interactive session does too.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or does not hold an Ed25519 key.
+    pub fn set_identity(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.identity = Some(Client::load_identity(path)?);
+        Ok(())
+    }
+    /// Returns `true` if an identity is set for public key logins.
+    pub fn has_identity(&self) -> bool {
+        self.identity.is_some()
+    }
+    /// Reads an Ed25519 private key in PKCS#8 PEM format, as written by
+    /// `openssl genpkey -algorithm ed25519`.
+    pub fn load_identity(path: &Path) -> Result<Ed25519KeyPair, Box<dyn std::error::Error>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let keys = rustls_pemfile::pkcs8_private_keys(&mut reader)?;
+        let key = keys.first().ok_or("Private key not found")?;
+        Ed25519KeyPair::from_pkcs8_maybe_unchecked(key)
+            .map_err(|_| "The identity is not an Ed25519 private key".into())
+    }
+    /// Sets the file where the interactive session keeps its command history.
+    pub fn set_history_path(&mut self, path: PathBuf) {
+        self.history_path = Some(path);
     }
     /// Establishes a secure TLS connection to the remote shell server.
     /// 
     /// This method performs the complete connection establishment process:
     /// 1. Creates a TCP connection to the server
-    /// 2. Configures TLS with the loaded CA certificate
+    /// 2. Configures TLS with the loaded CA certificate and the client certificate, if any
     /// 3. Performs TLS handshake with certificate validation
     /// 4. Stores the established connection for subsequent operations
+    /// 5. Negotiates the protocol version and capabilities via `handshake()`
     /// 
     /// # Returns
     /// 
@@ -134,10 +228,13 @@ impl Client {
         let mut root_cert_store = RootCertStore::empty();
         root_cert_store.add(&self.cert)?;
 
-        let config = ClientConfig::builder()
+        let builder = ClientConfig::builder()
             .with_safe_defaults()
-            .with_root_certificates(root_cert_store)
-            .with_no_client_auth();
+            .with_root_certificates(root_cert_store);
+        let config = match &self.client_cert {
+            Some((certs, key)) => builder.with_single_cert(certs.clone(), key.clone())?,
+            None => builder.with_no_client_auth(),
+        };
 
         let connector = TlsConnector::from(Arc::new(config));
 
@@ -147,8 +244,421 @@ impl Client {
 
         self.tls_stream = Some(connector.connect(server_name, stream).await?);
 
+        self.handshake().await
+    }
+    /// Exchanges `Hello` messages with the server right after the TLS handshake.
+    ///
+    /// The welcome message sent by the server is kept for `send_and_receive()`, then
+    /// the client announces its protocol version and capabilities. A server that
+    /// refuses the connection (e.g. too many connections from this address) sends an
+    /// error instead of the welcome message, which is returned as the error. A server
+    /// that does not know the handshake answers with an ordinary response instead of
+    /// a `Hello`; in that case the client falls back to the features every server
+    /// supports.
+    ///
+    /// When `Capability::Keepalive` is negotiated, the interval the server asked for is
+    /// kept: the interactive session pings the server at that pace while it waits for
+    /// the user.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: Handshake completed, `capabilities` holds the negotiated set
+    /// - `Err(Box<dyn std::error::Error>)`: The connection failed during the exchange
+    async fn handshake(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let tls_stream = self.tls_stream.as_mut().ok_or("Error TLS not configured")?;
+
+        let frame = read_frame(tls_stream)
+            .await?
+            .ok_or("Server closed the connection")?;
+        let welcome = Response::decode(&String::from_utf8_lossy(&frame));
+        if Client::exit_status(&welcome) != 0 {
+            return Err(get_unformated_text(&welcome.output).trim().into());
+        }
+        self.welcome = Some(welcome);
+
+        let hello = Hello::new(
+            concat!("client/", env!("CARGO_PKG_VERSION")),
+            &Capability::ALL,
+        );
+        write_frame(
+            tls_stream,
+            Request::Hello(hello.clone()).encode().as_bytes(),
+        )
+        .await?;
+        let frame = read_frame(tls_stream)
+            .await?
+            .ok_or("Server closed the connection")?;
+        let server_hello = Hello::decode(&String::from_utf8_lossy(&frame));
+        self.capabilities = match &server_hello {
+            Some(server_hello) => hello.negotiate(server_hello),
+            None => vec![Capability::Framing],
+        };
+        if self.capabilities.contains(&Capability::Keepalive) {
+            self.keepalive = server_hello
+                .and_then(|server_hello| server_hello.keepalive)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs);
+        }
         Ok(())
     }
+    /// Logs in as `user` before the interactive session starts.
+    ///
+    /// The welcome message is shown first, then `login [USERNAME] [PASSWORD]` is sent
+    /// with both values quoted, so a password containing spaces or quotes reaches the
+    /// server unchanged. Without a password the client logs in with its identity
+    /// (see `set_identity()`), or sends `login [USERNAME]`, which the server only
+    /// accepts when the client certificate maps to `user`. The server reply is
+    /// displayed like any other response.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(true)`: The server accepted the credentials
+    /// - `Ok(false)`: The login was rejected
+    /// - `Err(Box<dyn std::error::Error>)`: The connection failed
+    pub async fn login(
+        &mut self,
+        user: &str,
+        password: Option<&str>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if let Some(welcome) = self.welcome.take() {
+            ShowResponse::new(welcome.output).show();
+        }
+        let answer = self.login_request(user, password, None).await?;
+        let succes = Client::exit_status(&answer) == 0;
+        ShowResponse::new(answer.output).show();
+        Ok(succes)
+    }
+    /// Runs a single command without an interactive session, like `ssh host cmd`.
+    ///
+    /// The client logs in as `user`, sends `command`, displays its output and closes
+    /// the connection. Nothing else is printed, so the output can be piped into
+    /// other programs.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(status)`: Exit status of the command chain (`0` on success, `1` otherwise)
+    /// - `Err(Box<dyn std::error::Error>)`: The login was rejected or the connection failed
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// client.connect_to_server().await?;
+    /// let status = client.execute("alice", Some(&password), "ls docs && cat notes.txt").await?;
+    /// std::process::exit(status);
+    /// ```
+    pub async fn execute(
+        &mut self,
+        user: &str,
+        password: Option<&str>,
+        command: &str,
+    ) -> Result<i32, Box<dyn std::error::Error>> {
+        self.authenticate(user, password, None).await?;
+        let answer = self.request(command.to_string()).await?;
+        let status = Client::exit_status(&answer);
+        ShowResponse::new(answer.output).show();
+        std::io::stdout().flush()?;
+        if let Some(tls_stream) = self.tls_stream.as_mut() {
+            tls_stream.shutdown().await?;
+        }
+        Ok(status)
+    }
+    /// Runs the command lines of a script in order within one session.
+    ///
+    /// Each line is reported as `Server:cwd>line`, with the server-side directory
+    /// the line runs in, followed by its output. Empty lines and lines starting with
+    /// `#` are skipped, and an `exit` line ends the script early. When `user` is
+    /// `None` the script has to log in itself with a `login` line. When the account
+    /// needs a one-time code, the code is the first line of the script, since stdin
+    /// may be the script itself.
+    ///
+    /// # Parameters
+    ///
+    /// - `script`: Source of the command lines (a file or stdin)
+    /// - `credentials`: User and password to log in with before the first line; the
+    ///   password is `None` when the client certificate replaces it
+    /// - `stop_on_error`: Stop at the first line whose exit status is not `0`
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(status)`: Exit status of the last line that ran
+    /// - `Err(Box<dyn std::error::Error>)`: The login was rejected, the script could
+    ///   not be read or the connection failed
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let script = BufReader::new(File::open("deploy.txt")?);
+    /// let status = client.run_script(script, Some(("alice", Some(&password))), true).await?;
+    /// ```
+    pub async fn run_script(
+        &mut self,
+        mut script: impl BufRead,
+        credentials: Option<(&str, Option<&str>)>,
+        stop_on_error: bool,
+    ) -> Result<i32, Box<dyn std::error::Error>> {
+        let mut last_path = self.welcome.take().map(|w| w.path).unwrap_or_default();
+        let mut number = 0;
+        if let Some((user, password)) = credentials {
+            let mut code = || {
+                number += 1;
+                Client::read_code(&mut script)
+            };
+            last_path = self
+                .authenticate(user, password, Some(&mut code))
+                .await?
+                .path;
+        }
+        let mut status = 0;
+        for line in script.lines() {
+            let line = line?;
+            number += 1;
+            let command = line.trim();
+            if command.is_empty() || command.starts_with('#') {
+                continue;
+            }
+            if command == "exit" {
+                break;
+            }
+            println!("{}:{}>{}", "Server".cyan(), last_path.cyan(), command);
+            let answer = self.request(command.to_string()).await?;
+            status = Client::exit_status(&answer);
+            last_path = answer.path.clone();
+            ShowResponse::new(answer.output).show();
+            if status != 0 && stop_on_error {
+                std::io::stdout().flush()?;
+                eprintln!("Stopped at line {}: exit status {}", number, status);
+                break;
+            }
+        }
+        std::io::stdout().flush()?;
+        if let Some(tls_stream) = self.tls_stream.as_mut() {
+            tls_stream.shutdown().await?;
+        }
+        Ok(status)
+    }
+    /// Logs in as `user` without displaying anything.
+    ///
+    /// `code` gives the one-time code when the account needs one; without it the
+    /// code is asked for on stdin.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Response)`: The reply of the server to the login
+    /// - `Err(Box<dyn std::error::Error>)`: The login was rejected, with the server
+    ///   message as error, or the connection failed
+    async fn authenticate(
+        &mut self,
+        user: &str,
+        password: Option<&str>,
+        code: Option<&mut CodeSource<'_>>,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        self.welcome = None;
+        let answer = self.login_request(user, password, code).await?;
+        if Client::exit_status(&answer) != 0 {
+            return Err(get_unformated_text(&answer.output).trim().into());
+        }
+        Ok(answer)
+    }
+    /// Sends the login of `user` and returns the reply of the server.
+    ///
+    /// A public key login is used when no password is given, an identity is set and
+    /// the server negotiated `Capability::PublicKey`; otherwise the `login` command.
+    /// When the server answers that the account needs a one-time code, the code is
+    /// taken from `code`, or asked for on stdin, and the login is sent again with it.
+    async fn login_request(
+        &mut self,
+        user: &str,
+        password: Option<&str>,
+        code: Option<&mut CodeSource<'_>>,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        if password.is_none()
+            && self.identity.is_some()
+            && self.capabilities.contains(&Capability::PublicKey)
+        {
+            return self.key_login(user).await;
+        }
+        let answer = self.request(Client::login_line(user, password)).await?;
+        match password {
+            Some(password)
+                if get_unformated_text(&answer.output)
+                    .trim()
+                    .starts_with(CODE_REQUIRED) =>
+            {
+                let code = match code {
+                    Some(code) => code()?,
+                    None => Configure::new().set_code(user),
+                };
+                let line = format!("{} {}", Client::login_line(user, Some(password)), code);
+                self.request(line).await
+            }
+            _ => Ok(answer),
+        }
+    }
+    /// Reads the one-time code from the next line of `script`.
+    fn read_code(script: &mut impl BufRead) -> Result<String, Box<dyn std::error::Error>> {
+        let mut code = String::new();
+        if script.read_line(&mut code)? == 0 {
+            return Err("The script ends before the one-time code".into());
+        }
+        Ok(code.trim().to_string())
+    }
+    /// Logs in as `user` by signing a challenge of the server with the identity.
+    ///
+    /// The private key never leaves the client; only the signature of
+    /// `protocol::auth::signed_message()` is sent. The reply to the signature is
+    /// returned like the reply to a `login` command.
+    async fn key_login(&mut self, user: &str) -> Result<Response, Box<dyn std::error::Error>> {
+        let identity = self.identity.as_ref().ok_or("No identity set")?;
+        let tls_stream = self.tls_stream.as_mut().ok_or("Error TLS not configured")?;
+
+        let start = KeyLogin::Start {
+            user: user.to_string(),
+        };
+        write_frame(tls_stream, Request::KeyLogin(start).encode().as_bytes()).await?;
+        let frame = read_frame(tls_stream)
+            .await?
+            .ok_or("Server closed the connection")?;
+        let payload = String::from_utf8_lossy(&frame);
+        let Some(challenge) = Challenge::decode(&payload).and_then(|c| c.bytes()) else {
+            return Ok(Response::decode(&payload));
+        };
+
+        let signature = identity.sign(&signed_message(user, &challenge));
+        let proof = KeyLogin::proof(signature.as_ref());
+        write_frame(tls_stream, Request::KeyLogin(proof).encode().as_bytes()).await?;
+        let frame = read_frame(tls_stream)
+            .await?
+            .ok_or("Server closed the connection")?;
+        Ok(Response::decode(&String::from_utf8_lossy(&frame)))
+    }
+    /// Returns the user and password of a `login` line typed in the interactive
+    /// session, so it can go through `login_request()`: `login [USERNAME]` when an
+    /// identity is set, and `login [USERNAME] [PASSWORD]`.
+    fn typed_login(&self, line: &str) -> Option<(String, Option<String>)> {
+        match shell_words::split(line).ok()?.as_slice() {
+            [login, user] if login == "login" && self.identity.is_some() => {
+                Some((user.clone(), None))
+            }
+            [login, user, password] if login == "login" => {
+                Some((user.clone(), Some(password.clone())))
+            }
+            _ => None,
+        }
+    }
+    /// Builds the `login` command line with its arguments quoted.
+    fn login_line(user: &str, password: Option<&str>) -> String {
+        match password {
+            Some(password) => format!(
+                "login {} {}",
+                shell_words::quote(user),
+                shell_words::quote(password)
+            ),
+            None => format!("login {}", shell_words::quote(user)),
+        }
+    }
+    /// Asks the server for the completions of a partial word.
+    ///
+    /// Servers that did not negotiate `Capability::Complete` are not asked and no
+    /// candidates are returned.
+    pub async fn complete(
+        &mut self,
+        request: CompletionRequest,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        if !self.capabilities.contains(&Capability::Complete) {
+            return Ok(Vec::new());
+        }
+        let tls_stream = self.tls_stream.as_mut().ok_or("Error TLS not configured")?;
+
+        write_frame(tls_stream, Request::Complete(request).encode().as_bytes()).await?;
+        let frame = read_frame(tls_stream)
+            .await?
+            .ok_or("Server closed the connection")?;
+        let completions = Completions::decode(&String::from_utf8_lossy(&frame)).unwrap_or_default();
+        Ok(completions.candidates)
+    }
+    /// Checks that the server is still there with a keepalive ping.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: The server answered within `wait`
+    /// - `Err(String)`: Why the connection is considered lost; a server that closes the
+    ///   session answers the ping with its reason instead
+    async fn ping(&mut self, wait: Duration) -> Result<(), String> {
+        let tls_stream = self.tls_stream.as_mut().ok_or("Error TLS not configured")?;
+
+        let exchange = async {
+            write_frame(tls_stream, Request::Ping.encode().as_bytes()).await?;
+            read_frame(tls_stream).await
+        };
+        match timeout(wait, exchange).await {
+            Ok(Ok(Some(frame))) if frame == PING_MARKER.as_bytes() => Ok(()),
+            Ok(Ok(Some(frame))) => {
+                let answer = Response::decode(&String::from_utf8_lossy(&frame));
+                Err(get_unformated_text(&answer.output).trim().to_string())
+            }
+            Ok(Ok(None)) => Err("Server closed the connection".to_string()),
+            Ok(Err(e)) => Err(format!("Connection lost: {}", e)),
+            Err(_) => Err("The server does not answer".to_string()),
+        }
+    }
+    /// Reads the next line from the line editor, answering its completion requests
+    /// while the user types.
+    ///
+    /// When the server asked for keepalives, it is pinged every interval while the
+    /// user thinks. If a ping fails, the reason is printed once and kept in `lost`,
+    /// so the session ends after the line being typed instead of in the middle of it.
+    ///
+    /// Returns `None` once the user closed the input.
+    async fn read_line(&mut self, editor: &mut LineEditor, prompt: String) -> Option<String> {
+        editor.read_line(prompt);
+        loop {
+            let interval = self.keepalive.filter(|_| self.lost.is_none());
+            let event = tokio::select! {
+                event = editor.next_event() => event,
+                _ = sleep(interval.unwrap_or_default()), if interval.is_some() => {
+                    if let Err(e) = self.ping(interval.unwrap_or_default()).await {
+                        // The editor holds the terminal in raw mode.
+                        print!("\r\n{}\r\n", e.red());
+                        let _ = std::io::stdout().flush();
+                        self.lost = Some(e);
+                    }
+                    continue;
+                }
+            };
+            match event? {
+                EditorEvent::Line(line) => return Some(line),
+                EditorEvent::Complete(request, reply) => {
+                    let candidates = match self.lost {
+                        Some(_) => Vec::new(),
+                        None => self.complete(request).await.unwrap_or_default(),
+                    };
+                    let _ = reply.send(candidates);
+                }
+            }
+        }
+    }
+    /// Sends one command line and waits for the server reply.
+    async fn request(&mut self, line: String) -> Result<Response, Box<dyn std::error::Error>> {
+        let tls_stream = self.tls_stream.as_mut().ok_or("Error TLS not configured")?;
+
+        write_frame(tls_stream, Request::Command(line).encode().as_bytes()).await?;
+        let frame = read_frame(tls_stream)
+            .await?
+            .ok_or("Server closed the connection")?;
+        Ok(Response::decode(&String::from_utf8_lossy(&frame)))
+    }
+    /// Returns the exit status of a reply.
+    ///
+    /// Servers that did not negotiate `Capability::Status` send no status; for them
+    /// a reply is considered failed when it contains an error section.
+    fn exit_status(answer: &Response) -> i32 {
+        answer.status.unwrap_or_else(|| {
+            let failed = split_sections(&answer.output)
+                .iter()
+                .any(|section| section.format == Some(Format::Error));
+            if failed { 1 } else { 0 }
+        })
+    }
     // Clears the console screen using platform-appropriate commands.
     /// 
     /// This utility method provides cross-platform console clearing functionality,
@@ -204,70 +714,84 @@ impl Client {
     /// | `clear` | Clear screen (Unix) | Clears terminal and refreshes prompt |
     /// | `cls` | Clear screen (Windows) | Clears terminal and refreshes prompt |
     /// 
+    /// # Line Editing
+    /// 
+    /// When stdin is a terminal, lines are read with `LineEditor`: arrow keys edit
+    /// the line and recall the history kept in the file set by `set_history_path()`,
+    /// and tab completes command names and remote paths with the help of the server.
+    /// Otherwise lines are read from stdin as they come, and the end of the input
+    /// ends the session like `exit`.
+    /// 
+    /// # Keepalive
+    /// 
+    /// While the line editor waits for the user, the server is pinged at the interval
+    /// it announced in its `Hello`. A server that stopped answering, or that closed
+    /// the session (e.g. after its idle timeout), is reported at once and the session
+    /// ends with that error once the current line is entered.
+    /// 
     /// # Message Protocol
     /// 
     /// ## Server Response Format
+    /// Each command is sent as one frame and answered by exactly one frame with
+    /// the payload:
     /// ```text
-    /// response_content[-]:current_path[-]\r\n\r\n
+    /// response_content[-]:current_path[-]
     /// ```
     /// 
     pub async fn send_and_receive(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let tls_stream = self.tls_stream.as_mut().unwrap_or_else(|| {
+        if self.tls_stream.is_none() {
             panic!("Error TLS not configured");
-        });
+        }
+        let mut editor = if std::io::stdin().is_terminal() {
+            LineEditor::new(self.history_path.clone()).ok()
+        } else {
+            None
+        };
 
         let mut last_path = String::new();
 
-        let mut buf = vec![0u8; 1024];
-        let n = tls_stream.read(&mut buf).await?;
-
-        let answer = String::from_utf8_lossy(&buf[..n]);
-        let r: Vec<&str> = answer.split("[-]").collect();
-        let resonse = ShowResponse::new(r[0].to_string());
+        let answer = self.welcome.take().unwrap_or(Response::new("", ""));
+        let resonse = ShowResponse::new(answer.output);
         resonse.show();
-        print!("{}{}>", "Server".cyan(), r[1].cyan());
-        last_path += r[1];
-        std::io::stdout().flush().unwrap();
+        last_path += &answer.path;
 
         loop {
-            let mut message = String::new();
-            std::io::stdin()
-                .read_line(&mut message)
-                .expect("Read Error");
+            let prompt = format!("{}:{}>", "Server".cyan(), last_path.cyan());
+            let message = match editor.as_mut() {
+                Some(editor) => self.read_line(editor, prompt).await,
+                None => {
+                    print!("{}", prompt);
+                    std::io::stdout().flush().unwrap();
+                    let mut message = String::new();
+                    let read = std::io::stdin()
+                        .read_line(&mut message)
+                        .expect("Read Error");
+                    (read > 0).then_some(message)
+                }
+            };
+            if let Some(reason) = self.lost.take() {
+                return Err(reason.into());
+            }
+            let message = message.unwrap_or(String::from("exit"));
             if message.trim() == "exit" {
-                tls_stream.shutdown().await?;
+                if let Some(tls_stream) = self.tls_stream.as_mut() {
+                    tls_stream.shutdown().await?;
+                }
                 return Ok(());
             }
             if message.trim() == "clear" || message.trim() == "cls" {
                 Client::clear_console();
-                print!("{}{}>", "Server".cyan(), last_path.cyan());
-                std::io::stdout().flush().unwrap();
                 continue;
             }
-            tls_stream.write_all(message.as_bytes()).await?;
-
-            let mut buffer = Vec::new();
-            let mut temp_buf = vec![0u8; 1024];
-
-            loop {
-                let n = tls_stream.read(&mut temp_buf).await?;
-                if n == 0 {
-                    break;
-                }
-
-                buffer.extend_from_slice(&temp_buf[..n]);
-                if buffer.ends_with(b"\r\n\r\n") {
-                    break;
+            let answer = match self.typed_login(&message) {
+                Some((user, password)) => {
+                    self.login_request(&user, password.as_deref(), None).await?
                 }
-            }
-
-            let answer = String::from_utf8_lossy(&buffer);
-            let r: Vec<&str> = answer.split("[-]").collect();
-            let resonse = ShowResponse::new(r[0].to_string());
+                None => self.request(message).await?,
+            };
+            let resonse = ShowResponse::new(answer.output);
             resonse.show();
-            print!("{}{}>", "Server".cyan(), r[1].cyan());
-            last_path = r[1].to_string();
-            std::io::stdout().flush().unwrap();
+            last_path = answer.path;
         }
     }
     fn load_cert(path: &str) -> Result<Certificate, Box<dyn std::error::Error>> {
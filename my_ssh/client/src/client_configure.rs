@@ -1,3 +1,385 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{IsTerminal, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Port used when neither the command line nor the host profile gives one.
+pub const DEFAULT_PORT: u16 = 12345;
+/// TLS server name used when connecting to a bare IP address.
+pub const DEFAULT_SERVER_NAME: &str = "localhost";
+/// Environment variable holding the password for non-interactive logins.
+pub const PASSWORD_ENV: &str = "SECURE_SHELL_PASSWORD";
+
+/// Command-line arguments of the client.
+///
+/// The destination is either the name of a host profile from the configuration
+/// file or a server address, optionally prefixed with `USER@`. Flags override the
+/// values of the profile. Without a destination the client asks for the server
+/// address on stdin, and without a CA file it asks for the certificate path.
+///
+/// Any words after the destination form a command that is run non-interactively:
+/// the client logs in, prints the output and exits with the status of the command.
+/// With `--script` the lines of a file (or stdin for `-`) are run the same way, one
+/// after the other in a single session.
+///
+/// # Examples
+///
+/// ```text
+/// client prod-box
+/// client alice@prod-box
+/// client 10.0.0.5:12345 --ca certs/ca.crt --server-name prod.example.com
+/// client alice@prod-box ls -la docs
+/// client alice@prod-box --script deploy.txt --stop-on-error
+/// client alice@prod-box -i ~/.config/secure_shell/id_ed25519.pem
+/// client --print-public-key ~/.config/secure_shell/id_ed25519.pem
+/// ```
+#[derive(Parser, Debug)]
+#[command(name = "client", version, about = "TLS secure shell client", long_about = None)]
+pub struct Args {
+    /// Host profile name or server address ([USER@]HOST[:PORT])
+    pub destination: Option<String>,
+    /// Server port [default: 12345]
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    /// CA certificate used to verify the server (PEM)
+    #[arg(long)]
+    pub ca: Option<PathBuf>,
+    /// Name expected in the server certificate (SNI)
+    #[arg(short, long)]
+    pub server_name: Option<String>,
+    /// Client certificate presented to the server (PEM)
+    #[arg(long, requires = "key")]
+    pub cert: Option<PathBuf>,
+    /// Private key of the client certificate (PKCS#8 PEM)
+    #[arg(long, requires = "cert")]
+    pub key: Option<PathBuf>,
+    /// Ed25519 private key (PKCS#8 PEM) used to log in instead of a password
+    #[arg(short, long, value_name = "FILE")]
+    pub identity: Option<PathBuf>,
+    /// Print the public key of an identity file for the server password file and exit
+    #[arg(long, value_name = "FILE", exclusive = true)]
+    pub print_public_key: Option<PathBuf>,
+    /// User to log in as; the password is asked for after connecting
+    #[arg(short, long)]
+    pub user: Option<String>,
+    /// Configuration file [default: ~/.config/secure_shell/client.toml]
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Read the password from the first line of this file
+    #[arg(long)]
+    pub password_file: Option<PathBuf>,
+    /// Run the command lines of this file ('-' for stdin) instead of a session
+    #[arg(long, value_name = "FILE", conflicts_with = "command")]
+    pub script: Option<PathBuf>,
+    /// Stop the script at the first line that fails
+    #[arg(short = 'e', long, requires = "script")]
+    pub stop_on_error: bool,
+    /// Command to run instead of starting an interactive session
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub command: Vec<String>,
+}
+
+/// A named host profile of the configuration file.
+///
+/// All entries are optional; relative `ca`, `cert`, `key` and `identity` paths are
+/// resolved against the directory of the configuration file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HostProfile {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub ca: Option<PathBuf>,
+    pub server_name: Option<String>,
+    pub user: Option<String>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub identity: Option<PathBuf>,
+}
+
+/// Contents of the per-user configuration file.
+///
+/// # Examples
+///
+/// ```toml
+/// [hosts.prod-box]
+/// host = "10.0.0.5"
+/// port = 12345
+/// ca = "certs/prod-ca.crt"
+/// server_name = "prod.example.com"
+/// user = "alice"
+/// cert = "certs/alice.crt"
+/// key = "certs/alice.pk8"
+/// identity = "id_ed25519.pem"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostProfile>,
+}
+
+impl ConfigFile {
+    /// Reads and parses the configuration file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or is not a valid configuration.
+    pub fn read(path: &Path) -> Result<ConfigFile, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file {}: {}", path.display(), e))?;
+        let mut config: ConfigFile = toml::from_str(&text)
+            .map_err(|e| format!("invalid config file {}: {}", path.display(), e))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for profile in config.hosts.values_mut() {
+            for entry in [
+                &mut profile.ca,
+                &mut profile.cert,
+                &mut profile.key,
+                &mut profile.identity,
+            ] {
+                if let Some(p) = entry.as_mut() {
+                    *p = base.join(&p);
+                }
+            }
+        }
+        Ok(config)
+    }
+    /// Returns the per-user configuration file path.
+    ///
+    /// `$XDG_CONFIG_HOME/secure_shell/client.toml`, falling back to
+    /// `~/.config/secure_shell/client.toml` and to `%APPDATA%\secure_shell\client.toml`
+    /// on Windows.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(ConfigFile::directory()?.join("client.toml"))
+    }
+    /// Returns the file holding the command history of the sessions with `host`.
+    ///
+    /// Each host profile or server address gets its own file in the `history`
+    /// directory next to the configuration file.
+    pub fn history_path(host: &str) -> Option<PathBuf> {
+        let name: String = host
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        Some(ConfigFile::directory()?.join("history").join(name))
+    }
+    /// Returns the per-user directory of the client files.
+    fn directory() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(base.join("secure_shell"))
+    }
+}
+
+/// The resolved connection settings.
+///
+/// - `ip_port`: Server address in `IP:PORT` format
+/// - `ca_path`: CA certificate used to verify the server
+/// - `server_name`: Name checked against the server certificate
+/// - `client_cert`: Client certificate and private key presented to the server, if any
+/// - `identity`: Ed25519 private key used for public key logins, if any
+/// - `user`: User to log in as right after connecting, if any
+/// - `password_file`: File holding the password of `user`, if any
+/// - `command`: Command to run non-interactively, if any
+/// - `script`: Script whose lines are run non-interactively (`-` for stdin), if any
+/// - `stop_on_error`: Stop the script at the first failing line
+/// - `history_path`: File holding the command history of this host, if any
+pub struct Settings {
+    pub ip_port: String,
+    pub ca_path: String,
+    pub server_name: String,
+    pub client_cert: Option<(String, String)>,
+    pub identity: Option<PathBuf>,
+    pub user: Option<String>,
+    pub password_file: Option<PathBuf>,
+    pub command: Option<String>,
+    pub script: Option<PathBuf>,
+    pub stop_on_error: bool,
+    pub history_path: Option<PathBuf>,
+}
+
+impl Settings {
+    /// Builds the settings from the parsed arguments and the configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the configuration file is invalid, the CA, client certificate or
+    /// identity files do not exist or a command is given without a user to run it as.
+    pub fn load(args: Args) -> Result<Settings, Box<dyn Error>> {
+        let file = match &args.config {
+            Some(path) => ConfigFile::read(path)?,
+            None => match ConfigFile::default_path() {
+                Some(path) if path.is_file() => ConfigFile::read(&path)?,
+                _ => ConfigFile::default(),
+            },
+        };
+        Settings::resolve(args, file)
+    }
+    fn resolve(args: Args, file: ConfigFile) -> Result<Settings, Box<dyn Error>> {
+        let prompt = Configure::new();
+        let (user, destination) = match args.destination.as_deref() {
+            Some(destination) => match destination.split_once('@') {
+                Some((user, rest)) => (Some(user.to_string()), Some(rest)),
+                None => (None, Some(destination)),
+            },
+            None => (None, None),
+        };
+        let profile_name = destination.filter(|name| file.hosts.contains_key(*name));
+        let (profile, host, port) = match destination {
+            Some(name) if profile_name.is_some() => {
+                let profile = file.hosts[name].clone();
+                let host = profile.host.clone().unwrap_or(name.to_string());
+                (profile, Some(host), None)
+            }
+            Some(address) => {
+                let (host, port) = split_host_port(address)?;
+                (HostProfile::default(), Some(host), port)
+            }
+            None => (HostProfile::default(), None, None),
+        };
+        let port = args.port.or(port).or(profile.port);
+        let user = args.user.or(user).or(profile.user);
+        let command = (!args.command.is_empty()).then(|| args.command.join(" "));
+        if command.is_some() && user.is_none() {
+            return Err("a user is required to run a command (USER@HOST or --user)".into());
+        }
+
+        let ca_path = match args.ca.or(profile.ca) {
+            Some(path) if path.is_file() => path.to_string_lossy().to_string(),
+            Some(path) => return Err(format!("invalid CA path: {}", path.display()).into()),
+            None => prompt.set_cert_path(),
+        };
+        let client_cert = match (args.cert.or(profile.cert), args.key.or(profile.key)) {
+            (Some(cert), Some(key)) => {
+                for path in [&cert, &key] {
+                    if !path.is_file() {
+                        return Err(
+                            format!("invalid client certificate path: {}", path.display()).into(),
+                        );
+                    }
+                }
+                Some((
+                    cert.to_string_lossy().to_string(),
+                    key.to_string_lossy().to_string(),
+                ))
+            }
+            (None, None) => None,
+            _ => return Err("a client certificate needs both cert and key".into()),
+        };
+        let identity = match args.identity.or(profile.identity) {
+            Some(path) if !path.is_file() => {
+                return Err(format!("invalid identity path: {}", path.display()).into());
+            }
+            identity => identity,
+        };
+        let (ip_port, host) = match host {
+            Some(host) => {
+                let port = port.unwrap_or(DEFAULT_PORT);
+                let ip_port = if host.contains(':') {
+                    format!("[{}]:{}", host, port)
+                } else {
+                    format!("{}:{}", host, port)
+                };
+                (ip_port, host)
+            }
+            None => {
+                let ip_port = prompt.set_ip_path();
+                let host = split_host_port(&ip_port)?.0;
+                (ip_port, host)
+            }
+        };
+        let server_name = args
+            .server_name
+            .or(profile.server_name)
+            .unwrap_or_else(|| match host.parse::<IpAddr>() {
+                Ok(_) => DEFAULT_SERVER_NAME.to_string(),
+                Err(_) => host,
+            });
+        let history_path = ConfigFile::history_path(profile_name.unwrap_or(&ip_port));
+        Ok(Settings {
+            ip_port,
+            ca_path,
+            server_name,
+            client_cert,
+            identity,
+            user,
+            password_file: args.password_file,
+            command,
+            script: args.script,
+            stop_on_error: args.stop_on_error,
+            history_path,
+        })
+    }
+    /// Returns `true` if a password was given without asking for it, through
+    /// `password_file` or the `SECURE_SHELL_PASSWORD` environment variable.
+    pub fn has_password(&self) -> bool {
+        self.password_file.is_some() || std::env::var_os(PASSWORD_ENV).is_some()
+    }
+    /// Returns the password to log in as `user` with, or `None` when the identity
+    /// or the client certificate should replace it.
+    ///
+    /// They are only used alone when `has_password()` is `false`, so a password
+    /// given on purpose is always sent.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the password file cannot be read.
+    pub fn login_password(
+        &self,
+        user: &str,
+        has_key: bool,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        if has_key && !self.has_password() {
+            return Ok(None);
+        }
+        self.password(user).map(Some)
+    }
+    /// Returns the password of `user`.
+    ///
+    /// The password is taken from `password_file`, then from the
+    /// `SECURE_SHELL_PASSWORD` environment variable, and is otherwise asked for.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the password file cannot be read.
+    pub fn password(&self, user: &str) -> Result<String, Box<dyn Error>> {
+        if let Some(path) = &self.password_file {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| format!("cannot read password file {}: {}", path.display(), e))?;
+            return Ok(text.lines().next().unwrap_or("").to_string());
+        }
+        if let Ok(password) = std::env::var(PASSWORD_ENV) {
+            return Ok(password);
+        }
+        Ok(Configure::new().set_password(user))
+    }
+}
+
+/// Splits `HOST[:PORT]` (or `[IPV6][:PORT]`) into its host and optional port.
+fn split_host_port(address: &str) -> Result<(String, Option<u16>), Box<dyn Error>> {
+    let address = address.trim();
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, tail) = rest.split_once(']').ok_or("invalid address")?;
+        let port = match tail.strip_prefix(':') {
+            Some(port) => Some(port.parse()?),
+            None => None,
+        };
+        return Ok((host.to_string(), port));
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => Ok((host.to_string(), Some(port.parse()?))),
+        _ => Ok((address.to_string(), None)),
+    }
+}
+
 pub struct Configure {}
 impl Configure {
     pub fn new() -> Self {
@@ -24,4 +406,27 @@ impl Configure {
         std::io::stdin().read_line(&mut input).expect("Read Error");
         input.trim().to_string()
     }
+    /// Asks for the password of `user` without echoing it to the terminal.
+    ///
+    /// When stdin is not a terminal (e.g. piped input) the password is read as a
+    /// plain line instead.
+    pub fn set_password(&self, user: &str) -> String {
+        print!("Password for {}: ", user);
+        std::io::stdout().flush().expect("Write Error");
+        if std::io::stdin().is_terminal() {
+            rpassword::read_password().expect("Read Error")
+        } else {
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input).expect("Read Error");
+            input.trim_end_matches(['\r', '\n']).to_string()
+        }
+    }
+    /// Asks for the one-time code of `user`, for accounts with a second factor.
+    pub fn set_code(&self, user: &str) -> String {
+        print!("One-time code for {}: ", user);
+        std::io::stdout().flush().expect("Write Error");
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input).expect("Read Error");
+        input.trim().to_string()
+    }
 }
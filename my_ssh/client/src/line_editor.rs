@@ -0,0 +1,196 @@
+use protocol::complete::{CompletionKind, CompletionRequest};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+use std::error::Error;
+use std::path::PathBuf;
+use tokio::sync::{mpsc, oneshot};
+
+/// Shell operators after which a new command starts.
+const COMMAND_SEPARATORS: [&str; 4] = ["|", "||", "&&", ";"];
+
+/// Largest number of lines kept in the history file.
+const MAX_HISTORY: usize = 1000;
+
+/// Something the user did in the line editor.
+///
+/// # Variants
+///
+/// - `Line`: A line was entered
+/// - `Complete`: Tab was pressed; the candidates must be sent back on the channel
+pub enum EditorEvent {
+    Line(String),
+    Complete(CompletionRequest, oneshot::Sender<Vec<String>>),
+}
+
+/// A readline-style editor for the interactive session.
+///
+/// `LineEditor` provides arrow-key editing, a persistent history and tab completion.
+/// The editor blocks while it waits for keys, so it runs on its own thread; the
+/// session loop hands it a prompt with `read_line()` and then handles the events it
+/// sends back until the line is entered. Completions need the server, so they are
+/// sent to the session loop as `EditorEvent::Complete` and answered there.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut editor = LineEditor::new(Some(history_path))?;
+/// editor.read_line("Server:>".to_string());
+/// while let Some(event) = editor.next_event().await {
+///     match event {
+///         EditorEvent::Line(line) => break,
+///         EditorEvent::Complete(request, reply) => { /* ask the server */ }
+///     }
+/// }
+/// ```
+pub struct LineEditor {
+    prompts: std::sync::mpsc::Sender<String>,
+    events: mpsc::Receiver<EditorEvent>,
+}
+
+impl LineEditor {
+    /// Creates the editor and starts its thread.
+    ///
+    /// # Parameters
+    ///
+    /// - `history_path`: File the history is loaded from and appended to, if any
+    ///
+    /// # Errors
+    ///
+    /// Fails if the terminal cannot be set up for line editing.
+    pub fn new(history_path: Option<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let (prompts, prompt_receiver) = std::sync::mpsc::channel();
+        let (event_sender, events) = mpsc::channel(1);
+
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .max_history_size(MAX_HISTORY)?
+            .auto_add_history(false)
+            .build();
+        let mut editor: Editor<RemoteCompleter, DefaultHistory> = Editor::with_config(config)?;
+        editor.set_helper(Some(RemoteCompleter {
+            events: event_sender.clone(),
+        }));
+        if let Some(path) = &history_path {
+            let _ = editor.load_history(path);
+        }
+        std::thread::spawn(move || {
+            LineEditor::run(editor, prompt_receiver, event_sender, history_path)
+        });
+        Ok(LineEditor { prompts, events })
+    }
+    /// Asks the editor to read the next line with `prompt`.
+    pub fn read_line(&self, prompt: String) {
+        let _ = self.prompts.send(prompt);
+    }
+    /// Waits for the next event of the editor.
+    ///
+    /// Returns `None` once the user closed the input (Ctrl-D).
+    pub async fn next_event(&mut self) -> Option<EditorEvent> {
+        self.events.recv().await
+    }
+    /// Body of the editor thread: reads one line for every prompt received.
+    ///
+    /// Ctrl-C discards the current line and asks again. Lines are added to the
+    /// history, except `login` lines that contain a password.
+    fn run(
+        mut editor: Editor<RemoteCompleter, DefaultHistory>,
+        prompts: std::sync::mpsc::Receiver<String>,
+        events: mpsc::Sender<EditorEvent>,
+        history_path: Option<PathBuf>,
+    ) {
+        while let Ok(prompt) = prompts.recv() {
+            let line = loop {
+                match editor.readline(&prompt) {
+                    Ok(line) => break line,
+                    Err(ReadlineError::Interrupted) => continue,
+                    Err(_) => return,
+                }
+            };
+            let entry = line.trim();
+            if !entry.is_empty() && !entry.starts_with("login ") {
+                let _ = editor.add_history_entry(entry);
+                if let Some(path) = &history_path {
+                    if let Some(parent) = path.parent() {
+                        let _ = std::fs::create_dir_all(parent);
+                    }
+                    let _ = editor.append_history(path);
+                }
+            }
+            if events.blocking_send(EditorEvent::Line(line)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Completes the word under the cursor by asking the server through the session loop.
+///
+/// The first word of a command is completed as a command name, every other word as a
+/// remote path.
+struct RemoteCompleter {
+    events: mpsc::Sender<EditorEvent>,
+}
+
+impl Completer for RemoteCompleter {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .trim_end_matches(|c: char| !c.is_whitespace())
+            .len();
+        let kind = match line[..start].split_whitespace().last() {
+            Some(token) if !COMMAND_SEPARATORS.contains(&token) => CompletionKind::Path,
+            _ => CompletionKind::Command,
+        };
+        let (reply, candidates) = oneshot::channel();
+        let request = CompletionRequest::new(kind, &line[start..pos]);
+        if self
+            .events
+            .blocking_send(EditorEvent::Complete(request, reply))
+            .is_err()
+        {
+            return Ok((start, Vec::new()));
+        }
+        let pairs = candidates
+            .blocking_recv()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|candidate| {
+                let name = candidate.trim_end_matches('/');
+                let name = name.rsplit('/').next().unwrap_or(name);
+                if candidate.ends_with('/') {
+                    Pair {
+                        display: format!("{}/", name),
+                        replacement: candidate,
+                    }
+                } else {
+                    Pair {
+                        display: name.to_string(),
+                        replacement: format!("{} ", candidate),
+                    }
+                }
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for RemoteCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for RemoteCompleter {}
+
+impl Validator for RemoteCompleter {}
+
+impl Helper for RemoteCompleter {}
@@ -1,21 +1,151 @@
 mod client;
 mod client_configure;
+mod line_editor;
 mod response_handlers;
+use clap::Parser;
 use client::Client;
+use protocol::auth::encode_public_key;
+use ring::signature::KeyPair;
+use std::fs::File;
+use std::io::{BufRead, BufReader, IsTerminal};
+
+/// Exit status used when the client itself fails, as `ssh` does.
+const CLIENT_ERROR_STATUS: i32 = 255;
 
 #[tokio::main]
 async fn main() {
-    let client_cfg = client_configure::Configure::new();
+    let args = client_configure::Args::parse();
+    if let Some(path) = &args.print_public_key {
+        let identity = Client::load_identity(path).unwrap_or_else(|e| panic!("{}", e));
+        println!("{}", encode_public_key(identity.public_key().as_ref()));
+        return;
+    }
+    let settings = client_configure::Settings::load(args).unwrap_or_else(|e| panic!("{}", e));
+
+    if !std::io::stdout().is_terminal() {
+        colored::control::set_override(false);
+    }
 
-    let mut client = Client::new(
-        &client_cfg.set_cert_path(),
-        "localhost",
-        &client_cfg.set_ip_path(),
-    );
+    let mut client = Client::new(&settings.ca_path, &settings.server_name, &settings.ip_port);
+    if let Some((cert, key)) = &settings.client_cert {
+        client
+            .set_client_certificate(cert, key)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+    if let Some(path) = &settings.identity {
+        client
+            .set_identity(path)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+    let has_key = client.has_client_certificate() || client.has_identity();
+    if let Some(path) = &settings.history_path {
+        client.set_history_path(path.clone());
+    }
 
     if let Err(e) = client.connect_to_server().await {
         eprintln!("Error At Connect: {:?}", e);
-        return;
+        std::process::exit(CLIENT_ERROR_STATUS);
+    }
+
+    if let (Some(user), Some(command)) = (&settings.user, &settings.command) {
+        let status = match settings.login_password(user, has_key) {
+            Ok(password) => match client.execute(user, password.as_deref(), command).await {
+                Ok(status) => status,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    CLIENT_ERROR_STATUS
+                }
+            },
+            Err(e) => {
+                eprintln!("Error at login: {}", e);
+                CLIENT_ERROR_STATUS
+            }
+        };
+        std::process::exit(status);
+    }
+
+    if let Some(path) = &settings.script {
+        let from_stdin = path.as_os_str() == "-";
+        if from_stdin
+            && settings.user.is_some()
+            && !has_key
+            && !settings.has_password()
+            && !std::io::stdin().is_terminal()
+        {
+            eprintln!(
+                "Error at login: cannot ask for the password while the script is read from stdin, use --password-file or {}",
+                client_configure::PASSWORD_ENV
+            );
+            std::process::exit(CLIENT_ERROR_STATUS);
+        }
+        let password = match settings
+            .user
+            .as_ref()
+            .map(|user| settings.login_password(user, has_key))
+            .transpose()
+        {
+            Ok(password) => password,
+            Err(e) => {
+                eprintln!("Error at login: {}", e);
+                std::process::exit(CLIENT_ERROR_STATUS);
+            }
+        };
+        let script: Box<dyn BufRead> = if from_stdin {
+            Box::new(std::io::stdin().lock())
+        } else {
+            match File::open(path) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(e) => {
+                    eprintln!("Error: cannot read script {}: {}", path.display(), e);
+                    std::process::exit(CLIENT_ERROR_STATUS);
+                }
+            }
+        };
+        let credentials = settings
+            .user
+            .as_deref()
+            .zip(password.as_ref().map(|password| password.as_deref()));
+        let status = match client
+            .run_script(script, credentials, settings.stop_on_error)
+            .await
+        {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                CLIENT_ERROR_STATUS
+            }
+        };
+        std::process::exit(status);
+    }
+
+    if let Some(user) = &settings.user {
+        let password = match settings.login_password(user, has_key) {
+            Ok(password) => password,
+            Err(e) => {
+                eprintln!("Error at login: {}", e);
+                std::process::exit(CLIENT_ERROR_STATUS);
+            }
+        };
+        let logged_in = match client.login(user, password.as_deref()).await {
+            Ok(logged_in) => logged_in,
+            Err(e) => {
+                eprintln!("Error at login: {:?}", e);
+                return;
+            }
+        };
+        if !logged_in && password.is_none() {
+            let password = match settings.password(user) {
+                Ok(password) => password,
+                Err(e) => {
+                    eprintln!("Error at login: {}", e);
+                    std::process::exit(CLIENT_ERROR_STATUS);
+                }
+            };
+            if let Err(e) = client.login(user, Some(&password)).await {
+                eprintln!("Error at login: {:?}", e);
+                return;
+            }
+        }
     }
 
     if let Err(e) = client.send_and_receive().await {
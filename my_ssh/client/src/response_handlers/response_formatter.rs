@@ -1,4 +1,5 @@
 use colored::*;
+use protocol::format::{Format, split_sections, unescape_text};
 use std::io::Write;
 /// A response formatter that renders structured server responses with appropriate styling.
 /// 
@@ -131,20 +132,26 @@ impl ShowResponse {
                 print!(" ");
             }
         }
+        let items: Vec<(Option<&str>, String)> = word
+            .iter()
+            .map(|e| match e.get(0..2) {
+                Some(color_code) if e.starts_with('^') => {
+                    (Some(color_code), unescape_text(&e[2..]))
+                }
+                _ => (None, unescape_text(e)),
+            })
+            .collect();
         let mut count = 0;
         let mut max_len = 0usize;
-        for e in &word {
-            max_len = std::cmp::max(max_len, e.chars().count());
+        for (_, name) in &items {
+            max_len = std::cmp::max(max_len, name.chars().count());
         }
-        for e in word {
-            if e.starts_with('^') {
-                let color_code = &e[0..2];
-                print!("{}", e[2..].color(get_color(color_code)));
-                print_space(max_len + 1 - e[2..].len());
-            } else {
-                print!("{}", e);
-                print_space(max_len + 1 - e.len());
+        for (color_code, name) in items {
+            match color_code {
+                Some(color_code) => print!("{}", name.color(get_color(color_code))),
+                None => print!("{}", name),
             }
+            print_space(max_len + 1 - name.chars().count());
             count += 1;
             if count % 3 == 0 {
                 count = 0;
@@ -170,6 +177,7 @@ impl ShowResponse {
     /// - **`~~`**: Stop red highlighting (return to normal color)
     /// - Characters between markers are displayed in bright red
     /// - Markers themselves are not displayed
+    /// - The text between markers is unescaped with `unescape_text` before printing
     /// 
     /// # Character-by-Character Processing
     /// 
@@ -182,27 +190,36 @@ impl ShowResponse {
         for w in word {
             if !w.is_empty() {
                 let mut red_status = false;
+                let mut text = String::new();
+                let print_text = |text: &mut String, red_status: bool| {
+                    let plain = unescape_text(text);
+                    if red_status {
+                        print!("{}", plain.bright_red());
+                    } else {
+                        print!("{}", plain);
+                    }
+                    text.clear();
+                };
                 let chars: Vec<_> = w.chars().collect();
                 let mut i = 0;
                 while i < chars.len() {
                     let c = chars[i];
                     if i + 1 < chars.len() && c == '^' && chars[i + 1] == '@' {
+                        print_text(&mut text, red_status);
                         red_status = true;
                         i += 2;
                         continue;
                     }
                     if i + 1 < chars.len() && c == '~' && chars[i + 1] == '~' {
+                        print_text(&mut text, red_status);
                         red_status = false;
                         i += 2;
                         continue;
                     }
-                    if red_status {
-                        print!("{}", c.to_string().bright_red());
-                    } else {
-                        print!("{}", c);
-                    }
+                    text.push(c);
                     i += 1;
                 }
+                print_text(&mut text, red_status);
             }
         }
         println!();
@@ -228,11 +245,10 @@ impl ShowResponse {
     /// 
     /// # Processing Flow
     /// 
-    /// 1. **Split on Markers**: Divides response on `?&` boundaries
-    /// 2. **Filter Empty**: Removes empty sections
-    /// 3. **Extract Type**: Reads first character as format type
-    /// 4. **Extract Content**: Splits content on `\n\n` separators
-    /// 5. **Route Display**: Calls appropriate display method
+    /// 1. **Split on Markers**: `protocol::format::split_sections` divides the response
+    ///    on `?&` boundaries, reads the format type and splits content on `\n\n`
+    /// 2. **Route Display**: Calls appropriate display method, which decodes the text
+    ///    escaped by the server with `protocol::format::unescape_text`
     /// 
     /// # Format Type Handling
     /// 
@@ -243,39 +259,32 @@ impl ShowResponse {
     /// | `C` | `show_grep_style()` | Colored text (inline highlights) |
     /// | `N` | Space-separated | Normal output (plain text) |
     /// | *other* | Ignored | Unknown format types |
-    /// | *none* | Debug message | Empty or malformed sections |
     /// 
     fn split_response(&self) {
-        let props: Vec<&str> = self
-            .response
-            .split("?&")
-            .filter(|f| !f.is_empty())
-            .collect();
-        for w in props {
-            let word: Vec<&str> = w[1..].split("\n\n").filter(|f| !f.is_empty()).collect();
+        for section in split_sections(&self.response) {
+            let word = section.parts;
             //dbg!(&word);
-            match w.chars().next() {
-                Some('E') => {
-                    println!("{}", word[0]);
+            match section.format {
+                Some(Format::Error) => {
+                    if let Some(message) = word.first() {
+                        println!("{}", unescape_text(message));
+                    }
                 }
-                Some('L') => {
+                Some(Format::ListDir) => {
                     self.show_list_style(word);
                 }
-                Some('C') => {
+                Some(Format::NormalColored) => {
                     self.show_grep_style(word);
                 }
-                Some('N') => {
+                Some(Format::Normal) => {
                     for w in word {
                         if !w.is_empty() {
-                            print!("{} ", w);
+                            print!("{} ", unescape_text(w));
                         }
                     }
                     println!();
                 }
-                Some(_) => (),
-                None => {
-                    println!("String gol");
-                }
+                _ => (),
             }
         }
     }
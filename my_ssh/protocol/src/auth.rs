@@ -0,0 +1,167 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+
+/// Marker that starts a public key login request or a challenge.
+pub const KEY_LOGIN_MARKER: &str = "?&K";
+
+/// Prefix of an Ed25519 public key written as text (`ed25519:` + base64 of the raw key).
+pub const PUBLIC_KEY_PREFIX: &str = "ed25519:";
+
+/// Start of the login error sent when the account also needs a one-time code; the
+/// client then asks for the code and sends `login [USERNAME] [PASSWORD] [CODE]`.
+pub const CODE_REQUIRED: &str = "A one-time code is required";
+
+/// Context bytes put in front of every signed login challenge, so a signature made
+/// for a login can never be reused for anything else.
+const SIGNATURE_CONTEXT: &[u8] = b"secure-shell key login\0";
+
+/// A step of the public key (challenge/response) login.
+///
+/// The client starts with `Start`; the server answers with a `Challenge` of random
+/// bytes. The client signs `signed_message(user, challenge)` with its Ed25519
+/// private key and sends the signature in `Proof`, which the server answers like a
+/// `login` command. The password never travels over the connection.
+///
+/// Key logins are only used with servers that negotiated `Capability::PublicKey`.
+///
+/// # Variants
+///
+/// - `Start`: Asks for a challenge to log in as `user`
+/// - `Proof`: The base64 Ed25519 signature of the last challenge
+///
+/// # Wire Format
+///
+/// ```text
+/// ?&K{"step":"start","user":"alice"}
+/// ?&K{"step":"proof","signature":"3q2+7w..."}
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::auth::*;
+/// let request = KeyLogin::Start { user: "alice".to_string() };
+/// assert_eq!(request.encode(), r#"?&K{"step":"start","user":"alice"}"#);
+/// assert_eq!(KeyLogin::decode(&request.encode()), Some(request));
+///
+/// let proof = KeyLogin::proof(&[1, 2, 3]);
+/// assert_eq!(proof, KeyLogin::Proof { signature: "AQID".to_string() });
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "lowercase")]
+pub enum KeyLogin {
+    Start { user: String },
+    Proof { signature: String },
+}
+
+impl KeyLogin {
+    /// Builds the `Proof` step for a raw signature.
+    pub fn proof(signature: &[u8]) -> Self {
+        KeyLogin::Proof {
+            signature: STANDARD.encode(signature),
+        }
+    }
+    /// Encodes the request into the frame payload sent on the wire.
+    pub fn encode(&self) -> String {
+        format!(
+            "{}{}",
+            KEY_LOGIN_MARKER,
+            serde_json::to_string(self).unwrap_or_default()
+        )
+    }
+    /// Decodes a request payload, returning `None` if `payload` is not a valid request.
+    pub fn decode(payload: &str) -> Option<KeyLogin> {
+        let body = payload.strip_prefix(KEY_LOGIN_MARKER)?;
+        serde_json::from_str(body).ok()
+    }
+}
+
+/// The reply of the server to `KeyLogin::Start`.
+///
+/// The challenge is sent as is whatever the response mode, and only the last
+/// challenge of a session can be answered.
+///
+/// # Wire Format
+///
+/// ```text
+/// ?&K{"challenge":"q83v..."}
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::auth::*;
+/// let challenge = Challenge::new(&[0xab, 0xcd]);
+/// assert_eq!(challenge.encode(), r#"?&K{"challenge":"q80="}"#);
+/// assert_eq!(Challenge::decode(&challenge.encode()).unwrap().bytes(), Some(vec![0xab, 0xcd]));
+/// assert_eq!(Challenge::decode("?&EInvalid username[-]:[-]"), None);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Challenge {
+    pub challenge: String,
+}
+
+impl Challenge {
+    pub fn new(bytes: &[u8]) -> Self {
+        Challenge {
+            challenge: STANDARD.encode(bytes),
+        }
+    }
+    /// Returns the raw challenge, or `None` if it is not valid base64.
+    pub fn bytes(&self) -> Option<Vec<u8>> {
+        STANDARD.decode(&self.challenge).ok()
+    }
+    /// Encodes the challenge into the frame payload sent on the wire.
+    pub fn encode(&self) -> String {
+        format!(
+            "{}{}",
+            KEY_LOGIN_MARKER,
+            serde_json::to_string(self).unwrap_or_default()
+        )
+    }
+    /// Decodes a challenge payload, returning `None` if `payload` is not a challenge.
+    pub fn decode(payload: &str) -> Option<Challenge> {
+        let body = payload.strip_prefix(KEY_LOGIN_MARKER)?;
+        serde_json::from_str(body).ok()
+    }
+}
+
+/// Returns the bytes the client signs to log in as `user` with `challenge`.
+///
+/// The user name is part of the message, so a signature for one account cannot be
+/// replayed for another.
+pub fn signed_message(user: &str, challenge: &[u8]) -> Vec<u8> {
+    let mut message = SIGNATURE_CONTEXT.to_vec();
+    message.extend_from_slice(user.as_bytes());
+    message.push(0);
+    message.extend_from_slice(challenge);
+    message
+}
+
+/// Decodes base64 text, such as the signature of a `KeyLogin::Proof`.
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    STANDARD.decode(text.trim()).ok()
+}
+
+/// Writes a raw Ed25519 public key as `ed25519:<base64>`, the form used in the
+/// password file.
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::auth::*;
+/// let text = encode_public_key(&[7; 32]);
+/// assert!(text.starts_with(PUBLIC_KEY_PREFIX));
+/// assert_eq!(decode_public_key(&text), Some(vec![7; 32]));
+/// assert_eq!(decode_public_key("$2b$12$hash"), None);
+/// ```
+pub fn encode_public_key(key: &[u8]) -> String {
+    format!("{}{}", PUBLIC_KEY_PREFIX, STANDARD.encode(key))
+}
+
+/// Reads a public key written by `encode_public_key`, returning `None` for any
+/// other text.
+pub fn decode_public_key(text: &str) -> Option<Vec<u8>> {
+    decode_base64(text.strip_prefix(PUBLIC_KEY_PREFIX)?)
+}
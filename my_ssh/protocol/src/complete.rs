@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+/// Marker that starts a completion request or a completion reply.
+pub const COMPLETE_MARKER: &str = "?&T";
+
+/// What a completion request asks for.
+///
+/// # Variants
+///
+/// - `Command`: Command names known to the server (the first word of a command)
+/// - `Path`: Files and directories, relative to the current directory of the session
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionKind {
+    Command,
+    Path,
+}
+
+/// A request for the completions of a partially typed word.
+///
+/// The client sends it when the user presses tab; the server answers with
+/// `Completions`, whatever the response mode of the session. Completion requests
+/// are only answered for clients that negotiated `Capability::Complete` and are
+/// logged in.
+///
+/// # Fields
+///
+/// - `kind`: Whether the word is a command name or a path
+/// - `prefix`: The partial word typed so far (e.g. `docs/no`)
+///
+/// # Wire Format
+///
+/// ```text
+/// ?&T{"kind":"path","prefix":"docs/no"}
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::complete::*;
+/// let request = CompletionRequest::new(CompletionKind::Path, "docs/no");
+/// assert_eq!(request.encode(), r#"?&T{"kind":"path","prefix":"docs/no"}"#);
+/// assert_eq!(CompletionRequest::decode(&request.encode()), Some(request));
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompletionRequest {
+    pub kind: CompletionKind,
+    pub prefix: String,
+}
+
+impl CompletionRequest {
+    pub fn new(kind: CompletionKind, prefix: impl Into<String>) -> Self {
+        CompletionRequest {
+            kind,
+            prefix: prefix.into(),
+        }
+    }
+    /// Encodes the request into the frame payload sent on the wire.
+    pub fn encode(&self) -> String {
+        format!(
+            "{}{}",
+            COMPLETE_MARKER,
+            serde_json::to_string(self).unwrap_or_default()
+        )
+    }
+    /// Decodes a request payload, returning `None` if `payload` is not a valid request.
+    pub fn decode(payload: &str) -> Option<CompletionRequest> {
+        let body = payload.strip_prefix(COMPLETE_MARKER)?;
+        serde_json::from_str(body).ok()
+    }
+}
+
+/// The reply to a `CompletionRequest`.
+///
+/// # Fields
+///
+/// - `candidates`: Sorted words that can replace the prefix; path candidates keep the
+///   directory part of the prefix and directories end with `/`
+///
+/// # Wire Format
+///
+/// ```text
+/// ?&T{"candidates":["docs/notes.txt","docs/novels/"]}
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::complete::*;
+/// let completions = Completions::new(vec!["docs/notes.txt".to_string()]);
+/// assert_eq!(Completions::decode(&completions.encode()), Some(completions));
+/// assert_eq!(Completions::decode("?&Nnot a reply"), None);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Completions {
+    pub candidates: Vec<String>,
+}
+
+impl Completions {
+    pub fn new(candidates: Vec<String>) -> Self {
+        Completions { candidates }
+    }
+    /// Encodes the reply into the frame payload sent on the wire.
+    pub fn encode(&self) -> String {
+        format!(
+            "{}{}",
+            COMPLETE_MARKER,
+            serde_json::to_string(self).unwrap_or_default()
+        )
+    }
+    /// Decodes a reply payload, returning `None` if `payload` is not a valid reply.
+    pub fn decode(payload: &str) -> Option<Completions> {
+        let body = payload.strip_prefix(COMPLETE_MARKER)?;
+        serde_json::from_str(body).ok()
+    }
+}
@@ -0,0 +1,453 @@
+/// Enumeration of text formatting types used in the shell output system.
+///
+/// `Format` defines various formatting options for shell output, including
+/// error messages, directory listings, colors, and special markers used
+/// by the shell's display system.
+///
+/// # Variants
+///
+/// - `Error`: Error message formatting
+/// - `ListDir`: Directory listing formatting
+/// - `Color(&'static str)`: Specific color formatting with color name
+/// - `Split`: Line separator/split formatting
+/// - `Normal`: Normal text formatting
+/// - `NormalColored`: Normal colored text formatting
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::format::*;
+/// // Error formatting
+/// let error_format = get_format(Format::Error);
+///
+/// // Color formatting
+/// let blue_format = get_format(Format::Color("BLUE"));
+/// let red_format = get_format(Format::Color("LIGHT_RED"));
+///
+/// // Normal formatting
+/// let normal_format = get_format(Format::Normal);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Error,
+    ListDir,
+    Color(&'static str),
+    Split,
+    Normal,
+    NormalColored,
+}
+/// Returns the formatting string for the specified format type.
+///
+/// This function maps `Format` enum variants to their corresponding
+/// formatting control strings used by the shell's display system.
+///
+/// # Parameters
+///
+/// - `format`: The format type to get the control string for
+///
+/// # Returns
+///
+/// A static string slice containing the formatting control sequence.
+///
+/// # Format Mappings
+///
+/// | Format | Control String | Purpose |
+/// |--------|---------------|---------|
+/// | `Error` | `"?&E"` | Error message prefix |
+/// | `ListDir` | `"?&L"` | Directory listing prefix |
+/// | `NormalColored` | `"?&C"` | Colored text prefix |
+/// | `Normal` | `"?&N"` | Normal text prefix |
+/// | `Color("BLUE")` | `"^!"` | Blue color marker |
+/// | `Color("LIGHT_RED")` | `"^@"` | Light red color marker |
+/// | `Color("GREEN")` | `"^#"` | Green color marker |
+/// | `Color("stop")` | `"~~"` | Color stop marker |
+/// | `Split` | `"\n\n"` | Line separator |
+/// | *other* | `""` | Empty string for unknown formats |
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::format::*;
+/// // Format an error message
+/// let error_msg = format!("{}File not found{}",
+///     get_format(Format::Error),
+///     get_format(Format::Split)
+/// );
+///
+/// // Format colored text
+/// let colored_text = format!("{}Important{}{}",
+///     get_format(Format::Color("LIGHT_RED")),
+///     get_format(Format::Color("stop")),
+///     get_format(Format::Split)
+/// );
+///
+/// // Format normal output
+/// let normal_output = format!("{}Command completed{}",
+///     get_format(Format::Normal),
+///     get_format(Format::Split)
+/// );
+/// ```
+impl Format {
+    /// Maps the letter that follows a `?&` section marker back to its format.
+    ///
+    /// Only the four section formats (`E`, `L`, `C`, `N`) have a marker letter;
+    /// any other character yields `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use protocol::format::*;
+    /// assert_eq!(Format::from_marker('L'), Some(Format::ListDir));
+    /// assert_eq!(Format::from_marker('x'), None);
+    /// ```
+    pub fn from_marker(marker: char) -> Option<Format> {
+        match marker {
+            'E' => Some(Format::Error),
+            'L' => Some(Format::ListDir),
+            'C' => Some(Format::NormalColored),
+            'N' => Some(Format::Normal),
+            _ => None,
+        }
+    }
+}
+pub fn get_format(format: Format) -> &'static str {
+    match format {
+        Format::Error => "?&E",
+        Format::ListDir => "?&L",
+        Format::NormalColored => "?&C",
+        Format::Normal => "?&N",
+        Format::Color("BLUE") => "^!",
+        Format::Color("LIGHT_RED") => "^@",
+        Format::Color("GREEN") => "^#",
+        Format::Color("stop") => "~~",
+        Format::Split => "\n\n",
+        _ => "",
+    }
+}
+/// Removes formatting control sequences from formatted text.
+///
+/// This function processes text that contains shell formatting control sequences
+/// and returns a clean, unformatted version suitable for plain text output
+///
+/// # Parameters
+///
+/// - `text`: The formatted text string containing control sequences
+///
+/// # Returns
+///
+/// A clean `String` with all formatting control sequences removed.
+///
+/// # Processing Rules
+///
+/// 1. **Section Parsing**: Splits text on `?&` markers to identify formatted sections
+/// 2. **Format Type Detection**: Identifies the format type from the first character after `?&`
+/// 3. **Color Sequence Removal**: Removes color markers like `^@`, `~~` from colored text
+/// 4. **Prefix Removal**: Strips formatting prefixes from regular sections
+/// 5. **Text Reconstruction**: Rebuilds clean text with proper spacing
+///
+/// # Supported Control Sequences
+///
+/// - `?&E`, `?&L`, `?&N`, `?&C`: Format type prefixes
+/// - `^@`, `^!`, `^#`: Color start markers
+/// - `~~`: Color stop marker
+/// - `\n\n`: Section separators
+///
+/// Escape sequences produced by `escape_text` are decoded once the markers are gone.
+///
+/// # Examples
+///
+/// ## Basic Formatting Removal
+/// ```rust
+/// # use protocol::format::*;
+/// let formatted = "?&NHello World\n\n";
+/// let clean = get_unformated_text(formatted);
+/// assert_eq!(clean, "Hello World");
+/// ```
+///
+/// ## Color Sequence Removal
+/// ```rust
+/// # use protocol::format::*;
+/// let colored = "?&C^@Important~~Text\n\n";
+/// let clean = get_unformated_text(colored);
+/// assert_eq!(clean, "ImportantText");
+/// ```
+///
+/// ## Complex Formatting
+/// ```rust
+/// # use protocol::format::*;
+/// let complex = "?&EError: ?&C^@File not found~~\n\n?&NOperation failed\n\n";
+/// let clean = get_unformated_text(complex);
+/// assert_eq!(clean, "Error: File not found Operation failed");
+/// ```
+///
+/// ## Multiple Sections
+/// ```rust
+/// # use protocol::format::*;
+/// let multi = "?&NStatus: ?&C^@OK~~\n\n?&NFiles: 42\n\n";
+/// let clean = get_unformated_text(multi);
+/// assert_eq!(clean, "Status: OK Files: 42");
+/// ```
+pub fn get_unformated_text(text: &str) -> String {
+    let mut new_text = String::new();
+    let props: Vec<&str> = text.split("?&").filter(|f| !f.is_empty()).collect();
+    for w in props {
+        let word: Vec<&str> = w[1..].split("\n\n").filter(|f| !f.is_empty()).collect();
+        match w.chars().next() {
+            Some('C') => {
+                for e in word {
+                    let chars: Vec<_> = e.chars().collect();
+                    let mut clean = String::new();
+                    let mut i = 0;
+                    while i < chars.len() {
+                        let c = chars[i];
+                        if i + 1 < chars.len() && c == '^' && chars[i + 1] == '@' {
+                            i += 2;
+                            continue;
+                        }
+                        if i + 1 < chars.len() && c == '~' && chars[i + 1] == '~' {
+                            i += 2;
+                            continue;
+                        }
+
+                        clean.push(c);
+
+                        i += 1;
+                    }
+                    new_text = format!("{}{}", new_text, unescape_text(&clean));
+                }
+            }
+            Some(_) => {
+                for e in word {
+                    if e.starts_with('^') {
+                        let tail = unescape_text(&e.chars().skip(2).collect::<String>());
+                        if new_text.is_empty() {
+                            new_text = tail;
+                        } else {
+                            new_text = format!("{} {}", new_text, tail);
+                        }
+                    } else if new_text.is_empty() {
+                        new_text = unescape_text(e);
+                    } else {
+                        new_text = format!("{} {}", new_text, unescape_text(e));
+                    }
+                }
+            }
+            None => (),
+        }
+    }
+    new_text
+}
+
+/// One `?&X` section of a formatted response.
+///
+/// - `format`: The section format, `None` when the marker letter is unknown
+/// - `parts`: The non-empty chunks of the section body, split on `\n\n`
+#[derive(Debug, PartialEq)]
+pub struct Section<'a> {
+    pub format: Option<Format>,
+    pub parts: Vec<&'a str>,
+}
+
+/// Splits formatted text into its `?&X` sections.
+///
+/// This is the decoding counterpart of building output with `get_format`: the text
+/// is cut on every `?&` marker, the letter after the marker selects the section
+/// format, and the rest of the section is split on the `\n\n` separator.
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::format::*;
+/// let sections = split_sections("?&L\n\n^!docs\n\nnotes.txt\n\n?&Edone\n\n");
+/// assert_eq!(sections.len(), 2);
+/// assert_eq!(sections[0].format, Some(Format::ListDir));
+/// assert_eq!(sections[0].parts, vec!["^!docs", "notes.txt"]);
+/// assert_eq!(sections[1].format, Some(Format::Error));
+/// assert_eq!(sections[1].parts, vec!["done"]);
+/// ```
+pub fn split_sections(text: &str) -> Vec<Section<'_>> {
+    text.split("?&")
+        .filter(|f| !f.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            let format = chars.next().and_then(Format::from_marker);
+            let parts = chars
+                .as_str()
+                .split("\n\n")
+                .filter(|f| !f.is_empty())
+                .collect();
+            Section { format, parts }
+        })
+        .collect()
+}
+
+/// Escape sequences used by `escape_text`, as `(escaped letter, original character)`.
+const ESCAPES: [(char, char); 6] = [
+    ('\\', '\\'),
+    ('q', '?'),
+    ('c', '^'),
+    ('w', '~'),
+    ('b', '['),
+    ('l', '\n'),
+];
+
+/// Escapes text so it can be embedded between format markers.
+///
+/// Command output such as file contents, program output or file names is
+/// arbitrary text and may contain sequences that the response parser treats as
+/// markers. Every character that would start a marker is replaced by a
+/// backslash escape, so the escaped text never contains `?&`, `^!`, `^@`, `^#`,
+/// `~~`, the `[-]` field separator or the `\n\n` split separator.
+///
+/// # Escape Sequences
+///
+/// | Escape | Character | Escaped when |
+/// |--------|-----------|--------------|
+/// | `\\` | `\` | Always |
+/// | `\q` | `?` | Followed by `&` |
+/// | `\c` | `^` | Followed by `!`, `@` or `#` |
+/// | `\w` | `~` | Followed by `~` or at the end of the text |
+/// | `\b` | `[` | Followed by `-]` |
+/// | `\l` | `\n` | Preceded by `\n` or at the end of the text |
+///
+/// A trailing `~` is escaped because the text may be followed by a `~~` marker, and
+/// a trailing `\n` because it would merge with the `\n\n` split separator.
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::format::*;
+/// let hostile = "?&Eboom\n\n^@red~~[-]:/etc[-]";
+/// let escaped = escape_text(hostile);
+/// assert_eq!(escaped, "\\q&Eboom\n\\l\\c@red\\w~\\b-]:/etc\\b-]");
+/// assert_eq!(split_sections(&format!("?&N{}\n\n", escaped)).len(), 1);
+/// assert_eq!(unescape_text(&escaped), hostile);
+/// ```
+pub fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut previous = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+        let escape = match c {
+            '\\' => true,
+            '?' => next == Some('&'),
+            '^' => matches!(next, Some('!' | '@' | '#')),
+            '~' => matches!(next, Some('~') | None),
+            '[' => chars.clone().take(2).eq("-]".chars()),
+            '\n' => previous == Some('\n') || next.is_none(),
+            _ => false,
+        };
+        let letter = ESCAPES.iter().find(|(_, original)| *original == c);
+        match letter {
+            Some((letter, _)) if escape => {
+                escaped.push('\\');
+                escaped.push(*letter);
+            }
+            _ => escaped.push(c),
+        }
+        previous = Some(c);
+    }
+    escaped
+}
+
+/// Reverses `escape_text`.
+///
+/// Unknown escape sequences and a trailing backslash are kept as they are.
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::format::*;
+/// assert_eq!(unescape_text("C:\\\\dir \\q& \\x"), "C:\\dir ?& \\x");
+/// ```
+pub fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escape = match c {
+            '\\' => chars
+                .peek()
+                .and_then(|next| ESCAPES.iter().find(|(letter, _)| letter == next)),
+            _ => None,
+        };
+        match escape {
+            Some((_, original)) => {
+                unescaped.push(*original);
+                chars.next();
+            }
+            None => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Texts made of the sequences the response parser gives a meaning to.
+    const TEXTS: [&str; 12] = [
+        "",
+        "hello\n",
+        "hello\n\n",
+        "\n",
+        "\n\n\n",
+        "?&Eforged",
+        "^!dir ^@red^#x",
+        "~~ end~",
+        "[-]:/etc[-]",
+        "back\\slash\\q \\l \\",
+        "a\n\nb\n\n\nc\n",
+        "?&?&&^^!!~~~[[-]]\n",
+    ];
+
+    #[test]
+    fn escaped_texts_round_trip() {
+        for text in TEXTS {
+            assert_eq!(unescape_text(&escape_text(text)), text, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn escaped_texts_contain_no_marker() {
+        for text in TEXTS {
+            let escaped = escape_text(text);
+            for marker in ["?&", "^!", "^@", "^#", "~~", "[-]", "\n\n"] {
+                assert!(!escaped.contains(marker), "{:?} -> {:?}", text, escaped);
+            }
+            assert!(
+                !escaped.ends_with('~') && !escaped.ends_with('\n'),
+                "{:?}",
+                escaped
+            );
+        }
+    }
+
+    #[test]
+    fn escaped_texts_stay_one_section_part() {
+        for text in TEXTS.iter().filter(|text| !text.is_empty()) {
+            let output = format!(
+                "{}{}{}",
+                get_format(Format::Normal),
+                escape_text(text),
+                get_format(Format::Split)
+            );
+            let sections = split_sections(&output);
+            assert_eq!(sections.len(), 1, "{:?}", output);
+            assert_eq!(sections[0].format, Some(Format::Normal));
+            let parts: Vec<String> = sections[0]
+                .parts
+                .iter()
+                .map(|part| unescape_text(part))
+                .collect();
+            assert_eq!(parts, vec![text.to_string()], "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn unknown_escapes_are_kept() {
+        assert_eq!(unescape_text("\\x \\"), "\\x \\");
+        assert_eq!(unescape_text("\\l\\w\\b\\c\\q\\\\"), "\n~[^?\\");
+    }
+}
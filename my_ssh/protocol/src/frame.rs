@@ -0,0 +1,177 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest payload accepted in a single frame (16 MiB).
+///
+/// Frames announcing a bigger payload are rejected before any allocation is made,
+/// so a misbehaving peer cannot make either side reserve arbitrary amounts of memory.
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Reads one complete length-prefixed frame from the stream.
+///
+/// Every message exchanged between client and server is sent as a frame made of a
+/// 4-byte big-endian payload length followed by the payload itself. This function
+/// keeps reading until the whole payload has arrived, no matter how the bytes were
+/// split by TCP or TLS records.
+///
+/// # Returns
+///
+/// - `Ok(Some(Vec<u8>))`: The payload of the next frame
+/// - `Ok(None)`: The peer closed the connection cleanly before a new frame started
+/// - `Err(std::io::Error)`: The stream failed, ended in the middle of a frame, or the
+///   announced length is larger than `MAX_FRAME_LEN`
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::frame::read_frame;
+/// # async fn run<S: tokio::io::AsyncRead + Unpin>(tls_stream: &mut S) -> std::io::Result<()> {
+/// while let Some(payload) = read_frame(tls_stream).await? {
+///     let received = String::from_utf8_lossy(&payload);
+///     println!("received: {}", received);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn read_frame<R>(reader: &mut R) -> std::io::Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    read_frame_limited(reader, MAX_FRAME_LEN).await
+}
+
+/// Reads one frame like `read_frame`, rejecting payloads larger than `max_len`.
+///
+/// The server uses it to apply its configured request size limit; `max_len` is
+/// capped at `MAX_FRAME_LEN`.
+pub async fn read_frame_limited<R>(
+    reader: &mut R,
+    max_len: usize,
+) -> std::io::Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    let max_len = std::cmp::min(max_len, MAX_FRAME_LEN);
+    let mut len_buf = [0u8; 4];
+    let mut filled = 0;
+    while filled < len_buf.len() {
+        match reader.read(&mut len_buf[filled..]).await? {
+            0 if filled == 0 => return Ok(None),
+            0 => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "stream ended in the middle of a frame length",
+                ));
+            }
+            read => filled += read,
+        }
+    }
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > max_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the {} bytes limit", len, max_len),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+/// Writes `payload` to the stream as a single length-prefixed frame.
+///
+/// # Errors
+///
+/// Fails if the payload is larger than `MAX_FRAME_LEN` or if the stream cannot be
+/// written to.
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::frame::write_frame;
+/// # async fn run<S: tokio::io::AsyncWrite + Unpin>(tls_stream: &mut S) -> std::io::Result<()> {
+/// write_frame(tls_stream, "?&NSuccesful login[-]:[-]".as_bytes()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn write_frame<W>(writer: &mut W, payload: &[u8]) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "frame of {} bytes exceeds the {} bytes limit",
+                payload.len(),
+                MAX_FRAME_LEN
+            ),
+        ));
+    }
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Payloads of various sizes, including an empty one.
+    const PAYLOADS: [&[u8]; 4] = [b"?&NSuccesful login[-]:[-]", b"", b"x", &[0xAB; 1000]];
+
+    #[tokio::test]
+    async fn frames_survive_short_reads() {
+        let (mut client, mut server) = tokio::io::duplex(3);
+        let write = async move {
+            for payload in PAYLOADS {
+                write_frame(&mut client, payload).await.unwrap();
+            }
+        };
+        let read = async {
+            let mut frames = Vec::new();
+            while let Some(frame) = read_frame(&mut server).await.unwrap() {
+                frames.push(frame);
+            }
+            frames
+        };
+        let ((), frames) = tokio::join!(write, read);
+        assert_eq!(frames, PAYLOADS);
+    }
+
+    #[tokio::test]
+    async fn oversized_frames_are_rejected() {
+        let header = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes();
+        let error = read_frame(&mut &header[..]).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let error = read_frame_limited(&mut &header[..], usize::MAX)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let mut frame = Vec::new();
+        write_frame(&mut frame, b"0123456789").await.unwrap();
+        let error = read_frame_limited(&mut &frame[..], 9).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let payload = read_frame_limited(&mut &frame[..], 10).await.unwrap();
+        assert_eq!(payload.as_deref(), Some(&b"0123456789"[..]));
+        let error = write_frame(&mut Vec::new(), &vec![0; MAX_FRAME_LEN + 1])
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn only_an_end_between_frames_is_clean() {
+        assert!(read_frame(&mut &b""[..]).await.unwrap().is_none());
+        let mut frame = Vec::new();
+        write_frame(&mut frame, b"hello").await.unwrap();
+        for cut in 1..frame.len() {
+            let error = read_frame(&mut &frame[..cut]).await.unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof, "{}", cut);
+        }
+        let mut stream = &frame[..];
+        assert!(read_frame(&mut stream).await.unwrap().is_some());
+        assert!(read_frame(&mut stream).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of the protocol implemented by this crate.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Marker that starts a hello message.
+pub const HELLO_MARKER: &str = "?&H";
+
+/// An optional protocol feature that a peer can announce in its `Hello`.
+///
+/// # Variants
+///
+/// | Capability | Name | Feature |
+/// |------------|------|---------|
+/// | `Framing` | `framing` | Length-prefixed frames (always supported) |
+/// | `Json` | `json` | The json response mode (`?&Mjson`) |
+/// | `Status` | `status` | Text responses carry the exit status of the command |
+/// | `Complete` | `complete` | Tab completion requests (`?&T{...}`) |
+/// | `PublicKey` | `publickey` | Public key logins (`?&K{...}`) |
+/// | `Keepalive` | `keepalive` | Keepalive pings (`?&P`) at the interval of the server `Hello` |
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    Framing,
+    Json,
+    Status,
+    Complete,
+    PublicKey,
+    Keepalive,
+}
+
+impl Capability {
+    /// Every capability known to this version of the protocol.
+    pub const ALL: [Capability; 6] = [
+        Capability::Framing,
+        Capability::Json,
+        Capability::Status,
+        Capability::Complete,
+        Capability::PublicKey,
+        Capability::Keepalive,
+    ];
+    /// Capabilities assumed for clients that never send a `Hello`.
+    pub const LEGACY: &'static [Capability] = &[Capability::Framing, Capability::Json];
+
+    /// Returns the name used for the capability on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Framing => "framing",
+            Capability::Json => "json",
+            Capability::Status => "status",
+            Capability::Complete => "complete",
+            Capability::PublicKey => "publickey",
+            Capability::Keepalive => "keepalive",
+        }
+    }
+    /// Parses a capability name, returning `None` for capabilities this version does not know.
+    pub fn from_name(name: &str) -> Option<Capability> {
+        Capability::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+/// The message each peer sends to announce what it supports.
+///
+/// After the TLS handshake the server sends its usual welcome message. A client that
+/// knows about the handshake then sends its `Hello` as the first request and the
+/// server answers with its own `Hello`; both sides use the features present in both
+/// messages. Clients that never send a `Hello` keep the default feature set.
+///
+/// # Fields
+///
+/// - `version`: Protocol version spoken by the peer
+/// - `capabilities`: Names of the capabilities supported by the peer, unknown names
+///   are kept so newer peers can announce features older ones ignore
+/// - `agent`: Name and version of the peer software (e.g. `client/0.1.0`)
+/// - `keepalive`: Seconds between the keepalive pings the server expects, only
+///   sent by servers that drop clients that stop pinging
+///
+/// # Wire Format
+///
+/// ```text
+/// ?&H{"version":1,"capabilities":["framing","json","status","complete","publickey","keepalive"],"agent":"client/0.1.0"}
+/// ?&H{"version":1,"capabilities":["framing","json","status","complete","publickey","keepalive"],"agent":"server/0.1.0","keepalive":30}
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::handshake::*;
+/// let client = Hello::new("client/0.1.0", &Capability::ALL);
+/// let server = Hello::decode(&Hello::new("server/0.1.0", &[Capability::Framing]).encode()).unwrap();
+/// assert_eq!(client.negotiate(&server), vec![Capability::Framing]);
+/// assert_eq!(client.negotiated_version(&server), PROTOCOL_VERSION);
+///
+/// let server = Hello::new("server/0.1.0", &Capability::ALL).with_keepalive(30);
+/// assert_eq!(Hello::decode(&server.encode()).unwrap().keepalive, Some(30));
+/// assert_eq!(Hello::decode(&client.encode()).unwrap().keepalive, None);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u32,
+    pub capabilities: Vec<String>,
+    pub agent: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive: Option<u64>,
+}
+
+impl Hello {
+    /// Creates the `Hello` of a peer speaking `PROTOCOL_VERSION`.
+    pub fn new(agent: &str, capabilities: &[Capability]) -> Self {
+        Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: capabilities.iter().map(|c| c.name().to_string()).collect(),
+            agent: agent.to_string(),
+            keepalive: None,
+        }
+    }
+    /// Returns the hello announcing a keepalive ping every `secs` seconds.
+    pub fn with_keepalive(mut self, secs: u64) -> Self {
+        self.keepalive = Some(secs);
+        self
+    }
+    /// Returns `true` if the peer announced `capability`.
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.iter().any(|c| c == capability.name())
+    }
+    /// Returns the capabilities announced by both peers.
+    pub fn negotiate(&self, other: &Hello) -> Vec<Capability> {
+        Capability::ALL
+            .into_iter()
+            .filter(|c| self.supports(*c) && other.supports(*c))
+            .collect()
+    }
+    /// Returns the protocol version both peers can speak.
+    pub fn negotiated_version(&self, other: &Hello) -> u32 {
+        std::cmp::min(self.version, other.version)
+    }
+    /// Encodes the hello into the frame payload sent on the wire.
+    pub fn encode(&self) -> String {
+        format!(
+            "{}{}",
+            HELLO_MARKER,
+            serde_json::to_string(self).unwrap_or_default()
+        )
+    }
+    /// Decodes a hello payload, returning `None` if `payload` is not a valid hello.
+    pub fn decode(payload: &str) -> Option<Hello> {
+        let body = payload.strip_prefix(HELLO_MARKER)?;
+        serde_json::from_str(body).ok()
+    }
+}
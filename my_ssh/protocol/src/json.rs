@@ -0,0 +1,149 @@
+use crate::format::{Format, split_sections, unescape_text};
+use serde::{Deserialize, Serialize};
+
+/// Kind of a directory entry reported by `ls`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// A directory entry listed by `ls`.
+///
+/// # Fields
+///
+/// - `name`: Entry name relative to the listed directory
+/// - `kind`: Whether the entry is a file or a directory
+/// - `executable`: `true` for files with an executable permission bit
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    pub executable: bool,
+}
+
+/// The JSON document sent for every command in the json response mode.
+///
+/// # Fields
+///
+/// - `command`: The command line the result belongs to
+/// - `exit_status`: `0` when the command chain succeeded, `1` otherwise
+/// - `stdout`: Output text without any format markers
+/// - `stderr`: Error messages without any format markers
+/// - `cwd`: Current directory of the session, relative to the server root
+/// - `entries`: Directory entries listed by `ls`, omitted when empty
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::json::*;
+/// let result = CommandResult::from_output("ls", " ?&L\n\n^!docs\n\n^#run.sh\n\n", true, "");
+/// assert_eq!(result.exit_status, 0);
+/// assert_eq!(result.stdout, "docs\nrun.sh");
+/// assert_eq!(result.entries[0].kind, EntryKind::Directory);
+/// assert!(result.entries[1].executable);
+///
+/// let json = result.to_json();
+/// assert_eq!(CommandResult::from_json(&json).unwrap(), result);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandResult {
+    pub command: String,
+    pub exit_status: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub cwd: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<DirEntry>,
+}
+
+/// Removes the color markers (`^!`, `^@`, `^#` and `~~`) from a section part and
+/// decodes its escape sequences.
+fn strip_colors(text: &str) -> String {
+    let mut clean = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('^', Some('!' | '@' | '#')) | ('~', Some('~')) => {
+                chars.next();
+            }
+            _ => clean.push(c),
+        }
+    }
+    unescape_text(&clean)
+}
+
+/// Appends `line` to `text`, separating it from previous content with a newline.
+fn push_line(text: &mut String, line: &str) {
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text.push_str(line);
+}
+
+impl CommandResult {
+    /// Builds the structured result of a command from its formatted output.
+    ///
+    /// Error sections (`?&E`) become `stderr`, every other section becomes `stdout`,
+    /// and the items of directory listings (`?&L`) are also reported as typed
+    /// `entries`, using the color markers to tell directories (`^!`) and executable
+    /// files (`^#`) apart.
+    ///
+    /// # Parameters
+    ///
+    /// - `command`: The command line the output belongs to
+    /// - `output`: Formatted output as produced by the command system
+    /// - `success`: Success status of the command chain
+    /// - `cwd`: Current directory of the session, relative to the server root
+    pub fn from_output(command: &str, output: &str, success: bool, cwd: &str) -> Self {
+        let mut result = CommandResult {
+            command: command.trim().to_string(),
+            exit_status: if success { 0 } else { 1 },
+            stdout: String::new(),
+            stderr: String::new(),
+            cwd: cwd.to_string(),
+            entries: Vec::new(),
+        };
+        for section in split_sections(output) {
+            match section.format {
+                Some(Format::Error) => {
+                    for part in section.parts {
+                        push_line(&mut result.stderr, strip_colors(part).trim());
+                    }
+                }
+                Some(Format::ListDir) => {
+                    for part in section.parts {
+                        let (kind, executable, name) = match part.get(0..2) {
+                            Some("^!") => (EntryKind::Directory, false, &part[2..]),
+                            Some("^#") => (EntryKind::File, true, &part[2..]),
+                            _ => (EntryKind::File, false, part),
+                        };
+                        let name = unescape_text(name);
+                        push_line(&mut result.stdout, &name);
+                        result.entries.push(DirEntry {
+                            name,
+                            kind,
+                            executable,
+                        });
+                    }
+                }
+                Some(_) => {
+                    for part in section.parts {
+                        push_line(&mut result.stdout, strip_colors(part).trim());
+                    }
+                }
+                None => (),
+            }
+        }
+        result
+    }
+    /// Serializes the result into the JSON document sent on the wire.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+    /// Parses a JSON document received from the server.
+    pub fn from_json(text: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(text)
+    }
+}
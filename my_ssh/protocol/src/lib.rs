@@ -0,0 +1,18 @@
+//! Wire protocol shared by the secure shell server and client.
+//!
+//! The crate is the single definition of everything both sides must agree on:
+//!
+//! - [`frame`]: length-prefixed framing used for every message on the TLS stream
+//! - [`format`]: the `?&X` section markers, color markers, text escaping and their parsing
+//! - [`message`]: typed requests sent by the client and responses sent by the server
+//! - [`json`]: structured command results sent in the json response mode
+//! - [`handshake`]: protocol version and capability negotiation
+//! - [`complete`]: tab completion requests and replies
+//! - [`auth`]: public key (challenge/response) login and one-time codes
+pub mod auth;
+pub mod complete;
+pub mod format;
+pub mod frame;
+pub mod handshake;
+pub mod json;
+pub mod message;
@@ -0,0 +1,235 @@
+use crate::auth::{KEY_LOGIN_MARKER, KeyLogin};
+use crate::complete::{COMPLETE_MARKER, CompletionRequest};
+use crate::format::{escape_text, unescape_text};
+use crate::handshake::{HELLO_MARKER, Hello};
+
+/// Separator placed between the fields of a response payload.
+pub const FIELD_SEPARATOR: &str = "[-]";
+
+/// Marker that starts a response mode selection request.
+pub const SET_MODE_MARKER: &str = "?&M";
+
+/// Payload of a keepalive ping, which the server sends back unchanged.
+pub const PING_MARKER: &str = "?&P";
+
+/// Prefix shared by every control request.
+const CONTROL_PREFIX: &str = "?&";
+
+/// How the server encodes the result of each command.
+///
+/// # Variants
+///
+/// - `Text`: The marker based `Response` payload (default, used by the GUI)
+/// - `Json`: One `json::CommandResult` document per command
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ResponseMode {
+    #[default]
+    Text,
+    Json,
+}
+
+impl ResponseMode {
+    /// Returns the name used for the mode on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResponseMode::Text => "text",
+            ResponseMode::Json => "json",
+        }
+    }
+    /// Parses a mode name, returning `None` for unknown modes.
+    pub fn from_name(name: &str) -> Option<ResponseMode> {
+        match name.trim() {
+            "text" => Some(ResponseMode::Text),
+            "json" => Some(ResponseMode::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A message sent by the client to the server.
+///
+/// Each request travels as the payload of one frame. Control requests start with a
+/// `?&` marker. A command line that starts with `?&` itself, after any backslashes,
+/// is sent with one more leading backslash, which `decode` removes, so a typed
+/// command is never taken for a control request.
+///
+/// # Variants
+///
+/// - `Command`: A raw command line, including `login [USERNAME] [PASSWORD]`
+/// - `SetMode`: Selects the response mode for the rest of the session (`?&Mjson`)
+/// - `Hello`: Announces the client protocol version and capabilities (`?&H{...}`)
+/// - `Complete`: Asks for the completions of a partial word (`?&T{...}`)
+/// - `KeyLogin`: A step of the public key login (`?&K{...}`)
+/// - `Ping`: A keepalive probe (`?&P`), answered with the same payload
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::message::{Request, ResponseMode};
+/// let request = Request::Command("ls -la".to_string());
+/// assert_eq!(request.encode(), "ls -la");
+/// assert_eq!(Request::decode("ls -la"), Ok(request));
+///
+/// let request = Request::Command("?&Mjson".to_string());
+/// assert_eq!(request.encode(), "\\?&Mjson");
+/// assert_eq!(Request::decode("\\?&Mjson"), Ok(request));
+/// let request = Request::Command("\\?&P".to_string());
+/// assert_eq!(Request::decode(&request.encode()), Ok(request));
+///
+/// let request = Request::SetMode(ResponseMode::Json);
+/// assert_eq!(request.encode(), "?&Mjson");
+/// assert_eq!(Request::decode("?&Mjson"), Ok(request));
+/// assert!(Request::decode("?&Mxml").is_err());
+///
+/// assert_eq!(Request::Ping.encode(), "?&P");
+/// assert_eq!(Request::decode("?&P"), Ok(Request::Ping));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Command(String),
+    SetMode(ResponseMode),
+    Hello(Hello),
+    Complete(CompletionRequest),
+    KeyLogin(KeyLogin),
+    Ping,
+}
+
+impl Request {
+    /// Encodes the request into the frame payload sent on the wire.
+    pub fn encode(&self) -> String {
+        match self {
+            Request::Command(line) if Request::needs_escape(line) => format!("\\{}", line),
+            Request::Command(line) => line.clone(),
+            Request::SetMode(mode) => format!("{}{}", SET_MODE_MARKER, mode.name()),
+            Request::Hello(hello) => hello.encode(),
+            Request::Complete(request) => request.encode(),
+            Request::KeyLogin(request) => request.encode(),
+            Request::Ping => PING_MARKER.to_string(),
+        }
+    }
+    /// Returns true for a command line that would read as a control request, or as
+    /// an escaped one, without a leading backslash.
+    fn needs_escape(line: &str) -> bool {
+        line.trim_start_matches('\\').starts_with(CONTROL_PREFIX)
+    }
+    /// Decodes a frame payload received from the client.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem when a control request is malformed.
+    pub fn decode(payload: &str) -> Result<Request, String> {
+        if let Some(mode) = payload.strip_prefix(SET_MODE_MARKER) {
+            return ResponseMode::from_name(mode)
+                .map(Request::SetMode)
+                .ok_or(format!("Unknown response mode: {}", mode.trim()));
+        }
+        if payload.starts_with(HELLO_MARKER) {
+            return Hello::decode(payload)
+                .map(Request::Hello)
+                .ok_or("Invalid hello message".to_string());
+        }
+        if payload.starts_with(COMPLETE_MARKER) {
+            return CompletionRequest::decode(payload)
+                .map(Request::Complete)
+                .ok_or("Invalid completion request".to_string());
+        }
+        if payload == PING_MARKER {
+            return Ok(Request::Ping);
+        }
+        if payload.starts_with(KEY_LOGIN_MARKER) {
+            return KeyLogin::decode(payload)
+                .map(Request::KeyLogin)
+                .ok_or("Invalid key login request".to_string());
+        }
+        match payload.strip_prefix('\\') {
+            Some(line) if Request::needs_escape(line) => Ok(Request::Command(line.to_string())),
+            _ => Ok(Request::Command(payload.to_string())),
+        }
+    }
+}
+
+/// A message sent by the server to the client.
+///
+/// # Fields
+///
+/// - `output`: Command output built from `format` section markers, with any
+///   embedded text escaped by `format::escape_text`
+/// - `path`: Current directory of the session, relative to the server root; it is
+///   escaped on the wire so directory names cannot contain the `[-]` separator
+/// - `status`: Exit status of the command chain (`0` on success, `1` otherwise),
+///   only sent to clients that negotiated `Capability::Status`
+///
+/// # Wire Format
+///
+/// ```text
+/// output[-]:path[-]
+/// output[-]:path[-]status[-]
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// # use protocol::message::Response;
+/// let response = Response::new("?&NSuccesful login", "");
+/// assert_eq!(response.encode(), "?&NSuccesful login[-]:[-]");
+///
+/// let decoded = Response::decode("?&Nhome:/docs\n\n[-]:docs[-]");
+/// assert_eq!(decoded.output, "?&Nhome:/docs\n\n");
+/// assert_eq!(decoded.path, "docs");
+///
+/// let response = Response::new("?&N\n\n", "a[-]b");
+/// assert_eq!(response.encode(), "?&N\n\n[-]:a\\b-]b[-]");
+/// assert_eq!(Response::decode(&response.encode()), response);
+///
+/// let response = Response::new("?&Ebad\n\n", "docs").with_status(false);
+/// assert_eq!(response.encode(), "?&Ebad\n\n[-]:docs[-]1[-]");
+/// assert_eq!(Response::decode(&response.encode()).status, Some(1));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub output: String,
+    pub path: String,
+    pub status: Option<i32>,
+}
+
+impl Response {
+    pub fn new(output: impl Into<String>, path: impl Into<String>) -> Self {
+        Response {
+            output: output.into(),
+            path: path.into(),
+            status: None,
+        }
+    }
+    /// Returns the response with the exit status matching the success flag `succes`.
+    pub fn with_status(mut self, succes: bool) -> Self {
+        self.status = Some(if succes { 0 } else { 1 });
+        self
+    }
+    /// Encodes the response into the frame payload sent on the wire.
+    pub fn encode(&self) -> String {
+        let mut payload = format!(
+            "{}{}:{}{}",
+            self.output,
+            FIELD_SEPARATOR,
+            escape_text(&self.path),
+            FIELD_SEPARATOR
+        );
+        if let Some(status) = self.status {
+            payload += &format!("{}{}", status, FIELD_SEPARATOR);
+        }
+        payload
+    }
+    /// Decodes a frame payload received from the server.
+    ///
+    /// Payloads without a separator are treated as output with an empty path, and
+    /// payloads without a valid status field have no status.
+    pub fn decode(payload: &str) -> Response {
+        let mut fields = payload.split(FIELD_SEPARATOR);
+        let output = fields.next().unwrap_or("");
+        let path = fields.next().unwrap_or("");
+        let path = path.strip_prefix(':').unwrap_or(path);
+        let mut response = Response::new(output, unescape_text(path));
+        response.status = fields.next().and_then(|status| status.parse().ok());
+        response
+    }
+}
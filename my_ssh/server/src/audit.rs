@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Server-side record of the authentication events.
+///
+/// Clients only get a generic reply when a login fails; the detailed reason (unknown
+/// user, wrong password, wrong code, ...) is written here instead, one line per event
+/// with the UTC time, the client address and the user name as sent by the client:
+///
+/// ```text
+/// 2026-10-17T07:12:03Z 127.0.0.1:52814 user "alice" login failed: Incorrect password
+/// 2026-10-17T07:12:09Z 127.0.0.1:52816 user "alice" login accepted
+/// ```
+///
+/// Without a file, the lines go to the standard output of the server, prefixed
+/// with `audit:`.
+///
+/// # Examples
+///
+/// ```rust
+/// let audit = AuditLog::open(Path::new("/var/log/secure_shell/audit.log"))?;
+/// audit.record(addr, "alice", "login failed: Incorrect password");
+/// ```
+#[derive(Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    /// Opens the audit log at `path` for appending, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be opened.
+    pub fn open(path: &Path) -> Result<AuditLog, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("cannot open audit log {}: {}", path.display(), e))?;
+        Ok(AuditLog {
+            file: Some(Mutex::new(file)),
+        })
+    }
+    /// Records `event` of `user` for the client at `peer`.
+    ///
+    /// The user name is quoted and escaped, so a crafted name cannot forge lines.
+    /// A failed write is reported on the standard error and the event printed there.
+    pub fn record(&self, peer: SocketAddr, user: &str, event: &str) {
+        let line = format!(
+            "{} {} user {:?} {}",
+            format_time(SystemTime::now()),
+            peer,
+            user,
+            event
+        );
+        match &self.file {
+            Some(file) => {
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                if let Err(e) = writeln!(file, "{}", line) {
+                    eprintln!("cannot write audit log: {}: {}", e, line);
+                }
+            }
+            None => println!("audit: {}", line),
+        }
+    }
+}
+
+/// Formats `time` in UTC as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    // Civil date of a day number, from the algorithm of Howard Hinnant.
+    let days = secs / 86400 + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
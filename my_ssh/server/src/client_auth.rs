@@ -0,0 +1,127 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::{fs::File, io::BufReader};
+use tokio_rustls::rustls::{Certificate, RootCertStore};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// Client certificate authentication (mutual TLS) settings.
+///
+/// When configured, the server asks every client for a certificate signed by one of
+/// the CAs of `ca_path`. A verified certificate is mapped to a user of the password
+/// database: first through the `fingerprints` table, then through the common name
+/// (CN) of its subject.
+///
+/// # Fields
+///
+/// - `ca_path`: PEM bundle of the CAs that sign client certificates
+/// - `require_certificate`: Refuse TLS connections without a client certificate
+/// - `require_password`: Require both the certificate and the password at login,
+///   instead of letting the certificate replace the password
+/// - `fingerprints`: SHA-256 fingerprint (lowercase hex, without separators) to user
+///
+/// # Examples
+///
+/// ```rust
+/// let client_auth = ClientAuth::new("certs/client-ca.crt", false, false, HashMap::new());
+/// let config = ServerConfig::builder()
+///     .with_safe_defaults()
+///     .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(
+///         client_auth.load_roots()?,
+///     ))
+///     .with_single_cert(certs, key)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientAuth {
+    pub ca_path: String,
+    pub require_certificate: bool,
+    pub require_password: bool,
+    pub fingerprints: HashMap<String, String>,
+}
+
+/// What the client certificate of a connection allows at login.
+///
+/// - `user`: User the verified client certificate maps to, if any
+/// - `require_password`: The password is still required for that user
+#[derive(Clone, Debug, Default)]
+pub struct CertificateLogin {
+    pub user: Option<String>,
+    pub require_password: bool,
+}
+
+impl ClientAuth {
+    /// Creates the settings; fingerprints may be written in any case, with or without
+    /// `:` separators.
+    pub fn new(
+        ca_path: &str,
+        require_certificate: bool,
+        require_password: bool,
+        fingerprints: HashMap<String, String>,
+    ) -> Self {
+        ClientAuth {
+            ca_path: ca_path.to_string(),
+            require_certificate,
+            require_password,
+            fingerprints: fingerprints
+                .into_iter()
+                .map(|(fingerprint, user)| (normalize_fingerprint(&fingerprint), user))
+                .collect(),
+        }
+    }
+    /// Loads the CAs trusted to sign client certificates.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the bundle cannot be read or contains no valid certificate.
+    pub fn load_roots(&self) -> Result<RootCertStore, Box<dyn Error>> {
+        let mut reader = BufReader::new(File::open(&self.ca_path)?);
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut reader)? {
+            roots.add(&Certificate(cert))?;
+        }
+        if roots.is_empty() {
+            return Err(format!("no CA certificate found in {}", self.ca_path).into());
+        }
+        Ok(roots)
+    }
+    /// Returns what the certificate chain presented by a client allows at login.
+    ///
+    /// `chain` is the chain already verified by the TLS handshake, with the client
+    /// certificate first; clients without a certificate get no user.
+    pub fn certificate_login(&self, chain: Option<&[Certificate]>) -> CertificateLogin {
+        let user = chain.and_then(|chain| chain.first()).and_then(|cert| {
+            self.fingerprints
+                .get(&fingerprint(cert))
+                .cloned()
+                .or_else(|| common_name(cert))
+        });
+        CertificateLogin {
+            user,
+            require_password: self.require_password,
+        }
+    }
+}
+
+/// Returns the SHA-256 fingerprint of a certificate as lowercase hex.
+pub fn fingerprint(cert: &Certificate) -> String {
+    Sha256::digest(&cert.0)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Returns the common name (CN) of the subject of a certificate.
+fn common_name(cert: &Certificate) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(&cert.0).ok()?;
+    let name = cert.subject().iter_common_name().next()?;
+    name.as_str().ok().map(|name| name.to_string())
+}
+
+/// Removes separators from a fingerprint and converts it to lowercase.
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
@@ -1,10 +1,15 @@
 use crate::command_system::command_runner::RunCommand;
-use crate::command_system::common::Command;
 use crate::command_system::common::get_commands;
+use crate::command_system::common::{Command, Format, escape_text, get_format};
 use crate::command_system::operation_handler::OperationHandler;
+use crate::command_system::permissions::Permissions;
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
+use crate::connection_limiter::ConnectionLimiter;
+use crate::login_guard::LoginGuard;
+use crate::session_registry::SessionRegistry;
+use protocol::message::Response;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 /// A command handler for processing and executing shell-like commands with support for pipes,
 /// redirections, and logical operators.
@@ -23,7 +28,11 @@ pub struct CommandHandler {
     cmds: Vec<Command>,
     root: PathBuf,
     current_dir: PathBuf,
-    users_list: Arc<RwLock<Vec<String>>>,
+    home: PathBuf,
+    permissions: Permissions,
+    sessions: Arc<SessionRegistry>,
+    login_guard: Option<Arc<LoginGuard>>,
+    connections: Option<Arc<ConnectionLimiter>>,
 }
 impl CommandHandler {
     /// Creates a new `CommandHandler` instance with the specified configuration.
@@ -33,7 +42,7 @@ impl CommandHandler {
     /// - `client_input`: Raw command string from the client (e.g., "ls -la | grep txt")
     /// - `path`: Root directory path for sandboxing operations
     /// - `current_dir`: Current working directory for command execution
-    /// - `users`: Thread-safe list of active users for session management
+    /// - `sessions`: The logged in sessions, listed by `who` and `users`
     /// 
     /// # Returns
     /// 
@@ -43,7 +52,7 @@ impl CommandHandler {
     /// 
     /// - Parses the client input into individual commands using `get_commands()`
     /// - Canonicalizes paths when possible, falling back to original paths on error
-    /// - Stores user list reference for multi-user command support
+    /// - Stores the session registry for multi-user command support
     /// 
     /// # Examples
     /// 
@@ -52,22 +61,68 @@ impl CommandHandler {
     ///     "cd /tmp && ls".to_string(),
     ///     PathBuf::from("/home/secure"),
     ///     PathBuf::from("/home/secure/user"),
-    ///     Arc::new(RwLock::new(vec!["alice".to_string()]))
+    ///     Arc::new(SessionRegistry::default())
     /// );
     /// ```
     pub fn new(
         client_input: String,
         path: PathBuf,
         current_dir: PathBuf,
-        users: Arc<RwLock<Vec<String>>>,
+        sessions: Arc<SessionRegistry>,
     ) -> Self {
+        let root = std::fs::canonicalize(&path).unwrap_or(path);
         CommandHandler {
             cmds: get_commands(client_input),
-            root: std::fs::canonicalize(&path).unwrap_or(path),
+            home: root.clone(),
+            root,
+            permissions: Permissions::default(),
             current_dir: std::fs::canonicalize(&current_dir).unwrap_or(current_dir),
-            users_list: users,
+            sessions,
+            login_guard: None,
+            connections: None,
         }
     }
+    /// Sets the home directory of the user, where `cd` without arguments goes.
+    ///
+    /// The home must be inside the root; without it, the home is the root itself.
+    pub fn with_home(mut self, home: PathBuf) -> Self {
+        self.home = std::fs::canonicalize(&home).unwrap_or(home);
+        self
+    }
+    /// Sets what the user may run; without it every command is allowed.
+    pub fn with_permissions(mut self, permissions: Permissions) -> Self {
+        self.permissions = permissions;
+        self
+    }
+    /// Sets the `LoginGuard` administered by the `unlock` command.
+    pub fn with_login_guard(mut self, guard: Arc<LoginGuard>) -> Self {
+        self.login_guard = Some(guard);
+        self
+    }
+    /// Sets the `ConnectionLimiter` shown by the `connections` command.
+    pub fn with_connections(mut self, limiter: Arc<ConnectionLimiter>) -> Self {
+        self.connections = Some(limiter);
+        self
+    }
+    /// Returns the sandbox resolving paths from the current directory.
+    fn sandbox(&self) -> PathSandbox {
+        PathSandbox::new(self.root.clone(), self.current_dir.clone())
+    }
+    /// Checks every command of the line before any of them runs, so a refused
+    /// command cannot leave the ones before it half done, and a refused `>` never
+    /// creates its file.
+    ///
+    /// The file names after `<` and `>` are not commands and are not checked.
+    fn check_permissions(&self) -> Result<(), String> {
+        let mut previous_op: Option<&str> = None;
+        for cmd in &self.cmds {
+            if !matches!(previous_op, Some("<") | Some(">")) {
+                self.permissions.check(cmd)?;
+            }
+            previous_op = cmd.op.as_deref();
+        }
+        Ok(())
+    }
     /// Executes all parsed commands sequentially with support for shell operations.
     /// 
     /// This method processes the command chain, handling various shell operators and
@@ -80,16 +135,20 @@ impl CommandHandler {
     /// 
     /// # Returns
     /// 
-    /// - `Some(String)`: Combined output from all executed commands
-    /// - `None`: If no output was generated or an error occurred
+    /// A tuple containing:
+    /// - `Option<String>`: Combined output from all executed commands, `None` if no
+    ///   output was generated or an error occurred
+    /// - `bool`: Success status of the command chain
     /// 
     /// # Command Flow
     /// 
-    /// 1. **Input Processing**: Check for input redirection and read file content
-    /// 2. **Command Execution**: Run individual commands via `RunCommand`
-    /// 3. **Operation Handling**: Process shell operators via `OperationHandler`
-    /// 4. **State Management**: Update current directory and success status
-    /// 5. **Output Accumulation**: Combine results based on operators
+    /// 1. **Authorization**: Refuse the whole line if the user may not run one of its
+    ///    commands
+    /// 2. **Input Processing**: Check for input redirection and read file content
+    /// 3. **Command Execution**: Run individual commands via `RunCommand`
+    /// 4. **Operation Handling**: Process shell operators via `OperationHandler`
+    /// 5. **State Management**: Update current directory and success status
+    /// 6. **Output Accumulation**: Combine results based on operators
     /// 
     /// # Special Behaviors
     /// 
@@ -98,7 +157,10 @@ impl CommandHandler {
     /// - **Success tracking**: Logical operators depend on previous command success
     /// - **Directory changes**: `cd` commands update the current working directory
     /// 
-    async fn run_commands(&mut self) -> Option<String> {
+    async fn run_commands(&mut self) -> (Option<String>, bool) {
+        if let Err(denied) = self.check_permissions() {
+            return (Some(denied), false);
+        }
         let mut final_result: Option<String> = None;
         let mut jump_cmd = false;
 
@@ -115,15 +177,42 @@ impl CommandHandler {
             let op = cmd.op.clone().unwrap_or("".to_string());
             if op == "<" {
                 if self.cmds[i].cmd.is_empty() {
-                    return None;
+                    return (None, false);
                 }
-                input = Some(
-                    std::fs::read_to_string(self.current_dir.join(&self.cmds[i + 1].cmd[0]))
-                        .unwrap_or("".to_string()),
-                );
+                let Some(file_name) = self.cmds.get(i + 1).and_then(|c| c.cmd.first()) else {
+                    return (
+                        Some(format!(
+                            "{}missing file operand{}",
+                            get_format(Format::Error),
+                            get_format(Format::Split)
+                        )),
+                        false,
+                    );
+                };
+                let Some(file_path) = self.sandbox().resolve(file_name) else {
+                    return (
+                        Some(format!(
+                            "{}{}: {}{}",
+                            get_format(Format::Error),
+                            escape_text(file_name),
+                            PERMISSION_DENIED,
+                            get_format(Format::Split)
+                        )),
+                        false,
+                    );
+                };
+                // the file is passed on like the output of `cat`, so commands reading
+                // their input through get_unformated_text see the same text
+                let content = std::fs::read_to_string(file_path).unwrap_or("".to_string());
+                input = Some(format!(
+                    "{}{}{}",
+                    get_format(Format::Normal),
+                    escape_text(&content),
+                    get_format(Format::Split)
+                ));
             }
             if op == ">" && self.cmds[i].cmd.is_empty() {
-                return None;
+                return (None, false);
             }
             if !jump_cmd {
                 let mut runner = RunCommand::new(
@@ -131,8 +220,16 @@ impl CommandHandler {
                     self.root.clone(),
                     cmd,
                     input.clone(),
-                    self.users_list.clone(),
-                );
+                    self.sessions.clone(),
+                )
+                .with_home(self.home.clone())
+                .with_permissions(self.permissions.clone());
+                if let Some(guard) = &self.login_guard {
+                    runner = runner.with_login_guard(guard.clone());
+                }
+                if let Some(limiter) = &self.connections {
+                    runner = runner.with_connections(limiter.clone());
+                }
                 (current_dir, result, cmd_succes) = runner.test().await;
                 self.current_dir = current_dir; //cd 
             }
@@ -141,7 +238,7 @@ impl CommandHandler {
                 result.clone().unwrap_or("".to_string()),
                 cmds_output,
                 &sliced_cmds,
-                self.current_dir.clone(),
+                self.sandbox(),
                 last_succes & cmd_succes,
             );
             let (mut current_output, output, jump, op_succes) = operation.get_output();
@@ -167,7 +264,7 @@ impl CommandHandler {
             //dbg!(&cmds_output);
             //dbg!(&final_result);
         }
-        final_result
+        (final_result, last_succes)
     }
      /// Executes the parsed commands and returns formatted output with current directory.
     /// 
@@ -180,8 +277,9 @@ impl CommandHandler {
     /// # Returns
     /// 
     /// A tuple containing:
-    /// - `String`: Formatted command output with directory path in the format: `"output[-]:path[-]\r\n\r\n"`
+    /// - `Response`: Formatted command output with the directory path, encoded on the wire as `"output[-]:path[-]"`
     /// - `PathBuf`: Updated current working directory after command execution
+    /// - `bool`: Success status of the command chain
     /// 
     /// # Security
     /// 
@@ -192,9 +290,9 @@ impl CommandHandler {
     /// 
     /// # Output Format
     /// 
-    /// The returned string follows the format:
+    /// The returned response is encoded on the wire as:
     /// ```text
-    /// command_output[-]:relative/path/from/root[-]\r\n\r\n
+    /// command_output[-]:relative/path/from/root[-]
     /// ```
     /// 
     /// # Examples
@@ -204,13 +302,14 @@ impl CommandHandler {
     ///     "pwd".to_string(),
     ///     PathBuf::from("/home/secure"),
     ///     PathBuf::from("/home/secure/user"),
-    ///     users_list
+    ///     sessions
     /// );
     /// 
-    /// let (output, new_dir) = handler.get_output().await;
-    pub async fn get_output(&mut self) -> (String, PathBuf) {
+    /// let (output, new_dir, succes) = handler.get_output().await;
+    pub async fn get_output(&mut self) -> (Response, PathBuf, bool) {
         //dbg!(&self.cmds);
-        let output = self.run_commands().await.unwrap_or("".to_string());
+        let (output, succes) = self.run_commands().await;
+        let output = output.unwrap_or("".to_string());
 
         let current_dir = match self.current_dir.strip_prefix(&self.root) {
             Ok(path) => path,
@@ -219,8 +318,179 @@ impl CommandHandler {
                 std::path::Path::new("")
             }
         };
-        let reply = format!("{}[-]:{}[-]\r\n\r\n", output, current_dir.display());
+        let reply = Response::new(output, current_dir.display().to_string());
         //dbg!(&reply);
-        (reply, self.current_dir.clone())
+        (reply, self.current_dir.clone(), succes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::format::{split_sections, unescape_text};
+    use std::path::Path;
+
+    /// File contents made of every sequence the response parser gives a meaning to.
+    const HOSTILE: &str = "a ?&Eforged\n\n^!dir ^@red~~ [-]:/etc[-] back\\slash\\q end~";
+    /// File contents ending in a newline, which would merge with the split separator.
+    const HOSTILE_LINES: &str = "first ?&Eline\n\nlast ^!line\n";
+    /// A file name with the same sequences (`/` cannot appear in a name).
+    const HOSTILE_NAME: &str = "?&Ename ^!x ^@y~~ [-]:[-] \\q\n\nz~";
+
+    fn root() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("h.txt"), HOSTILE).unwrap();
+        std::fs::write(root.path().join("l.txt"), HOSTILE_LINES).unwrap();
+        root
+    }
+
+    async fn run(root: &Path, line: &str) -> (String, bool) {
+        let mut handler = CommandHandler::new(
+            line.to_string(),
+            root.to_path_buf(),
+            root.to_path_buf(),
+            Arc::new(SessionRegistry::default()),
+        );
+        let (response, _, succes) = handler.get_output().await;
+        (response.output, succes)
+    }
+
+    /// Splits `output` into its sections and unescapes their parts, the way clients
+    /// decode a response. Color markers are removed before unescaping.
+    fn decode(output: &str) -> Vec<(Format, Vec<String>)> {
+        split_sections(output)
+            .into_iter()
+            .filter(|section| !section.parts.is_empty())
+            .map(|section| {
+                let parts = section
+                    .parts
+                    .iter()
+                    .map(|part| unescape_text(&part.replace("^@", "").replace("~~", "")))
+                    .collect();
+                (section.format.unwrap(), parts)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn cat_returns_hostile_contents_unchanged() {
+        let root = root();
+        for (file, contents) in [("h.txt", HOSTILE), ("l.txt", HOSTILE_LINES)] {
+            let (output, succes) = run(root.path(), &format!("cat {}", file)).await;
+            assert!(succes);
+            assert_eq!(
+                decode(&output),
+                vec![(Format::Normal, vec![contents.to_string()])]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn echo_returns_hostile_arguments_unchanged() {
+        let root = root();
+        for argument in [HOSTILE, HOSTILE_LINES] {
+            let line = format!("echo {}", shell_words::quote(argument));
+            let (output, _) = run(root.path(), &line).await;
+            let expected = format!(" {}", argument);
+            assert_eq!(decode(&output), vec![(Format::Normal, vec![expected])]);
+        }
+    }
+
+    #[tokio::test]
+    async fn grep_returns_hostile_matches_unchanged() {
+        let root = root();
+        for (pattern, word) in [
+            ("forged", "?&Eforged"),
+            ("red", "^@red~~"),
+            ("etc", "[-]:/etc[-]"),
+        ] {
+            let (output, _) = run(root.path(), &format!("grep {} h.txt", pattern)).await;
+            let decoded = decode(&output);
+            assert_eq!(decoded.len(), 1, "{:?}", output);
+            assert_eq!(decoded[0].1, vec![word.to_string()], "{:?}", output);
+        }
+    }
+
+    #[tokio::test]
+    async fn wc_returns_hostile_file_names_unchanged() {
+        let root = root();
+        std::fs::write(root.path().join(HOSTILE_NAME), "one two").unwrap();
+        let line = format!("wc {}", shell_words::quote(HOSTILE_NAME));
+        let (output, _) = run(root.path(), &line).await;
+        let decoded = decode(&output);
+        assert_eq!(decoded.len(), 1, "{:?}", output);
+        assert_eq!(decoded[0].1.len(), 1, "{:?}", output);
+        assert!(
+            decoded[0].1[0].ends_with(&format!("  ({})", HOSTILE_NAME)),
+            "{:?}",
+            output
+        );
+    }
+
+    #[tokio::test]
+    async fn ls_returns_hostile_names_unchanged() {
+        let root = root();
+        let directory = HOSTILE_NAME.replace('z', "d");
+        std::fs::write(root.path().join(HOSTILE_NAME), "").unwrap();
+        std::fs::create_dir(root.path().join(&directory)).unwrap();
+        let (output, _) = run(root.path(), "ls").await;
+        let sections = split_sections(&output);
+        let listing = sections
+            .iter()
+            .find(|section| section.format == Some(Format::ListDir))
+            .unwrap();
+        let mut names: Vec<String> = listing
+            .parts
+            .iter()
+            .map(|part| match part.strip_prefix("^!") {
+                Some(name) => format!("{}/", unescape_text(name)),
+                None => unescape_text(part),
+            })
+            .collect();
+        names.sort();
+        let mut expected = vec![
+            format!("{}/", directory),
+            HOSTILE_NAME.to_string(),
+            "h.txt".to_string(),
+            "l.txt".to_string(),
+        ];
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn executables_return_hostile_output_unchanged() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = root();
+        for (file, contents) in [("h.txt", HOSTILE), ("l.txt", HOSTILE_LINES)] {
+            let script = root.path().join("run.sh");
+            let file = root.path().join(file);
+            std::fs::write(&script, format!("#!/bin/sh\ncat '{}'\n", file.display())).unwrap();
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let (output, succes) = run(root.path(), "./run.sh").await;
+            assert!(succes);
+            assert_eq!(
+                decode(&output),
+                vec![(Format::Normal, vec![contents.to_string()])]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn redirected_input_keeps_hostile_contents() {
+        let root = root();
+        for line in ["grep red < h.txt", "cat h.txt | grep red"] {
+            let (output, _) = run(root.path(), line).await;
+            let decoded = decode(&output);
+            assert_eq!(decoded.len(), 1, "{}: {:?}", line, output);
+            assert_eq!(
+                decoded[0].1,
+                vec!["^@red~~".to_string()],
+                "{}: {:?}",
+                line,
+                output
+            );
+        }
     }
 }
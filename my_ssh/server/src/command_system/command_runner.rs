@@ -1,5 +1,6 @@
 use crate::command_system::commands::change_directory::ChangeDIR;
 use crate::command_system::commands::concatenate::Cat;
+use crate::command_system::commands::connections::Connections;
 use crate::command_system::commands::echo::Echo;
 use crate::command_system::commands::executable_files::Execute;
 use crate::command_system::commands::global_regular_expresion_print::Grep;
@@ -7,16 +8,21 @@ use crate::command_system::commands::make_director::MakeDir;
 use crate::command_system::commands::move_class::MoveFileAndDir;
 use crate::command_system::commands::remove_director::RmDir;
 use crate::command_system::commands::remove_file::RemoveFile;
+use crate::command_system::commands::unlock::Unlock;
 use crate::command_system::commands::users::ListUsers;
 use crate::command_system::commands::word_count::WordCount;
 use crate::command_system::common::Format;
+use crate::command_system::permissions::Permissions;
+use crate::command_system::sandbox::PathSandbox;
 use crate::command_system::{
     commands::list_files::ListFiles,
-    common::{Command, get_format},
+    common::{Command, escape_text, get_format},
 };
+use crate::connection_limiter::ConnectionLimiter;
+use crate::login_guard::LoginGuard;
+use crate::session_registry::SessionRegistry;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 /// A command runner that executes individual shell commands within a secure environment.
 /// 
 /// `RunCommand` serves as a dispatcher that routes commands to their appropriate handlers
@@ -33,6 +39,7 @@ use tokio::sync::RwLock;
 /// - **Directory Operations**: `cd`, `pwd`, `mkdir`, `rmdir`
 /// - **Text Processing**: `echo`, `grep`, `wc` (word count)
 /// - **System Utilities**: `who`, `users`
+/// - **Administration**: `unlock`, `connections` (roles with `admin` only)
 /// - **Executable Files**: `./filename` (execute local programs)
 /// 
 /// # Examples
@@ -40,9 +47,8 @@ use tokio::sync::RwLock;
 /// ```rust
 /// use std::path::PathBuf;
 /// use std::sync::Arc;
-/// use tokio::sync::RwLock;
 /// 
-/// let users = Arc::new(RwLock::new(vec!["alice".to_string()]));
+/// let sessions = Arc::new(SessionRegistry::default());
 /// let command = Command { cmd: vec!["ls".to_string(), "-la".to_string()], op: None };
 /// 
 /// let mut runner = RunCommand::new(
@@ -50,7 +56,7 @@ use tokio::sync::RwLock;
 ///     PathBuf::from("/home"),
 ///     &command,
 ///     None,
-///     users
+///     sessions
 /// );
 /// 
 /// let (new_path, output, success) = runner.test().await;
@@ -60,8 +66,31 @@ pub struct RunCommand {
     command: Command,
     input: Option<String>,
     root: std::path::PathBuf,
-    users_list: Arc<RwLock<Vec<String>>>,
+    home: std::path::PathBuf,
+    permissions: Permissions,
+    sessions: Arc<SessionRegistry>,
+    login_guard: Option<Arc<LoginGuard>>,
+    connections: Option<Arc<ConnectionLimiter>>,
 }
+/// Every command name accepted by `Commands::from_str`, used for tab completion.
+pub const COMMAND_NAMES: [&str; 16] = [
+    "cat",
+    "cd",
+    "connections",
+    "echo",
+    "grep",
+    "ls",
+    "mkdir",
+    "mv",
+    "next",
+    "pwd",
+    "rm",
+    "rmdir",
+    "unlock",
+    "users",
+    "wc",
+    "who",
+];
 enum Commands {
     ChangeDirectory,
     PrintWorkingDirectory,
@@ -75,6 +104,8 @@ enum Commands {
     WordCount,
     Remove,
     Users,
+    Unlock,
+    Connections,
     Unknown(String),
 }
 
@@ -110,6 +141,8 @@ impl Commands {
     /// | `rmdir` | `RemoveDir` | Remove directories |
     /// | `wc` | `WordCount` | Word count |
     /// | `who`, `users` | `Users` | List users |
+    /// | `unlock` | `Unlock` | List or clear login lockouts |
+    /// | `connections` | `Connections` | Show connection counts and limits |
     /// | *other* | `Unknown(String)` | Unrecognized command |
     /// 
     /// # Examples
@@ -136,6 +169,8 @@ impl Commands {
             "rm" => Commands::Remove,
             "rmdir" => Commands::RemoveDir,
             "who" | "users" => Commands::Users,
+            "unlock" => Commands::Unlock,
+            "connections" => Commands::Connections,
             other => Commands::Unknown(other.to_string()),
         }
     }
@@ -147,10 +182,11 @@ impl RunCommand {
     /// # Parameters
     /// 
     /// - `current_path`: Current working directory where the command will be executed
-    /// - `root`: Root directory for security sandboxing (commands cannot access parent directories)
+    /// - `root`: Root directory for security sandboxing; every path argument is resolved
+    ///   through a `PathSandbox` and cannot leave it
     /// - `command`: The command structure containing the command name and arguments
     /// - `input`: Optional input string (typically from pipe operations)
-    /// - `users`: Thread-safe reference to the list of active users
+    /// - `sessions`: The logged in sessions, listed by `who` and `users`
     /// 
     /// # Returns
     /// 
@@ -169,7 +205,7 @@ impl RunCommand {
     ///     PathBuf::from("/home"),
     ///     &command,
     ///     Some("input data".to_string()),
-    ///     Arc::new(RwLock::new(vec!["user1".to_string()]))
+    ///     Arc::new(SessionRegistry::default())
     /// );
     /// ```
     pub fn new(
@@ -177,24 +213,51 @@ impl RunCommand {
         root: PathBuf,
         command: &Command,
         input: Option<String>,
-        users: Arc<RwLock<Vec<String>>>,
+        sessions: Arc<SessionRegistry>,
     ) -> Self {
         Self {
             path: current_path,
+            home: root.clone(),
             root,
+            permissions: Permissions::default(),
             command: command.clone(),
             input,
-            users_list: users,
+            sessions,
+            login_guard: None,
+            connections: None,
         }
     }
+    /// Sets the home directory of the user, where `cd` without arguments goes.
+    ///
+    /// Without it, the home is the root directory.
+    pub fn with_home(mut self, home: PathBuf) -> Self {
+        self.home = home;
+        self
+    }
+    /// Sets what the user may run; without it every command is allowed.
+    pub fn with_permissions(mut self, permissions: Permissions) -> Self {
+        self.permissions = permissions;
+        self
+    }
+    /// Sets the `LoginGuard` whose lockouts `unlock` lists and clears.
+    pub fn with_login_guard(mut self, guard: Arc<LoginGuard>) -> Self {
+        self.login_guard = Some(guard);
+        self
+    }
+    /// Sets the `ConnectionLimiter` whose counts `connections` shows.
+    pub fn with_connections(mut self, limiter: Arc<ConnectionLimiter>) -> Self {
+        self.connections = Some(limiter);
+        self
+    }
     /// Executes the command and returns the result with updated state.
     /// 
     /// This method serves as the main execution dispatcher. It:
-    /// 1. Parses the command name to determine the command type
-    /// 2. Routes the command to the appropriate handler
-    /// 3. Manages special cases for input handling (pipes)
-    /// 4. Updates the current directory state for directory-changing commands
-    /// 5. Handles executable file execution for `./filename` patterns
+    /// 1. Checks that the role of the user allows the command (see `Permissions`)
+    /// 2. Parses the command name to determine the command type
+    /// 3. Routes the command to the appropriate handler
+    /// 4. Manages special cases for input handling (pipes)
+    /// 5. Updates the current directory state for directory-changing commands
+    /// 6. Handles executable file execution for `./filename` patterns
     /// 
     /// # Returns
     /// 
@@ -206,7 +269,8 @@ impl RunCommand {
     /// # Command-Specific Behaviors
     /// 
     /// ## Directory Commands
-    /// - **`cd`**: Changes current directory and updates internal path state
+    /// - **`cd`**: Changes current directory and updates internal path state; without
+    ///   arguments it goes to the home directory, and it never leaves the root
     /// - **`pwd`**: Returns relative path from root directory
     /// 
     /// ## File Operations
@@ -223,7 +287,9 @@ impl RunCommand {
     /// - **`wc`**: Counts words, lines, and characters
     /// 
     /// ## System Commands
-    /// - **`who`/`users`**: Lists currently active users
+    /// - **`who`/`users`**: Lists the logged in sessions, with their details for `who`
+    /// - **`unlock`**: Lists the login lockouts, or clears those of the given users
+    ///   and addresses
     /// 
     /// ## Executable Files
     /// - **`./filename`**: Executes local executable files
@@ -239,6 +305,8 @@ impl RunCommand {
     /// # Error Handling
     /// 
     /// - Commands that fail return `false` for success status
+    /// - Commands, `>` redirections and executables the user may not use return a
+    ///   permission-denied error without running
     /// - Unknown commands return formatted error messages
     /// - Individual command handlers manage their own error conditions
     /// 
@@ -256,17 +324,21 @@ impl RunCommand {
     /// }
     /// ```
     pub async fn test(&mut self) -> (PathBuf, Option<String>, bool) {
+        if let Err(denied) = self.permissions.check(&self.command) {
+            return (self.path.clone(), Some(denied), false);
+        }
+        let sandbox = PathSandbox::new(self.root.clone(), self.path.clone());
         let command = Commands::from_str(&self.command.cmd[0]);
         let (output, succes) = match command {
             Commands::ChangeDirectory => {
-                let cd = ChangeDIR::new(self.command.clone(), self.path.clone(), self.root.clone());
+                let cd = ChangeDIR::new(self.command.clone(), sandbox, self.home.clone());
                 let (path, new_output, new_succes) = cd.get_new_path_or_output();
                 self.path = path;
 
                 (Some(new_output), new_succes)
             }
             Commands::ListFiles => {
-                let list = ListFiles::new(self.path.clone(), self.command.clone());
+                let list = ListFiles::new(sandbox, self.command.clone());
                 let (new_output, new_succes) = list.get_output();
                 if self.input.is_some() {
                     (Some("".to_string()), new_succes)
@@ -275,26 +347,35 @@ impl RunCommand {
                 }
             }
             Commands::Cat => {
-                let cat = Cat::new(self.command.clone(), self.path.clone());
+                let cat = Cat::new(self.command.clone(), sandbox);
                 let (new_output, new_succes) = cat.get_output();
                 (Some(new_output), new_succes)
             }
             Commands::WordCount => {
-                let wc =
-                    WordCount::new(self.command.clone(), self.input.clone(), self.path.clone());
+                let wc = WordCount::new(self.command.clone(), self.input.clone(), sandbox);
                 let (new_output, new_succes) = wc.get_output();
                 (Some(new_output), new_succes)
             }
             Commands::Grep => {
-                let grep = Grep::new(self.command.clone(), self.input.clone(), self.path.clone());
+                let grep = Grep::new(self.command.clone(), self.input.clone(), sandbox);
                 let (new_output, new_succes) = grep.get_output();
                 (Some(new_output), new_succes)
             }
             Commands::Users => {
-                let who = ListUsers::new(self.command.clone(), self.users_list.clone());
+                let who = ListUsers::new(self.command.clone(), self.sessions.clone());
                 let (new_output, new_succes) = who.get_output().await;
                 (Some(new_output), new_succes)
             }
+            Commands::Unlock => {
+                let unlock = Unlock::new(self.command.clone(), self.login_guard.clone());
+                let (new_output, new_succes) = unlock.get_output().await;
+                (Some(new_output), new_succes)
+            }
+            Commands::Connections => {
+                let connections = Connections::new(self.command.clone(), self.connections.clone());
+                let (new_output, new_succes) = connections.get_output();
+                (Some(new_output), new_succes)
+            }
             Commands::PrintWorkingDirectory => {
                 let out = self
                     .path
@@ -306,29 +387,29 @@ impl RunCommand {
                     Some(format!(
                         "{}home:/{}{}",
                         get_format(Format::Normal),
-                        out,
+                        escape_text(&out),
                         get_format(Format::Split)
                     )),
                     true,
                 )
             }
             Commands::MakeDir => {
-                let mkdir = MakeDir::new(self.command.clone(), self.path.clone());
+                let mkdir = MakeDir::new(self.command.clone(), sandbox);
                 let (new_output, new_succes) = mkdir.get_output();
                 (Some(new_output), new_succes)
             }
             Commands::RemoveDir => {
-                let rmdir = RmDir::new(self.command.clone(), self.path.clone());
+                let rmdir = RmDir::new(self.command.clone(), sandbox);
                 let (new_output, new_succes) = rmdir.get_output();
                 (Some(new_output), new_succes)
             }
             Commands::Remove => {
-                let rm = RemoveFile::new(self.command.clone(), self.path.clone());
+                let rm = RemoveFile::new(self.command.clone(), sandbox);
                 let (new_output, new_succes) = rm.get_output();
                 (Some(new_output), new_succes)
             }
             Commands::MoveFileAndDir => {
-                let mv = MoveFileAndDir::new(self.command.clone(), self.path.clone());
+                let mv = MoveFileAndDir::new(self.command.clone(), sandbox);
                 let (new_output, new_succes) = mv.get_output();
                 (Some(new_output), new_succes)
             }
@@ -343,7 +424,7 @@ impl RunCommand {
             }
             Commands::Unknown(cmd) => {
                 if cmd.starts_with("./") {
-                    let exe = Execute::new(self.command.clone(), self.path.clone());
+                    let exe = Execute::new(self.command.clone(), sandbox);
                     let (new_output, new_succes) = exe.get_output().await;
                     (Some(new_output), new_succes)
                 } else {
@@ -351,7 +432,7 @@ impl RunCommand {
                     let new_output = Some(format!(
                         "{}Error , Command {} not found {}",
                         get_format(Format::Error),
-                        cmd,
+                        escape_text(&cmd),
                         get_format(Format::Split)
                     ));
                     (new_output, new_succes)
@@ -1,22 +1,30 @@
-use crate::command_system::common::{Command, Format, get_format};
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 
 pub struct Cat {
     command: Command,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
 }
 
 impl Cat {
-    pub fn new(cmd: Command, path: std::path::PathBuf) -> Self {
+    pub fn new(cmd: Command, sandbox: PathSandbox) -> Self {
         Cat {
             command: cmd,
-            current_dir: path,
+            sandbox,
         }
     }
     pub fn get_output(&self) -> (String, bool) {
         let mut output = String::new();
         if self.command.cmd.len() != 1 {
             for file in &self.command.cmd[1..] {
-                let new_path = self.current_dir.join(file);
+                let Some(new_path) = self.sandbox.resolve(file) else {
+                    if output.is_empty() {
+                        output = format!("cat: {}: {}", file, PERMISSION_DENIED);
+                    } else {
+                        output = format!("{}\ncat: {}: {}", output, file, PERMISSION_DENIED);
+                    }
+                    continue;
+                };
                 if new_path.exists() && new_path.is_file() {
                     let text = std::fs::read_to_string(&new_path).unwrap_or("".to_string());
                     //dbg!(&text);
@@ -46,7 +54,7 @@ impl Cat {
             format!(
                 "{}{}{}",
                 get_format(Format::Normal),
-                output,
+                escape_text(&output),
                 get_format(Format::Split)
             ),
             true,
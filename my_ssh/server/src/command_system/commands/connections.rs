@@ -0,0 +1,88 @@
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::connection_limiter::ConnectionLimiter;
+use std::sync::Arc;
+
+/// The `connections` administration command.
+///
+/// Prints the connections counted by the `ConnectionLimiter` against their limits:
+/// all of them, the TLS handshakes in progress, the connections refused since the
+/// server started, and the connections of each source address.
+///
+/// # Output
+///
+/// ```text
+/// connections: 3 of 256
+/// handshakes: 0 of 32
+/// refused: 12
+/// address 127.0.0.1: 2 of 16
+/// address 203.0.113.7: 1 of 16
+/// ```
+pub struct Connections {
+    command: Command,
+    limiter: Option<Arc<ConnectionLimiter>>,
+}
+
+impl Connections {
+    pub fn new(cmd: Command, limiter: Option<Arc<ConnectionLimiter>>) -> Self {
+        Connections {
+            command: cmd,
+            limiter,
+        }
+    }
+    pub fn get_output(&self) -> (String, bool) {
+        let limiter = if self.command.cmd.len() > 1 {
+            return (
+                format!(
+                    "{}Error , connections: too many arguments{}",
+                    get_format(Format::Error),
+                    get_format(Format::Split)
+                ),
+                false,
+            );
+        } else if let Some(limiter) = &self.limiter {
+            limiter
+        } else {
+            return (
+                format!(
+                    "{}Error , connections: connections are not tracked{}",
+                    get_format(Format::Error),
+                    get_format(Format::Split)
+                ),
+                false,
+            );
+        };
+        let limits = limiter.limits();
+        let counts = limiter.counts();
+        let of = |count: usize, max: Option<usize>| match max {
+            Some(max) => format!("{} of {}", count, max),
+            None => format!("{} (no limit)", count),
+        };
+        let mut lines = vec![
+            format!(
+                "connections: {}",
+                of(counts.connections, limits.max_connections)
+            ),
+            format!(
+                "handshakes: {}",
+                of(counts.handshakes, limits.max_handshakes)
+            ),
+            format!("refused: {}", counts.refused),
+        ];
+        for (ip, count) in counts.per_ip {
+            lines.push(format!(
+                "address {}: {}",
+                ip,
+                of(count, limits.max_connections_per_ip)
+            ));
+        }
+        (
+            format!(
+                "{}{}{}",
+                get_format(Format::Normal),
+                escape_text(&lines.join("\n")),
+                get_format(Format::Split)
+            ),
+            true,
+        )
+    }
+}
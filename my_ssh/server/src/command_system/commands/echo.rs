@@ -1,4 +1,4 @@
-use crate::command_system::common::{Command, Format, get_format};
+use crate::command_system::common::{Command, Format, escape_text, get_format};
 
 pub struct Echo {
     command: Command,
@@ -18,7 +18,7 @@ impl Echo {
         output = format!(
             "{}{}{}",
             get_format(Format::Normal),
-            output,
+            escape_text(&output),
             get_format(Format::Split)
         );
         (output, true)
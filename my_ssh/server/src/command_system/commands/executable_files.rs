@@ -1,67 +1,83 @@
-use crate::command_system::common::{Command, Format, get_format, is_executable};
+use crate::command_system::common::{Command, Format, escape_text, get_format, is_executable};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 pub struct Execute {
     command: Command,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
 }
 
 impl Execute {
-    pub fn new(cmd: Command, path: std::path::PathBuf) -> Self {
+    pub fn new(cmd: Command, sandbox: PathSandbox) -> Self {
         Execute {
             command: cmd,
-            current_dir: path,
+            sandbox,
         }
     }
+    /// Runs the program and returns its output.
+    ///
+    /// The program is killed if the future is dropped, e.g. when the session is
+    /// aborted at the end of a server shutdown.
     pub async fn get_executable_output(&self, exe_path: std::path::PathBuf) -> (String, bool) {
         let cmd_clone = self.command.cmd.clone();
+        let args: Vec<String> = match cmd_clone.len() {
+            1 => Vec::new(),
+            _ => cmd_clone[1..].to_vec(),
+        };
+        let output = tokio::process::Command::new(exe_path)
+            .args(args)
+            .kill_on_drop(true)
+            .output()
+            .await;
+        if let Ok(output) = output {
+            let stdout_str = String::from_utf8_lossy(&output.stdout);
+            let stderr_str = String::from_utf8_lossy(&output.stderr);
 
-        tokio::task::spawn_blocking(move || {
-            let args: Vec<String> = match cmd_clone.len() {
-                1 => Vec::new(),
-                _ => cmd_clone[1..].to_vec(),
-            };
-            if let Ok(output) = std::process::Command::new(exe_path).args(args).output() {
-                let stdout_str = String::from_utf8_lossy(&output.stdout);
-                let stderr_str = String::from_utf8_lossy(&output.stderr);
-
-                let (data, status) = if stdout_str.is_empty() {
-                    (
-                        format!(
-                            "{}{}{}",
-                            get_format(Format::Normal),
-                            stderr_str,
-                            get_format(Format::Split)
-                        ),
-                        false,
-                    )
-                } else {
-                    (
-                        format!(
-                            "{}{}{}",
-                            get_format(Format::Normal),
-                            stdout_str,
-                            get_format(Format::Split)
-                        ),
-                        true,
-                    )
-                };
-                (data, status)
-            } else {
+            let (data, status) = if stdout_str.is_empty() {
                 (
                     format!(
                         "{}{}{}",
-                        get_format(Format::Error),
-                        "Unexpected error",
+                        get_format(Format::Normal),
+                        escape_text(&stderr_str),
                         get_format(Format::Split)
                     ),
                     false,
                 )
-            }
-        })
-        .await
-        .unwrap_or_else(|_| ("".to_string(), false))
+            } else {
+                (
+                    format!(
+                        "{}{}{}",
+                        get_format(Format::Normal),
+                        escape_text(&stdout_str),
+                        get_format(Format::Split)
+                    ),
+                    true,
+                )
+            };
+            (data, status)
+        } else {
+            (
+                format!(
+                    "{}{}{}",
+                    get_format(Format::Error),
+                    "Unexpected error",
+                    get_format(Format::Split)
+                ),
+                false,
+            )
+        }
     }
     pub async fn get_output(&self) -> (String, bool) {
-        let new_path = self.current_dir.join(&self.command.cmd[0]);
+        let Some(new_path) = self.sandbox.resolve(&self.command.cmd[0]) else {
+            let error = format!(" {}: {}", self.command.cmd[0], PERMISSION_DENIED);
+            return (
+                format!(
+                    "{}{}{}",
+                    get_format(Format::Error),
+                    escape_text(&error),
+                    get_format(Format::Split)
+                ),
+                false,
+            );
+        };
         if new_path.exists() && is_executable(new_path.clone()).unwrap_or(false) {
             self.get_executable_output(new_path).await
         } else if new_path.is_dir() {
@@ -70,7 +86,7 @@ impl Execute {
                 format!(
                     "{}{}{}",
                     get_format(Format::Error),
-                    error,
+                    escape_text(&error),
                     get_format(Format::Split)
                 ),
                 false,
@@ -84,7 +100,7 @@ impl Execute {
                 format!(
                     "{}{}{}",
                     get_format(Format::Error),
-                    error,
+                    escape_text(&error),
                     get_format(Format::Split)
                 ),
                 false,
@@ -95,7 +111,7 @@ impl Execute {
                 format!(
                     "{}{}{}",
                     get_format(Format::Error),
-                    error,
+                    escape_text(&error),
                     get_format(Format::Split)
                 ),
                 false,
@@ -1,16 +1,19 @@
-use crate::command_system::common::{Command, Format, get_format, get_unformated_text};
+use crate::command_system::common::{
+    Command, Format, escape_text, get_format, get_unformated_text,
+};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 
 pub struct Grep {
     command: Command,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
     input: Option<String>,
 }
 
 impl Grep {
-    pub fn new(cmd: Command, input: Option<String>, path: std::path::PathBuf) -> Self {
+    pub fn new(cmd: Command, input: Option<String>, sandbox: PathSandbox) -> Self {
         Grep {
             command: cmd,
-            current_dir: path,
+            sandbox,
             input,
         }
     }
@@ -29,14 +32,15 @@ impl Grep {
                     while let Some(poz) = remaining_part.find(pattern) {
                         if let Some(before_slice) = remaining_part.get(0..poz) {
                             if line_output.is_empty() && current_pos == 0 {
-                                line_output = before_slice.to_string();
+                                line_output = escape_text(before_slice);
                             } else {
-                                line_output = format!("{}{}", line_output, before_slice);
+                                line_output =
+                                    format!("{}{}", line_output, escape_text(before_slice));
                             }
                         }
                         line_output =
                             format!("{}{}", line_output, get_format(Format::Color("LIGHT_RED")));
-                        line_output = format!("{}{}", line_output, pattern);
+                        line_output = format!("{}{}", line_output, escape_text(pattern));
                         line_output =
                             format!("{}{}", line_output, get_format(Format::Color("stop")));
                         let next_start = poz + pattern.len();
@@ -49,7 +53,7 @@ impl Grep {
                         remaining_part = &remaining_part[next_start..];
                     }
                     if !remaining_part.is_empty() && !remaining_part.contains(pattern) {
-                        line_output = format!("{}{}", line_output, remaining_part);
+                        line_output = format!("{}{}", line_output, escape_text(remaining_part));
                     }
                 }
             }
@@ -85,7 +89,15 @@ impl Grep {
         if self.command.cmd.len() >= 3 {
             let pattern = self.command.cmd[1].clone();
             for cmd in &self.command.cmd[2..] {
-                let new_path = self.current_dir.join(cmd);
+                let Some(new_path) = self.sandbox.resolve(cmd) else {
+                    output = format!(
+                        "{}grep: {}{}",
+                        get_format(Format::Error),
+                        PERMISSION_DENIED,
+                        get_format(Format::Split)
+                    );
+                    continue;
+                };
                 if new_path.exists() && new_path.is_file() {
                     let text = std::fs::read_to_string(&new_path).unwrap_or("".to_string());
                     output = self.get_colored_output(text, &pattern);
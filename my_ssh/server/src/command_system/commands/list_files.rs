@@ -1,16 +1,21 @@
 use std::path::PathBuf;
 
-use crate::command_system::common::{Command, Format, get_files, get_format, is_executable};
+use crate::command_system::common::{
+    Command, Format, escape_text, get_files, get_format, is_executable,
+};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 
 pub struct ListFiles {
     current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
     command: Command,
 }
 
 impl ListFiles {
-    pub fn new(path: std::path::PathBuf, cmd: Command) -> Self {
+    pub fn new(sandbox: PathSandbox, cmd: Command) -> Self {
         ListFiles {
-            current_dir: path.canonicalize().unwrap_or(path),
+            current_dir: sandbox.current_dir().to_path_buf(),
+            sandbox,
             command: cmd,
         }
     }
@@ -23,6 +28,7 @@ impl ListFiles {
                 .unwrap()
                 .to_str()
                 .unwrap_or("default");
+            let f = escape_text(f);
             if file.is_file() {
                 let status = is_executable(file.clone()).unwrap_or(false);
                 if status {
@@ -57,19 +63,33 @@ impl ListFiles {
     fn get_files_in_dir_name(&self) -> (String, bool) {
         let mut output: String = String::new();
         let mut succes = false;
-        let paths: Vec<PathBuf> = self.command.cmd[1..]
-            .iter()
-            .map(|f| self.current_dir.join(f))
-            .filter(|f| f.starts_with(&self.current_dir))
-            .collect();
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for f in &self.command.cmd[1..] {
+            match self.sandbox.resolve(f) {
+                Some(path) => paths.push(path),
+                None => {
+                    output += format!(
+                        "{}ls: cannot access '{}': {}\n{}",
+                        get_format(Format::Error),
+                        escape_text(f),
+                        PERMISSION_DENIED,
+                        get_format(Format::Split)
+                    )
+                    .as_str();
+                }
+            }
+        }
         for path in &paths {
             if !path.exists() || path.is_file() {
                 output += format!(
                     "{}ls: cannot access '{}': No such file or directory\n{}",
                     get_format(Format::Error),
-                    path.strip_prefix(&self.current_dir)
-                        .unwrap_or(path)
-                        .to_string_lossy(),
+                    escape_text(
+                        &path
+                            .strip_prefix(&self.current_dir)
+                            .unwrap_or(path)
+                            .to_string_lossy()
+                    ),
                     get_format(Format::Split)
                 )
                 .as_str();
@@ -85,8 +105,19 @@ impl ListFiles {
                         .unwrap_or(std::path::Path::new(""))
                         .to_str()
                         .unwrap_or("default");
+                    let f = escape_text(f);
                     if file.is_file() {
-                        out += format!("{}{}", f, get_format(Format::Split)).as_str();
+                        if is_executable(file.clone()).unwrap_or(false) {
+                            out += format!(
+                                "{}{}{}",
+                                get_format(Format::Color("GREEN")),
+                                f,
+                                get_format(Format::Split)
+                            )
+                            .as_str();
+                        } else {
+                            out += format!("{}{}", f, get_format(Format::Split)).as_str();
+                        }
                     } else {
                         out += format!(
                             "{}{}{}",
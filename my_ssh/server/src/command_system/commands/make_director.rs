@@ -1,17 +1,18 @@
 use std::fs;
 
-use crate::command_system::common::{Command, Format, get_format};
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 
 pub struct MakeDir {
     command: Command,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
 }
 
 impl MakeDir {
-    pub fn new(cmd: Command, path: std::path::PathBuf) -> Self {
+    pub fn new(cmd: Command, sandbox: PathSandbox) -> Self {
         MakeDir {
             command: cmd,
-            current_dir: path,
+            sandbox,
         }
     }
     fn try_create_folders(&self) -> (String, bool) {
@@ -19,7 +20,19 @@ impl MakeDir {
         let mut succes = true;
         for cmd in &self.command.cmd[1..] {
             let mut line_output = String::new();
-            let new_path = self.current_dir.join(cmd);
+            let Some(new_path) = self.sandbox.resolve(cmd) else {
+                succes = false;
+                line_output = format!(
+                    "mkdir: cannot create directory `{}`: {}",
+                    cmd, PERMISSION_DENIED
+                );
+                output = if output.is_empty() {
+                    line_output
+                } else {
+                    format!("{}\n{}", output, line_output)
+                };
+                continue;
+            };
             if new_path.exists() {
                 succes = false;
                 line_output = format!("mkdir: cannot create directory `{}`: File exists", cmd);
@@ -42,7 +55,7 @@ impl MakeDir {
                 format!(
                     "{}{}{}",
                     get_format(Format::Normal),
-                    output,
+                    escape_text(&output),
                     get_format(Format::Split)
                 ),
                 succes,
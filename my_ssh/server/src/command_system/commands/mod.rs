@@ -1,5 +1,6 @@
 pub mod change_directory;
 pub mod concatenate;
+pub mod connections;
 pub mod echo;
 pub mod executable_files;
 pub mod global_regular_expresion_print;
@@ -8,5 +9,6 @@ pub mod make_director;
 pub mod move_class;
 pub mod remove_director;
 pub mod remove_file;
+pub mod unlock;
 pub mod users;
 pub mod word_count;
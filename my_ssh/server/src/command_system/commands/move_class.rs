@@ -1,28 +1,42 @@
-use crate::command_system::common::{Command, Format, get_format};
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 
 pub struct MoveFileAndDir {
     command: Command,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
 }
 
 impl MoveFileAndDir {
-    pub fn new(cmd: Command, path: std::path::PathBuf) -> Self {
+    pub fn new(cmd: Command, sandbox: PathSandbox) -> Self {
         MoveFileAndDir {
             command: cmd,
-            current_dir: path,
+            sandbox,
         }
     }
     fn try_to_move(&self) -> Result<(String, bool), Box<dyn std::error::Error>> {
         let mut output = String::new();
         let mut status = true;
-        let path = self.current_dir.join(self.command.cmd.last().unwrap());
+        let destination = self.command.cmd.last().unwrap();
+        let path = self.sandbox.resolve(destination).ok_or(format!(
+            "mv: cannot move to '{}': {}",
+            destination, PERMISSION_DENIED
+        ))?;
         let parent = path.parent().unwrap_or(&path);
         if !parent.exists() {
             std::fs::create_dir_all(parent)?;
         }
         for cmd in &self.command.cmd[1..self.command.cmd.len() - 1] {
             let mut output_cmd = String::new();
-            let old_path = self.current_dir.join(cmd);
+            let Some(old_path) = self.sandbox.resolve_entry(cmd) else {
+                output_cmd = format!("mv: cannot move '{}': {}", cmd, PERMISSION_DENIED);
+                status = false;
+                output = if output.is_empty() {
+                    output_cmd
+                } else {
+                    format!("{}\n{}", output, output_cmd)
+                };
+                continue;
+            };
             if old_path.exists() {
                 if let Err(e) = std::fs::rename(old_path, &path) {
                     output_cmd = format!("Unexpected Error {}", e);
@@ -45,7 +59,7 @@ impl MoveFileAndDir {
             format!(
                 "{}{}{}",
                 get_format(Format::Normal),
-                output,
+                escape_text(&output),
                 get_format(Format::Split)
             ),
             status,
@@ -60,7 +74,7 @@ impl MoveFileAndDir {
                     String::from("mv: missing destination file"),
                     get_format(Format::Split)
                 ),
-                true,
+                false,
             )
         } else {
             match self.try_to_move() {
@@ -69,10 +83,10 @@ impl MoveFileAndDir {
                     format!(
                         "{}{}{}",
                         get_format(Format::Error),
-                        e,
+                        escape_text(&e.to_string()),
                         get_format(Format::Split)
                     ),
-                    true,
+                    false,
                 ),
             }
         }
@@ -1,17 +1,18 @@
 use std::fs;
 
-use crate::command_system::common::{Command, Format, get_format};
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 
 pub struct RmDir {
     command: Command,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
 }
 
 impl RmDir {
-    pub fn new(cmd: Command, path: std::path::PathBuf) -> Self {
+    pub fn new(cmd: Command, sandbox: PathSandbox) -> Self {
         RmDir {
             command: cmd,
-            current_dir: path,
+            sandbox,
         }
     }
     fn try_remove_folders(&self) -> (String, bool) {
@@ -19,7 +20,16 @@ impl RmDir {
         let mut succes = true;
         for cmd in &self.command.cmd[1..] {
             let mut line_output = String::new();
-            let new_path = self.current_dir.join(cmd);
+            let Some(new_path) = self.sandbox.resolve_entry(cmd) else {
+                succes = false;
+                line_output = format!("rmdir: failed to remove '{}': {}", cmd, PERMISSION_DENIED);
+                output = if output.is_empty() {
+                    line_output
+                } else {
+                    format!("{}\n{}", output, line_output)
+                };
+                continue;
+            };
             if !new_path.exists() {
                 succes = false;
                 line_output = format!(
@@ -48,7 +58,7 @@ impl RmDir {
                 format!(
                     "{}{}{}",
                     get_format(Format::Normal),
-                    output,
+                    escape_text(&output),
                     get_format(Format::Split)
                 ),
                 succes,
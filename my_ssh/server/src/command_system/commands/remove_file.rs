@@ -1,17 +1,18 @@
 use std::fs;
 
-use crate::command_system::common::{Command, Format, get_format};
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 
 pub struct RemoveFile {
     command: Command,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
 }
 
 impl RemoveFile {
-    pub fn new(cmd: Command, path: std::path::PathBuf) -> Self {
+    pub fn new(cmd: Command, sandbox: PathSandbox) -> Self {
         RemoveFile {
             command: cmd,
-            current_dir: path,
+            sandbox,
         }
     }
     fn try_remove_file(&self) -> (String, bool) {
@@ -19,8 +20,17 @@ impl RemoveFile {
         let mut succes = true;
         for cmd in &self.command.cmd[1..] {
             let mut line_output = String::new();
-            let new_path = self.current_dir.join(cmd);
-            if !new_path.exists() {
+            let Some(new_path) = self.sandbox.resolve_entry(cmd) else {
+                succes = false;
+                line_output = format!("rm: failed to remove '{}': {}", cmd, PERMISSION_DENIED);
+                output = if output.is_empty() {
+                    line_output
+                } else {
+                    format!("{}\n{}", output, line_output)
+                };
+                continue;
+            };
+            if new_path.symlink_metadata().is_err() {
                 succes = false;
                 line_output = format!("rm: failed to remove '{}': No such file or directory", cmd);
             } else if new_path.is_dir() {
@@ -45,7 +55,7 @@ impl RemoveFile {
                 format!(
                     "{}{}{}",
                     get_format(Format::Normal),
-                    output,
+                    escape_text(&output),
                     get_format(Format::Split)
                 ),
                 succes,
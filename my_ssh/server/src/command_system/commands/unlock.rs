@@ -0,0 +1,74 @@
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::login_guard::{LoginGuard, Target};
+use std::sync::Arc;
+
+/// The `unlock` administration command.
+///
+/// Without arguments it lists the users and addresses locked out by the
+/// `LoginGuard`; with arguments it clears the failed logins of each user name or
+/// IP address given.
+///
+/// # Output
+///
+/// ```text
+/// user alice: locked for 842 s
+/// address 203.0.113.7: banned
+/// ```
+pub struct Unlock {
+    command: Command,
+    guard: Option<Arc<LoginGuard>>,
+}
+
+impl Unlock {
+    pub fn new(cmd: Command, guard: Option<Arc<LoginGuard>>) -> Self {
+        Unlock {
+            command: cmd,
+            guard,
+        }
+    }
+    pub async fn get_output(&self) -> (String, bool) {
+        let Some(guard) = &self.guard else {
+            return (
+                format!(
+                    "{}Error , unlock: failed logins are not tracked{}",
+                    get_format(Format::Error),
+                    get_format(Format::Split)
+                ),
+                false,
+            );
+        };
+        let mut lines = Vec::new();
+        let mut succes = true;
+        if self.command.cmd.len() == 1 {
+            for (target, left) in guard.lockouts() {
+                lines.push(match left {
+                    Some(left) => format!("{}: locked for {} s", target, left.as_secs() + 1),
+                    None => format!("{}: banned", target),
+                });
+            }
+            if lines.is_empty() {
+                lines.push("No lockouts".to_string());
+            }
+        } else {
+            for name in &self.command.cmd[1..] {
+                let target = Target::parse(name);
+                if guard.unlock(&target).await {
+                    println!("{} unlocked", target);
+                    lines.push(format!("{} unlocked", target));
+                } else {
+                    succes = false;
+                    lines.push(format!("unlock: no failed logins for {}", target));
+                }
+            }
+        }
+        (
+            format!(
+                "{}{}{}",
+                get_format(Format::Normal),
+                escape_text(&lines.join("\n")),
+                get_format(Format::Split)
+            ),
+            succes,
+        )
+    }
+}
@@ -1,40 +1,92 @@
-use crate::command_system::common::{Command, Format, get_format};
+use crate::audit::format_time;
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::session_registry::{SessionInfo, SessionRegistry};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::SystemTime;
 
+/// The `users` and `who` commands.
+///
+/// `users` prints the user of every logged in session, one per line; `who` prints
+/// one row per session with its details:
+///
+/// ```text
+/// ID  USER   FROM             LOGIN                 IDLE  DIRECTORY  CLIENT
+/// 1   alice  127.0.0.1:52814  2026-10-17T07:12:09Z  0 s   /alice     client/0.1.0
+/// 2   bob    127.0.0.1:52820  2026-10-17T07:13:40Z  42 s  /bob/docs  -
+/// ```
 pub struct ListUsers {
     command: Command,
-    users_list: Arc<RwLock<Vec<String>>>,
+    sessions: Arc<SessionRegistry>,
 }
 
 impl ListUsers {
-    pub fn new(cmd: Command, users: Arc<RwLock<Vec<String>>>) -> Self {
+    pub fn new(cmd: Command, sessions: Arc<SessionRegistry>) -> Self {
         ListUsers {
             command: cmd,
-            users_list: users,
+            sessions,
         }
     }
     pub async fn get_output(&self) -> (String, bool) {
         let mut output = String::new();
         let mut status = false;
         if self.command.cmd.len() == 1 {
-            let users_lock = self.users_list.read().await;
+            let sessions = self.sessions.sessions();
             status = true;
-            for user in users_lock.iter() {
-                if output.is_empty() {
-                    output = user.clone();
-                } else {
-                    output = format!("{}\n{}", output, user);
-                }
-            }
+            output = if self.command.cmd[0] == "who" {
+                ListUsers::table(&sessions)
+            } else {
+                sessions
+                    .iter()
+                    .map(|session| session.user.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
             output = format!(
                 "{}{}{}",
                 get_format(Format::Normal),
-                output,
+                escape_text(&output),
                 get_format(Format::Split)
             );
         }
 
         (output, status)
     }
+    /// Lays out the columns of `who`, each as wide as its longest cell.
+    fn table(sessions: &[SessionInfo]) -> String {
+        let now = SystemTime::now();
+        let mut rows =
+            vec![["ID", "USER", "FROM", "LOGIN", "IDLE", "DIRECTORY", "CLIENT"].map(String::from)];
+        for session in sessions {
+            let idle = now
+                .duration_since(session.last_activity)
+                .unwrap_or_default()
+                .as_secs();
+            rows.push([
+                session.id.to_string(),
+                session.user.clone(),
+                session.peer.to_string(),
+                format_time(session.login_time),
+                format!("{} s", idle),
+                format!("/{}", session.directory),
+                session.client_version.clone().unwrap_or("-".to_string()),
+            ]);
+        }
+        let mut widths = [0; 7];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        rows.iter()
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(widths)
+                    .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                    .collect();
+                cells.join("  ").trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
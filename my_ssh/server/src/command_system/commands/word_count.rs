@@ -1,16 +1,19 @@
-use crate::command_system::common::{Command, Format, get_format, get_unformated_text};
+use crate::command_system::common::{
+    Command, Format, escape_text, get_format, get_unformated_text,
+};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 
 pub struct WordCount {
     command: Command,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
     input: Option<String>,
 }
 
 impl WordCount {
-    pub fn new(cmd: Command, input: Option<String>, path: std::path::PathBuf) -> Self {
+    pub fn new(cmd: Command, input: Option<String>, sandbox: PathSandbox) -> Self {
         WordCount {
             command: cmd,
-            current_dir: path,
+            sandbox,
             input,
         }
     }
@@ -40,7 +43,14 @@ impl WordCount {
         if self.command.cmd.len() != 1 {
             let mut output: String = String::new();
             for cmd in &self.command.cmd[1..] {
-                let file_path = self.current_dir.join(cmd);
+                let Some(file_path) = self.sandbox.resolve(cmd) else {
+                    if !output.is_empty() {
+                        output = format!("{}\nwc: {}  ({})", output, PERMISSION_DENIED, cmd);
+                    } else {
+                        output = format!("wc: {}  ({})", PERMISSION_DENIED, cmd);
+                    }
+                    continue;
+                };
                 if !file_path.exists() {
                     if !output.is_empty() {
                         output =
@@ -65,7 +75,7 @@ impl WordCount {
                 format!(
                     "{}{}{}",
                     get_format(Format::Normal),
-                    output,
+                    escape_text(&output),
                     get_format(Format::Split)
                 ),
                 status,
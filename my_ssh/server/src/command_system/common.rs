@@ -1,3 +1,4 @@
+pub use protocol::format::{Format, escape_text, get_format, get_unformated_text};
 use shell_words::split;
 use std::path::PathBuf;
 
@@ -35,9 +36,10 @@ pub struct Command {
 
 /// Retrieves all entries (files and directories) from the specified directory path.
 /// 
-/// This function reads the contents of a directory and returns the canonical
-/// (absolute) paths of all entries. It handles both files and subdirectories,
-/// resolving symbolic links to their actual targets.
+/// This function reads the contents of a directory and returns the absolute paths
+/// of all entries. It handles both files and subdirectories; symbolic links are
+/// listed under their own name and not followed, so a link leading outside the
+/// directory cannot change where the entry appears.
 /// 
 /// # Parameters
 /// 
@@ -45,8 +47,8 @@ pub struct Command {
 /// 
 /// # Returns
 /// 
-/// - `Ok(Vec<PathBuf>)`: Vector of canonical paths for all directory entries
-/// - `Err(std::io::Error)`: I/O error if the directory cannot be read or paths cannot be canonicalized
+/// - `Ok(Vec<PathBuf>)`: Vector of absolute paths for all directory entries
+/// - `Err(std::io::Error)`: I/O error if the directory cannot be read or canonicalized
 /// 
 /// # Errors
 /// 
@@ -54,7 +56,6 @@ pub struct Command {
 /// - The specified path does not exist
 /// - The path is not a directory
 /// - Permission is denied to read the directory
-/// - A file path cannot be canonicalized (e.g., broken symbolic link)
 /// - I/O errors occur during directory traversal
 /// 
 /// # Examples
@@ -80,16 +81,15 @@ pub struct Command {
 /// 
 /// # Note
 /// 
-/// The returned paths are canonicalized, meaning they are absolute paths
-/// with all symbolic links resolved and `.` and `..` components removed.
+/// The returned paths are the canonical path of the directory followed by the
+/// name of each entry, so they never contain `.` or `..` components.
 pub fn get_files(path: &std::path::Path) -> Result<Vec<PathBuf>, std::io::Error> {
-    let dir = std::fs::read_dir(path)?;
+    let path = std::fs::canonicalize(path)?;
+    let dir = std::fs::read_dir(&path)?;
     let mut files: Vec<PathBuf> = Vec::new();
     for entry_res in dir {
         let entry = entry_res?;
-        let file_path = entry.path();
-        let file_path = std::fs::canonicalize(&file_path)?;
-        files.push(file_path.clone());
+        files.push(path.join(entry.file_name()));
     }
     Ok(files)
 }
@@ -171,212 +171,6 @@ pub fn get_commands(client_input: String) -> Vec<Command> {
 
     cmds
 }
-/// Enumeration of text formatting types used in the shell output system.
-/// 
-/// `Format` defines various formatting options for shell output, including
-/// error messages, directory listings, colors, and special markers used
-/// by the shell's display system.
-/// 
-/// # Variants
-/// 
-/// - `Error`: Error message formatting
-/// - `ListDir`: Directory listing formatting
-/// - `Color(&'static str)`: Specific color formatting with color name
-/// - `Split`: Line separator/split formatting
-/// - `Normal`: Normal text formatting
-/// - `NormalColored`: Normal colored text formatting
-/// 
-/// # Examples
-/// 
-/// ```rust
-/// // Error formatting
-/// let error_format = get_format(Format::Error);
-/// 
-/// // Color formatting
-/// let blue_format = get_format(Format::Color("BLUE"));
-/// let red_format = get_format(Format::Color("LIGHT_RED"));
-/// 
-/// // Normal formatting
-/// let normal_format = get_format(Format::Normal);
-/// ```
-pub enum Format {
-    Error,
-    ListDir,
-    Color(&'static str),
-    Split,
-    Normal,
-    NormalColored,
-}
-/// Returns the formatting string for the specified format type.
-/// 
-/// This function maps `Format` enum variants to their corresponding
-/// formatting control strings used by the shell's display system.
-/// 
-/// # Parameters
-/// 
-/// - `format`: The format type to get the control string for
-/// 
-/// # Returns
-/// 
-/// A static string slice containing the formatting control sequence.
-/// 
-/// # Format Mappings
-/// 
-/// | Format | Control String | Purpose |
-/// |--------|---------------|---------|
-/// | `Error` | `"?&E"` | Error message prefix |
-/// | `ListDir` | `"?&L"` | Directory listing prefix |
-/// | `NormalColored` | `"?&C"` | Colored text prefix |
-/// | `Normal` | `"?&N"` | Normal text prefix |
-/// | `Color("BLUE")` | `"^!"` | Blue color marker |
-/// | `Color("LIGHT_RED")` | `"^@"` | Light red color marker |
-/// | `Color("GREEN")` | `"^#"` | Green color marker |
-/// | `Color("stop")` | `"~~"` | Color stop marker |
-/// | `Split` | `"\n\n"` | Line separator |
-/// | *other* | `""` | Empty string for unknown formats |
-/// 
-/// # Examples
-/// 
-/// ```rust
-/// // Format an error message
-/// let error_msg = format!("{}File not found{}", 
-///     get_format(Format::Error),
-///     get_format(Format::Split)
-/// );
-/// 
-/// // Format colored text
-/// let colored_text = format!("{}Important{}{}",
-///     get_format(Format::Color("LIGHT_RED")),
-///     get_format(Format::Color("stop")),
-///     get_format(Format::Split)
-/// );
-/// 
-/// // Format normal output
-/// let normal_output = format!("{}Command completed{}",
-///     get_format(Format::Normal),
-///     get_format(Format::Split)
-/// );
-/// ```
-pub fn get_format(format: Format) -> &'static str {
-    match format {
-        Format::Error => "?&E",
-        Format::ListDir => "?&L",
-        Format::NormalColored => "?&C",
-        Format::Normal => "?&N",
-        Format::Color("BLUE") => "^!",
-        Format::Color("LIGHT_RED") => "^@",
-        Format::Color("GREEN") => "^#",
-        Format::Color("stop") => "~~",
-        Format::Split => "\n\n",
-        _ => "",
-    }
-}
-/// Removes formatting control sequences from formatted text.
-/// 
-/// This function processes text that contains shell formatting control sequences
-/// and returns a clean, unformatted version suitable for plain text output
-/// 
-/// # Parameters
-/// 
-/// - `text`: The formatted text string containing control sequences
-/// 
-/// # Returns
-/// 
-/// A clean `String` with all formatting control sequences removed.
-/// 
-/// # Processing Rules
-/// 
-/// 1. **Section Parsing**: Splits text on `?&` markers to identify formatted sections
-/// 2. **Format Type Detection**: Identifies the format type from the first character after `?&`
-/// 3. **Color Sequence Removal**: Removes color markers like `^@`, `~~` from colored text
-/// 4. **Prefix Removal**: Strips formatting prefixes from regular sections
-/// 5. **Text Reconstruction**: Rebuilds clean text with proper spacing
-/// 
-/// # Supported Control Sequences
-/// 
-/// - `?&E`, `?&L`, `?&N`, `?&C`: Format type prefixes
-/// - `^@`, `^!`, `^#`: Color start markers
-/// - `~~`: Color stop marker
-/// - `\n\n`: Section separators
-/// 
-/// # Examples
-/// 
-/// ## Basic Formatting Removal
-/// ```rust
-/// let formatted = "?&NHello World\n\n";
-/// let clean = get_unformated_text(formatted);
-/// assert_eq!(clean, "Hello World");
-/// ```
-/// 
-/// ## Color Sequence Removal
-/// ```rust
-/// let colored = "?&C^@Important~~Text\n\n";
-/// let clean = get_unformated_text(colored);
-/// assert_eq!(clean, "ImportantText");
-/// ```
-/// 
-/// ## Complex Formatting
-/// ```rust
-/// let complex = "?&EError: ?&C^@File not found~~\n\n?&NOperation failed\n\n";
-/// let clean = get_unformated_text(complex);
-/// assert_eq!(clean, "Error:  File not found Operation failed");
-/// ```
-/// 
-/// ## Multiple Sections
-/// ```rust
-/// let multi = "?&NStatus: ?&C^#OK~~\n\n?&NFiles: 42\n\n";
-/// let clean = get_unformated_text(multi);
-/// assert_eq!(clean, "Status:  OK Files: 42");
-/// ```
-pub fn get_unformated_text(text: &str) -> String {
-    let mut new_text = String::new();
-    let props: Vec<&str> = text.split("?&").filter(|f| !f.is_empty()).collect();
-    for w in props {
-        let word: Vec<&str> = w[1..].split("\n\n").filter(|f| !f.is_empty()).collect();
-        match w.chars().next() {
-            Some('C') => {
-                for e in word {
-                    let chars: Vec<_> = e.chars().collect();
-                    let mut i = 0;
-                    while i < chars.len() {
-                        let c = chars[i];
-                        if i + 1 < chars.len() && c == '^' && chars[i + 1] == '@' {
-                            i += 2;
-                            continue;
-                        }
-                        if i + 1 < chars.len() && c == '~' && chars[i + 1] == '~' {
-                            i += 2;
-                            continue;
-                        }
-
-                        new_text = format!("{}{}", new_text, c);
-
-                        i += 1;
-                    }
-                }
-            }
-            Some(_) => {
-                for e in word {
-                    if e.starts_with('^') {
-                        let tail: String = e.chars().skip(2).collect();
-                        if new_text.is_empty() {
-                            new_text = tail;
-                        } else {
-                            new_text = format!("{} {}", new_text, tail);
-                        }
-                    } else if new_text.is_empty() {
-                        new_text = e.to_string();
-                    } else {
-                        new_text = format!("{} {}", new_text, e);
-                    }
-                }
-            }
-            None => (),
-        }
-    }
-    new_text
-}
-
 #[cfg(unix)]
 pub fn is_executable(path: std::path::PathBuf) -> std::io::Result<bool> {
     use std::fs;
@@ -0,0 +1,71 @@
+use crate::command_system::command_runner::COMMAND_NAMES;
+use crate::command_system::sandbox::PathSandbox;
+use protocol::complete::{CompletionKind, CompletionRequest, Completions};
+use std::path::PathBuf;
+
+/// Answers the tab completion requests of a session.
+///
+/// Command names are taken from `COMMAND_NAMES`; paths are resolved by the same
+/// `PathSandbox` as the commands, so completion cannot reveal names the commands
+/// themselves could not reach.
+///
+/// # Examples
+///
+/// ```rust
+/// let completion = Completion::new(root_path, server_path);
+/// let request = CompletionRequest::new(CompletionKind::Path, "docs/no");
+/// let completions = completion.get_completions(&request);
+/// // completions.candidates == ["docs/notes.txt", "docs/novels/"]
+/// ```
+pub struct Completion {
+    root: PathBuf,
+    current_dir: PathBuf,
+}
+
+impl Completion {
+    pub fn new(root: PathBuf, current_dir: PathBuf) -> Self {
+        Completion { root, current_dir }
+    }
+    /// Returns the sorted candidates that can replace the prefix of `request`.
+    pub fn get_completions(&self, request: &CompletionRequest) -> Completions {
+        let mut candidates = match request.kind {
+            CompletionKind::Command => COMMAND_NAMES
+                .iter()
+                .filter(|name| name.starts_with(&request.prefix))
+                .map(|name| name.to_string())
+                .collect(),
+            CompletionKind::Path => self.get_paths(&request.prefix),
+        };
+        candidates.sort();
+        Completions::new(candidates)
+    }
+    /// Lists the entries of the directory part of `prefix` that start with its last
+    /// component. Hidden entries are only listed when the component starts with `.`.
+    fn get_paths(&self, prefix: &str) -> Vec<String> {
+        let (dir, name) = match prefix.rfind('/') {
+            Some(index) => prefix.split_at(index + 1),
+            None => ("", prefix),
+        };
+        let sandbox = PathSandbox::new(self.root.clone(), self.current_dir.clone());
+        let Some(dir_path) = sandbox.resolve(dir) else {
+            return Vec::new();
+        };
+        let entries = match std::fs::read_dir(&dir_path) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_str()?.to_string();
+                if !file_name.starts_with(name)
+                    || (file_name.starts_with('.') && !name.starts_with('.'))
+                {
+                    return None;
+                }
+                let suffix = if entry.path().is_dir() { "/" } else { "" };
+                Some(format!("{}{}{}", dir, file_name, suffix))
+            })
+            .collect()
+    }
+}
@@ -2,4 +2,7 @@ pub mod command_handler;
 pub mod command_runner;
 pub mod commands;
 pub mod common;
+pub mod completion;
 pub mod operation_handler;
+pub mod permissions;
+pub mod sandbox;
@@ -1,4 +1,7 @@
-use crate::command_system::common::{Command, get_unformated_text};
+use crate::command_system::common::{
+    Command, Format, escape_text, get_format, get_unformated_text,
+};
+use crate::command_system::sandbox::{PERMISSION_DENIED, PathSandbox};
 use std::io::Write;
 
 /// Enumeration of shell operation types for command chaining and flow control.
@@ -105,7 +108,7 @@ pub struct OperationHandler {
     output: String,
     last_output: String,
     commands: Vec<Command>,
-    current_dir: std::path::PathBuf,
+    sandbox: PathSandbox,
     last_succes: bool,
 }
 impl OperationHandler {
@@ -116,7 +119,7 @@ impl OperationHandler {
     /// - `output`: Current command output to be processed
     /// - `last_output`: Accumulated output from previous commands in the pipeline
     /// - `command`: Slice of commands in the current pipeline segment
-    /// - `path`: Current working directory for file operations
+    /// - `sandbox`: Resolves the files of redirections from the current directory
     /// - `last_succes`: Success status of the last executed command
     /// 
     /// # Returns
@@ -136,7 +139,7 @@ impl OperationHandler {
     ///     "file1.txt\nfile2.txt".to_string(),
     ///     "".to_string(),
     ///     &commands,
-    ///     PathBuf::from("/home/user"),
+    ///     PathSandbox::new(PathBuf::from("/home"), PathBuf::from("/home/user")),
     ///     true
     /// );
     /// ```
@@ -144,14 +147,14 @@ impl OperationHandler {
         output: String,
         last_output: String,
         command: &[Command],
-        path: std::path::PathBuf,
+        sandbox: PathSandbox,
         last_succes: bool,
     ) -> Self {
         OperationHandler {
             output,
             last_output,
             commands: command.to_owned(),
-            current_dir: path,
+            sandbox,
             last_succes,
         }
     }
@@ -173,7 +176,8 @@ impl OperationHandler {
     /// 
     /// # Behavior
     /// 
-    /// 1. **Path Resolution**: Creates full file path relative to current directory
+    /// 1. **Path Resolution**: Resolves the file path through the `PathSandbox`, so it
+    ///    cannot leave the root of the session
     /// 2. **Directory Creation**: Creates parent directories if they don't exist
     /// 3. **File Creation**: Creates new file or truncates existing file
     /// 4. **Content Processing**: Removes formatting with `get_unformated_text()`
@@ -189,16 +193,13 @@ impl OperationHandler {
     /// 
     /// # Error Conditions
     /// 
-    /// - **Permission Denied**: Insufficient permissions to create file or directories
+    /// - **Permission Denied**: Insufficient permissions to create file or directories,
+    ///   or the path leads outside the root of the session
     /// - **Disk Full**: No space available for file creation
     /// - **Invalid Path**: Invalid characters in filename or path
     /// - **I/O Errors**: Hardware or filesystem errors during write operation
-    fn write_in_file(
-        &self,
-        content: &str,
-        name: &String,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let file_path = self.current_dir.join(name);
+    fn write_in_file(&self, content: &str, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file_path = self.sandbox.resolve(name).ok_or(PERMISSION_DENIED)?;
         if !file_path.parent().unwrap().exists() {
             std::fs::create_dir_all(file_path.parent().unwrap())?;
         }
@@ -273,7 +274,7 @@ impl OperationHandler {
     ///     "/home/user".to_string(),
     ///     "".to_string(),
     ///     &[Command { cmd: vec!["pwd".to_string()], op: Some(";".to_string()) }],
-    ///     current_dir,
+    ///     sandbox.clone(),
     ///     true
     /// );
     /// 
@@ -294,7 +295,7 @@ impl OperationHandler {
     ///         Command { cmd: vec!["ls".to_string()], op: Some(">".to_string()) },
     ///         Command { cmd: vec!["files.txt".to_string()], op: None }
     ///     ],
-    ///     current_dir,
+    ///     sandbox.clone(),
     ///     true
     /// );
     /// 
@@ -312,7 +313,7 @@ impl OperationHandler {
     ///     "".to_string(), // mkdir output
     ///     "".to_string(),
     ///     &[Command { cmd: vec!["mkdir".to_string()], op: Some("&&".to_string()) }],
-    ///     current_dir,
+    ///     sandbox.clone(),
     ///     true // mkdir succeeded
     /// );
     /// 
@@ -330,7 +331,7 @@ impl OperationHandler {
     ///     "Permission denied".to_string(),
     ///     "".to_string(),
     ///     &[Command { cmd: vec!["mkdir".to_string()], op: Some("&&".to_string()) }],
-    ///     current_dir,
+    ///     sandbox.clone(),
     ///     false // mkdir failed
     /// );
     /// 
@@ -353,13 +354,34 @@ impl OperationHandler {
                 ("".to_string(), output, false, true)
             }
             Operation::OutputRedirection => {
-                if self
-                    .write_in_file(&self.output, &self.commands[1].cmd[0])
-                    .is_ok()
-                {
-                    return ("".to_string(), "".to_string(), true, true);
+                // reported as the final output, as no command may follow to flush it
+                let Some(name) = self.commands.get(1).and_then(|c| c.cmd.first()) else {
+                    return (
+                        "".to_string(),
+                        format!(
+                            "{}missing file operand{}",
+                            get_format(Format::Error),
+                            get_format(Format::Split)
+                        ),
+                        true,
+                        false,
+                    );
+                };
+                match self.write_in_file(&self.output, name) {
+                    Ok(()) => ("".to_string(), "".to_string(), true, true),
+                    Err(e) => (
+                        format!(
+                            "{}{}: {}{}",
+                            get_format(Format::Error),
+                            escape_text(name),
+                            escape_text(&e.to_string()),
+                            get_format(Format::Split)
+                        ),
+                        "".to_string(),
+                        true,
+                        false,
+                    ),
                 }
-                ("Error".to_string(), "".to_string(), true, false)
             }
             Operation::AndLogic => {
                 let mut jump = false;
@@ -0,0 +1,387 @@
+use crate::command_system::command_runner::COMMAND_NAMES;
+use crate::command_system::common::{Command, Format, escape_text, get_format};
+use crate::user_store::{Account, UserStore};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Role of the accounts that do not name one.
+pub const DEFAULT_ROLE: &str = "user";
+
+/// Commands of the built-in `read-only` role: everything that does not change files.
+const READ_ONLY_COMMANDS: [&str; 10] = [
+    "cat", "cd", "echo", "grep", "ls", "next", "pwd", "users", "wc", "who",
+];
+
+/// Commands that administer the server rather than files, only allowed for the roles
+/// with `admin = true`.
+pub const ADMIN_COMMANDS: [&str; 2] = ["connections", "unlock"];
+
+/// What the users of one role may do.
+///
+/// # Fields
+///
+/// - `commands`: Built-in commands the role may run; all of them when missing
+/// - `redirect`: The role may write files with the `>` output redirection
+/// - `execute`: The role may run executable files (`./program`)
+/// - `admin`: The role may run the `ADMIN_COMMANDS`, even if `commands` is missing
+///
+/// # Examples
+///
+/// ```toml
+/// [roles.operator]
+/// commands = ["ls", "cat", "cd", "pwd", "mkdir", "mv"]
+/// redirect = true
+/// execute = false
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RolePolicy {
+    pub commands: Option<Vec<String>>,
+    #[serde(default)]
+    pub redirect: bool,
+    #[serde(default)]
+    pub execute: bool,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// The roles known to the server and what each of them allows.
+///
+/// Three roles are built in and can be redefined by the `[roles]` table of the
+/// configuration file, which can also add new ones:
+///
+/// | Role | Commands | `>` | `./program` | `unlock`, `connections` |
+/// |------|----------|-----|-------------|----------|
+/// | `admin` | all | yes | yes | yes |
+/// | `user` | all | yes | no | no |
+/// | `read-only` | `cat`, `cd`, `echo`, `grep`, `ls`, `next`, `pwd`, `users`, `wc`, `who` | no | no | no |
+///
+/// Accounts without a `role` get `DEFAULT_ROLE`.
+///
+/// # Examples
+///
+/// ```rust
+/// let policy = Policy::new(config.roles)?;
+/// let permissions = policy.permissions(&account).ok_or("unknown role")?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Policy {
+    roles: HashMap<String, RolePolicy>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        let all = RolePolicy {
+            commands: None,
+            redirect: true,
+            execute: true,
+            admin: true,
+        };
+        let user = RolePolicy {
+            execute: false,
+            admin: false,
+            ..all.clone()
+        };
+        let read_only = RolePolicy {
+            commands: Some(READ_ONLY_COMMANDS.iter().map(|c| c.to_string()).collect()),
+            redirect: false,
+            execute: false,
+            admin: false,
+        };
+        Policy {
+            roles: HashMap::from([
+                ("admin".to_string(), all),
+                ("user".to_string(), user),
+                ("read-only".to_string(), read_only),
+            ]),
+        }
+    }
+}
+
+impl Policy {
+    /// Builds the policy from the built-in roles and the configured ones, which
+    /// replace the built-in roles of the same name.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a configured role lists a command the server does not have.
+    pub fn new(configured: HashMap<String, RolePolicy>) -> Result<Policy, Box<dyn Error>> {
+        let mut policy = Policy::default();
+        for (name, role) in configured {
+            if let Some(command) = role
+                .commands
+                .iter()
+                .flatten()
+                .find(|command| !COMMAND_NAMES.contains(&command.as_str()))
+            {
+                return Err(format!("unknown command {} in role {}", command, name).into());
+            }
+            policy.roles.insert(name, role);
+        }
+        Ok(policy)
+    }
+    /// Checks that the role of every account of `store` is defined.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the first account whose role is unknown.
+    pub fn check_accounts(&self, store: &UserStore) -> Result<(), Box<dyn Error>> {
+        for (name, account) in store.accounts() {
+            if self.permissions(account).is_none() {
+                return Err(format!(
+                    "unknown role {} of user {}",
+                    account.role.as_deref().unwrap_or(DEFAULT_ROLE),
+                    name
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+    /// Returns the permissions of `account`, or `None` if its role is not defined.
+    ///
+    /// The `allowed_commands` of the account further restrict the commands of its
+    /// role; they never grant more.
+    pub fn permissions(&self, account: &Account) -> Option<Permissions> {
+        let role = account.role.as_deref().unwrap_or(DEFAULT_ROLE);
+        let policy = self.roles.get(role)?;
+        Some(Permissions {
+            role: role.to_string(),
+            role_commands: policy.commands.clone(),
+            user_commands: account.allowed_commands.clone(),
+            redirect: policy.redirect,
+            execute: policy.execute,
+            admin: policy.admin,
+        })
+    }
+}
+
+/// What the user of a session may run, checked before every command.
+///
+/// The default value allows everything and is only meant for callers that do not
+/// run commands on behalf of a user.
+///
+/// # Permission Denied Format
+///
+/// ```text
+/// ?&EError , Permission denied: rm is not allowed for the read-only role[-]
+/// ?&EError , Permission denied: rm is not allowed for this user[-]
+/// ?&EError , Permission denied: output redirection is not allowed for the read-only role[-]
+/// ?&EError , Permission denied: executables are not allowed for the user role[-]
+/// ?&EError , Permission denied: unlock is not allowed for the user role[-]
+/// ```
+#[derive(Clone, Debug)]
+pub struct Permissions {
+    role: String,
+    role_commands: Option<Vec<String>>,
+    user_commands: Option<Vec<String>>,
+    redirect: bool,
+    execute: bool,
+    admin: bool,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Permissions {
+            role: "admin".to_string(),
+            role_commands: None,
+            user_commands: None,
+            redirect: true,
+            execute: true,
+            admin: true,
+        }
+    }
+}
+
+impl Permissions {
+    /// Checks that `command` may be run, with its operator.
+    ///
+    /// Unknown command names are let through, so they still fail with the usual
+    /// `Command not found` error.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: The command is allowed
+    /// - `Err(String)`: The formatted permission-denied output
+    pub fn check(&self, command: &Command) -> Result<(), String> {
+        let Some(name) = command.cmd.first() else {
+            return Ok(());
+        };
+        if command.op.as_deref() == Some(">") && !self.redirect {
+            return Err(self.denied("output redirection is", true));
+        }
+        if name.starts_with("./") {
+            if !self.execute {
+                return Err(self.denied("executables are", true));
+            }
+        } else if ADMIN_COMMANDS.contains(&name.as_str()) {
+            if !self.admin {
+                return Err(self.denied(&format!("{} is", name), true));
+            }
+            if !allows(&self.user_commands, name) {
+                return Err(self.denied(&format!("{} is", name), false));
+            }
+        } else if COMMAND_NAMES.contains(&name.as_str()) {
+            if !allows(&self.role_commands, name) {
+                return Err(self.denied(&format!("{} is", name), true));
+            }
+            if !allows(&self.user_commands, name) {
+                return Err(self.denied(&format!("{} is", name), false));
+            }
+        }
+        Ok(())
+    }
+    /// Formats the permission-denied error for `what` (e.g. `rm is`), refused by
+    /// the role or by the `allowed_commands` of the account.
+    fn denied(&self, what: &str, by_role: bool) -> String {
+        let target = if by_role {
+            format!("the {} role", self.role)
+        } else {
+            "this user".to_string()
+        };
+        format!(
+            "{}Error , Permission denied: {} not allowed for {}{}",
+            get_format(Format::Error),
+            escape_text(what),
+            escape_text(&target),
+            get_format(Format::Split)
+        )
+    }
+}
+
+/// Returns true if `name` is in `commands`, or if there is no list at all.
+fn allows(commands: &Option<Vec<String>>, name: &str) -> bool {
+    commands
+        .as_ref()
+        .is_none_or(|commands| commands.iter().any(|command| command == name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: &str, op: Option<&str>) -> Command {
+        Command {
+            cmd: line.split_whitespace().map(str::to_string).collect(),
+            op: op.map(str::to_string),
+        }
+    }
+
+    fn permissions(policy: &Policy, role: Option<&str>, allowed: Option<&[&str]>) -> Permissions {
+        let account = Account {
+            role: role.map(str::to_string),
+            allowed_commands: allowed
+                .map(|commands| commands.iter().map(|c| c.to_string()).collect()),
+            ..Account::default()
+        };
+        policy.permissions(&account).unwrap()
+    }
+
+    #[test]
+    fn roles_restrict_commands() {
+        let policy = Policy::default();
+        let admin = permissions(&policy, Some("admin"), None);
+        let user = permissions(&policy, None, None);
+        let read_only = permissions(&policy, Some("read-only"), None);
+        for name in READ_ONLY_COMMANDS {
+            assert!(read_only.check(&command(name, None)).is_ok(), "{}", name);
+        }
+        let error = read_only.check(&command("rm notes.txt", None)).unwrap_err();
+        assert!(
+            error.contains("rm is not allowed for the read-only role"),
+            "{}",
+            error
+        );
+        assert!(user.check(&command("rm notes.txt", None)).is_ok());
+        for name in ADMIN_COMMANDS {
+            assert!(admin.check(&command(name, None)).is_ok(), "{}", name);
+            let error = user.check(&command(name, None)).unwrap_err();
+            assert!(error.contains("not allowed for the user role"), "{}", error);
+        }
+        assert!(read_only.check(&command("nosuchcommand", None)).is_ok());
+        assert!(read_only.check(&command("", None)).is_ok());
+    }
+
+    #[test]
+    fn accounts_restrict_their_role() {
+        let policy = Policy::default();
+        let user = permissions(&policy, None, Some(&["ls", "cat", "unlock"]));
+        assert!(user.check(&command("ls", None)).is_ok());
+        let error = user.check(&command("rm notes.txt", None)).unwrap_err();
+        assert!(
+            error.contains("rm is not allowed for this user"),
+            "{}",
+            error
+        );
+        let error = user.check(&command("unlock", None)).unwrap_err();
+        assert!(error.contains("for the user role"), "{}", error);
+        let admin = permissions(&policy, Some("admin"), Some(&["ls"]));
+        let error = admin.check(&command("connections", None)).unwrap_err();
+        assert!(error.contains("for this user"), "{}", error);
+        let read_only = permissions(&policy, Some("read-only"), Some(&["ls", "rm"]));
+        assert!(read_only.check(&command("rm notes.txt", None)).is_err());
+    }
+
+    #[test]
+    fn redirection_and_executables_follow_the_role() {
+        let policy = Policy::default();
+        let admin = permissions(&policy, Some("admin"), None);
+        let user = permissions(&policy, None, None);
+        let read_only = permissions(&policy, Some("read-only"), None);
+        assert!(user.check(&command("ls", Some(">"))).is_ok());
+        assert!(read_only.check(&command("ls", Some("&&"))).is_ok());
+        let error = read_only.check(&command("ls", Some(">"))).unwrap_err();
+        assert!(
+            error.contains("output redirection is not allowed for the read-only role"),
+            "{}",
+            error
+        );
+        assert!(admin.check(&command("./program", None)).is_ok());
+        let error = user.check(&command("./program", None)).unwrap_err();
+        assert!(
+            error.contains("executables are not allowed for the user role"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn configured_roles_are_validated() {
+        let operator = RolePolicy {
+            commands: Some(vec!["ls".to_string(), "cat".to_string()]),
+            ..RolePolicy::default()
+        };
+        let policy =
+            Policy::new(HashMap::from([("operator".to_string(), operator.clone())])).unwrap();
+        let permissions = permissions(&policy, Some("operator"), None);
+        assert!(permissions.check(&command("cat notes.txt", None)).is_ok());
+        assert!(permissions.check(&command("rm notes.txt", None)).is_err());
+        assert!(permissions.check(&command("ls", Some(">"))).is_err());
+        let unknown = RolePolicy {
+            commands: Some(vec!["ls".to_string(), "sudo".to_string()]),
+            ..operator
+        };
+        let error = Policy::new(HashMap::from([("operator".to_string(), unknown)])).unwrap_err();
+        assert_eq!(error.to_string(), "unknown command sudo in role operator");
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        let policy = Policy::default();
+        let account = Account {
+            role: Some("operator".to_string()),
+            ..Account::default()
+        };
+        assert!(policy.permissions(&account).is_none());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.toml");
+        std::fs::write(&path, "[users.alice]\n\n[users.bob]\nrole = \"operator\"\n").unwrap();
+        let store = UserStore::load(&path).unwrap();
+        let error = policy.check_accounts(&store).unwrap_err();
+        assert_eq!(error.to_string(), "unknown role operator of user bob");
+        std::fs::write(&path, "[users.alice]\nrole = \"read-only\"\n").unwrap();
+        let store = UserStore::load(&path).unwrap();
+        assert!(policy.check_accounts(&store).is_ok());
+    }
+}
@@ -0,0 +1,314 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Reason given when a path resolves outside the root of the session.
+pub const PERMISSION_DENIED: &str = "Permission denied";
+
+/// Resolves the paths given to commands and keeps them inside the root of a session.
+///
+/// Every command, the `<` and `>` redirections and tab completion go through
+/// `PathSandbox` instead of joining their arguments to the current directory, so a
+/// path can only reach what the session is allowed to see:
+///
+/// - Relative paths start at the current directory
+/// - Absolute paths start at the root of the session, which is shown as `/`
+/// - `.` and `..` are applied before the file system is touched, and `..` cannot go
+///   above the root
+/// - Symbolic links are resolved, and a link that leads outside the root, or does not
+///   lead anywhere, is refused
+///
+/// The root is the server root, or the home of a `confined` user.
+///
+/// # Examples
+///
+/// ```rust
+/// let sandbox = PathSandbox::new(PathBuf::from("/srv/shell"), PathBuf::from("/srv/shell/alice"));
+/// assert_eq!(sandbox.resolve("notes.txt"), Some(PathBuf::from("/srv/shell/alice/notes.txt")));
+/// assert_eq!(sandbox.resolve("/bob"), Some(PathBuf::from("/srv/shell/bob")));
+/// assert_eq!(sandbox.resolve("../../etc/passwd"), None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct PathSandbox {
+    root: PathBuf,
+    current_dir: PathBuf,
+}
+
+impl PathSandbox {
+    /// Creates the sandbox of a session.
+    ///
+    /// # Parameters
+    ///
+    /// - `root`: Directory the paths cannot leave
+    /// - `current_dir`: Directory relative paths start from; the root is used if it
+    ///   is not inside it
+    pub fn new(root: PathBuf, current_dir: PathBuf) -> Self {
+        let root = std::fs::canonicalize(&root).unwrap_or(root);
+        let current_dir = std::fs::canonicalize(&current_dir).unwrap_or(current_dir);
+        let current_dir = if current_dir.starts_with(&root) {
+            current_dir
+        } else {
+            root.clone()
+        };
+        PathSandbox { root, current_dir }
+    }
+    /// Returns the current directory the relative paths start from.
+    pub fn current_dir(&self) -> &Path {
+        &self.current_dir
+    }
+    /// Resolves `path`, following every symbolic link in it.
+    ///
+    /// The path does not need to exist, so it can name a file or directory to
+    /// create; the links of the part that exists are still resolved.
+    ///
+    /// # Returns
+    ///
+    /// The absolute path, or `None` if it leads outside the root.
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let path = self.resolve_links(&self.normalize(path)?)?;
+        path.starts_with(&self.root).then_some(path)
+    }
+    /// Resolves `path` like `resolve`, except that a symbolic link at its end is not
+    /// followed, for the commands that remove or rename the entry itself.
+    ///
+    /// # Returns
+    ///
+    /// The absolute path, or `None` if its directory is outside the root or it is
+    /// the root itself.
+    pub fn resolve_entry(&self, path: &str) -> Option<PathBuf> {
+        let path = self.normalize(path)?;
+        if path == self.root {
+            return None;
+        }
+        let name = path.file_name()?.to_os_string();
+        let parent = self.resolve_links(path.parent()?)?;
+        parent.starts_with(&self.root).then(|| parent.join(name))
+    }
+    /// Applies `path` to the current directory without touching the file system.
+    ///
+    /// Returns `None` if a `..` would go above the root.
+    fn normalize(&self, path: &str) -> Option<PathBuf> {
+        let mut resolved = self.current_dir.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::RootDir => resolved = self.root.clone(),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if resolved == self.root {
+                        return None;
+                    }
+                    resolved.pop();
+                }
+                Component::Normal(name) => resolved.push(name),
+                Component::Prefix(_) => return None,
+            }
+        }
+        Some(resolved)
+    }
+    /// Resolves the symbolic links of a normalized path: the longest part that exists
+    /// is canonicalized and the missing names are appended to it.
+    ///
+    /// Returns `None` for a dangling link, whose target cannot be checked.
+    fn resolve_links(&self, path: &Path) -> Option<PathBuf> {
+        let mut existing = path.to_path_buf();
+        let mut missing = Vec::new();
+        loop {
+            if let Ok(mut resolved) = std::fs::canonicalize(&existing) {
+                resolved.extend(missing.iter().rev());
+                return Some(resolved);
+            }
+            if existing.symlink_metadata().is_ok() {
+                return None;
+            }
+            missing.push(existing.file_name()?.to_os_string());
+            existing.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_system::command_handler::CommandHandler;
+    use crate::session_registry::SessionRegistry;
+    use std::sync::Arc;
+
+    /// A root with an `alice` directory, next to a `secret.txt` it must not reach.
+    struct Tree {
+        _dir: tempfile::TempDir,
+        outside: PathBuf,
+        root: PathBuf,
+    }
+
+    fn tree() -> Tree {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = std::fs::canonicalize(dir.path()).unwrap();
+        let root = outside.join("root");
+        std::fs::create_dir_all(root.join("alice")).unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("notes.txt"), "notes").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+            symlink(&outside, root.join("out")).unwrap();
+            symlink(outside.join("secret.txt"), root.join("leak.txt")).unwrap();
+            symlink(root.join("nothing"), root.join("dangling")).unwrap();
+            symlink(root.join("alice"), root.join("inner")).unwrap();
+        }
+        Tree {
+            _dir: dir,
+            outside,
+            root,
+        }
+    }
+
+    async fn run(tree: &Tree, line: &str) -> (String, PathBuf, bool) {
+        let mut handler = CommandHandler::new(
+            line.to_string(),
+            tree.root.clone(),
+            tree.root.clone(),
+            Arc::new(SessionRegistry::default()),
+        );
+        let (response, current_dir, succes) = handler.get_output().await;
+        (response.output, current_dir, succes)
+    }
+
+    #[test]
+    fn parent_dirs_cannot_leave_the_root() {
+        let tree = tree();
+        let sandbox = PathSandbox::new(tree.root.clone(), tree.root.join("alice"));
+        assert_eq!(sandbox.resolve(".."), Some(tree.root.clone()));
+        assert_eq!(
+            sandbox.resolve("../notes.txt"),
+            Some(tree.root.join("notes.txt"))
+        );
+        assert_eq!(sandbox.resolve("../.."), None);
+        assert_eq!(sandbox.resolve("../../secret.txt"), None);
+        assert_eq!(sandbox.resolve("a/../../../secret.txt"), None);
+        assert_eq!(sandbox.resolve_entry(".."), None);
+    }
+
+    #[test]
+    fn absolute_paths_start_at_the_root() {
+        let tree = tree();
+        let sandbox = PathSandbox::new(tree.root.clone(), tree.root.join("alice"));
+        assert_eq!(sandbox.resolve("/"), Some(tree.root.clone()));
+        assert_eq!(
+            sandbox.resolve("/notes.txt"),
+            Some(tree.root.join("notes.txt"))
+        );
+        assert_eq!(sandbox.resolve("/../secret.txt"), None);
+        let secret = tree.outside.join("secret.txt");
+        let resolved = sandbox.resolve(secret.to_str().unwrap()).unwrap();
+        assert!(resolved.starts_with(&tree.root));
+        assert_ne!(resolved, secret);
+    }
+
+    #[test]
+    fn current_dir_outside_the_root_falls_back_to_the_root() {
+        let tree = tree();
+        let sandbox = PathSandbox::new(tree.root.clone(), tree.outside.clone());
+        assert_eq!(sandbox.current_dir(), tree.root.as_path());
+        assert_eq!(
+            sandbox.resolve("secret.txt"),
+            Some(tree.root.join("secret.txt"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_cannot_leave_the_root() {
+        let tree = tree();
+        let sandbox = PathSandbox::new(tree.root.clone(), tree.root.clone());
+        assert_eq!(sandbox.resolve("leak.txt"), None);
+        assert_eq!(sandbox.resolve("out"), None);
+        assert_eq!(sandbox.resolve("out/secret.txt"), None);
+        // a path that leaves through a link and comes back is inside the root
+        assert_eq!(
+            sandbox.resolve("out/root/notes.txt"),
+            Some(tree.root.join("notes.txt"))
+        );
+        assert_eq!(
+            sandbox.resolve("inner/todo.txt"),
+            Some(tree.root.join("alice/todo.txt"))
+        );
+        // the link itself can still be removed or renamed
+        assert_eq!(
+            sandbox.resolve_entry("leak.txt"),
+            Some(tree.root.join("leak.txt"))
+        );
+        assert_eq!(sandbox.resolve_entry("out/secret.txt"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_are_refused() {
+        let tree = tree();
+        let sandbox = PathSandbox::new(tree.root.clone(), tree.root.clone());
+        assert_eq!(sandbox.resolve("dangling"), None);
+        assert_eq!(sandbox.resolve("dangling/file.txt"), None);
+        assert_eq!(
+            sandbox.resolve_entry("dangling"),
+            Some(tree.root.join("dangling"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cd_cannot_leave_the_root() {
+        let tree = tree();
+        for line in ["cd ..", "cd ../..", "cd out", "cd dangling"] {
+            let (output, current_dir, succes) = run(&tree, line).await;
+            assert!(!succes, "{}", line);
+            assert!(output.contains(PERMISSION_DENIED), "{}: {:?}", line, output);
+            assert_eq!(current_dir, tree.root, "{}", line);
+        }
+        let (_, current_dir, succes) = run(&tree, "cd inner").await;
+        assert!(succes);
+        assert_eq!(current_dir, tree.root.join("alice"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn redirects_cannot_read_outside_files() {
+        let tree = tree();
+        let secret = tree.outside.join("secret.txt");
+        let absolute = format!("grep secret < {}", secret.display());
+        for line in [
+            "grep secret < ../secret.txt",
+            "grep secret < leak.txt",
+            "grep secret < out/secret.txt",
+            "grep secret < dangling",
+            absolute.as_str(),
+        ] {
+            let (output, _, succes) = run(&tree, line).await;
+            assert!(!succes, "{}", line);
+            assert!(!output.contains("secret~~"), "{}: {:?}", line, output);
+        }
+        let (output, _, _) = run(&tree, "grep notes < notes.txt").await;
+        assert!(output.contains("notes~~"), "{:?}", output);
+    }
+
+    #[tokio::test]
+    async fn redirect_without_a_file_is_an_error() {
+        let tree = tree();
+        let (output, _, succes) = run(&tree, "cat <").await;
+        assert!(!succes);
+        assert!(output.contains("missing file operand"), "{:?}", output);
+    }
+
+    #[tokio::test]
+    async fn output_redirect_without_a_file_is_an_error() {
+        let tree = tree();
+        for line in ["ls >", "ls > ;", "ls > && pwd"] {
+            let (output, _, _) = run(&tree, line).await;
+            assert!(
+                output.contains("missing file operand"),
+                "{}: {:?}",
+                line,
+                output
+            );
+            assert!(!output.contains("notes.txt"), "{}: {:?}", line, output);
+        }
+        let (_, _, succes) = run(&tree, "ls >").await;
+        assert!(!succes);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::server_configure::Limits;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// What happens to a newly accepted connection.
+///
+/// # Variants
+///
+/// - `Accepted`: The connection is served; the permit holds its places
+/// - `Refused`: A limit is reached; the client gets the message after the TLS
+///   handshake, whose place the permit holds
+/// - `Dropped`: Too many TLS handshakes are in progress, the socket is closed at once
+pub enum Admission {
+    Accepted(ConnectionPermit),
+    Refused(ConnectionPermit, String),
+    Dropped(String),
+}
+
+/// The current counts of a `ConnectionLimiter`, as shown by the `connections` command.
+///
+/// # Fields
+///
+/// - `connections`: Connections being served, logged in or not
+/// - `handshakes`: TLS handshakes in progress
+/// - `refused`: Connections refused or dropped since the server started
+/// - `per_ip`: Connections of each source address, most connections first
+pub struct ConnectionCounts {
+    pub connections: usize,
+    pub handshakes: usize,
+    pub refused: u64,
+    pub per_ip: Vec<(IpAddr, usize)>,
+}
+
+#[derive(Default)]
+struct State {
+    connections: usize,
+    handshakes: usize,
+    refused: u64,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Caps on the connections of the server, checked when a socket is accepted.
+///
+/// Three limits of `Limits` are enforced: the connections served at once, the
+/// connections of one source address and the TLS handshakes in progress. Each
+/// admitted connection holds a `ConnectionPermit`, which gives its places back
+/// when it is dropped, so a session counts until its task ends however it ends.
+///
+/// A connection over the total or per-address limit still gets its TLS handshake,
+/// so the client can be told why it is refused. The handshake limit bounds that
+/// work: beyond it, sockets are closed without a word.
+///
+/// # Examples
+///
+/// ```rust
+/// let limiter = Arc::new(ConnectionLimiter::new(Limits::default()));
+/// match limiter.admit(addr.ip()) {
+///     Admission::Accepted(mut permit) => {
+///         let tls_stream = acceptor.accept(stream).await?;
+///         permit.end_handshake();
+///         // ... serve the session, then drop the permit
+///     }
+///     Admission::Refused(permit, message) => { /* handshake, send message, close */ }
+///     Admission::Dropped(reason) => eprintln!("{} {}", addr, reason),
+/// }
+/// ```
+pub struct ConnectionLimiter {
+    limits: Limits,
+    state: Mutex<State>,
+}
+
+impl ConnectionLimiter {
+    /// Creates a limiter enforcing `max_connections`, `max_connections_per_ip` and
+    /// `max_handshakes` of `limits`.
+    pub fn new(limits: Limits) -> Self {
+        ConnectionLimiter {
+            limits,
+            state: Mutex::new(State::default()),
+        }
+    }
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Decides whether a connection from `ip` is served.
+    pub fn admit(self: &Arc<Self>, ip: IpAddr) -> Admission {
+        let mut state = self.state();
+        let over = |count: usize, max: Option<usize>| max.is_some_and(|max| count >= max);
+        if over(state.handshakes, self.limits.max_handshakes) {
+            state.refused += 1;
+            return Admission::Dropped("refused: too many TLS handshakes in progress".to_string());
+        }
+        state.handshakes += 1;
+        let mut permit = ConnectionPermit {
+            limiter: self.clone(),
+            ip,
+            connection: false,
+            handshake: true,
+        };
+        let from_ip = state.per_ip.get(&ip).copied().unwrap_or(0);
+        if over(state.connections, self.limits.max_connections) {
+            state.refused += 1;
+            return Admission::Refused(permit, "Too many connections to the server".to_string());
+        }
+        if over(from_ip, self.limits.max_connections_per_ip) {
+            state.refused += 1;
+            return Admission::Refused(permit, format!("Too many connections from {}", ip));
+        }
+        state.connections += 1;
+        *state.per_ip.entry(ip).or_default() += 1;
+        permit.connection = true;
+        Admission::Accepted(permit)
+    }
+    /// Returns the current counts.
+    pub fn counts(&self) -> ConnectionCounts {
+        let state = self.state();
+        let mut per_ip: Vec<(IpAddr, usize)> = state
+            .per_ip
+            .iter()
+            .map(|(ip, count)| (*ip, *count))
+            .collect();
+        per_ip.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        ConnectionCounts {
+            connections: state.connections,
+            handshakes: state.handshakes,
+            refused: state.refused,
+            per_ip,
+        }
+    }
+    /// Returns the limits enforced.
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+}
+
+/// The places one connection holds in a `ConnectionLimiter`, given back on drop.
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+    connection: bool,
+    handshake: bool,
+}
+
+impl ConnectionPermit {
+    /// Gives back the handshake place once the TLS handshake is over, successful
+    /// or not.
+    pub fn end_handshake(&mut self) {
+        if self.handshake {
+            self.handshake = false;
+            self.limiter.state().handshakes -= 1;
+        }
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.end_handshake();
+        if self.connection {
+            let mut state = self.limiter.state();
+            state.connections -= 1;
+            if let Some(count) = state.per_ip.get_mut(&self.ip) {
+                *count -= 1;
+                if *count == 0 {
+                    state.per_ip.remove(&self.ip);
+                }
+            }
+        }
+    }
+}
@@ -1,61 +1,96 @@
+use crate::audit::AuditLog;
+use crate::client_auth::CertificateLogin;
 use crate::command_system::common::get_commands;
+use crate::login_guard::{LoginAttempt, LoginGuard};
+use crate::totp::Totp;
+use crate::user_store::{Account, CachedUserStore, UserStore};
 use bcrypt::verify;
+use protocol::auth::{CODE_REQUIRED, decode_public_key, signed_message};
+use protocol::format::{Format, get_format};
+use protocol::message::Response;
+use ring::signature::{ED25519, UnparsedPublicKey};
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+
+/// The only reply to a failed login, whatever the reason, so clients cannot tell
+/// which accounts exist; the reason is written to the `AuditLog`.
+pub const AUTHENTICATION_FAILED: &str = "Authentication failed";
+
+/// Bcrypt hash of a password nobody knows, verified for unknown users so their
+/// login takes as long as a wrong password. Its cost (`12`) is replaced by the one
+/// of the accounts, see `dummy_hash()`.
+const DUMMY_HASH: &str = "$2b$12$NwC2MsZ8bjLZNHOGUXYc2eHPHnWg0E8vys.A9ArCV/42dvzmqWPRG";
+
 /// A user authentication system for secure shell login operations.
 /// 
 /// `UserLogin` handles user authentication by validating credentials against
-/// a password file containing bcrypt-hashed passwords. It manages user sessions
-/// to prevent duplicate logins and ensures secure password verification.
+/// a password file containing bcrypt-hashed passwords and ensures secure password
+/// verification. How many sessions the user may then open is decided when the
+/// session registers in the `SessionRegistry`.
 /// 
 /// # Features
 /// 
 /// - **Secure Password Verification**: Uses bcrypt for password hashing and verification
-/// - **Session Management**: Prevents multiple simultaneous logins from the same user
-/// - **File-based User Database**: Reads user credentials from a simple text file format
+/// - **File-based User Database**: Reads user accounts through `UserStore`
 /// - **Command Parsing**: Validates login command format and extracts credentials
-/// - **Thread-safe Operations**: Uses Arc<RwLock> for concurrent user session management
+/// - **Thread-safe Operations**: Shares the user database and the `LoginGuard` between sessions
 /// 
 /// # Password File Format
 /// 
-/// The password file should contain alternating usernames and bcrypt hashes:
+/// The password file is a `UserStore`, shared by the sessions through a
+/// `CachedUserStore`: either `[users.NAME]` TOML tables or the legacy lines of
+/// usernames and bcrypt hashes:
 /// ```text
 /// alice $2b$12$hash1...
 /// bob $2b$12$hash2...
 /// charlie $2b$12$hash3...
 /// ```
 /// 
+/// An account can register an Ed25519 public key instead of a hash; it then logs
+/// in with the challenge/response of `get_key_login_status()` and has no password.
+/// An account with a TOTP secret (see `totp`) needs a one-time code as second
+/// factor, and a disabled account cannot log in at all.
+/// 
+/// With a `LoginGuard`, every failed login is counted against the user name and the
+/// client address, which are slowed down and then locked out after too many failures.
+/// Every failed login gets the same `AUTHENTICATION_FAILED` reply; the reason is
+/// only written to the `AuditLog`.
+/// 
 /// # Login Command Format
 /// 
 /// Users must authenticate using the following command format:
 /// ```text
 /// login [USERNAME] [PASSWORD]
+/// login [USERNAME] [PASSWORD] [CODE]
 /// ```
 /// 
+/// A client whose TLS certificate maps to `USERNAME` (see `client_auth`) can leave
+/// the password out with `login [USERNAME]`, unless the server requires both.
+/// 
 /// # Examples
 /// 
 /// ```rust
-/// use std::path::PathBuf;
+/// use std::path::Path;
 /// use std::sync::Arc;
-/// use tokio::sync::RwLock;
 /// 
-/// let users = Arc::new(RwLock::new(Vec::new()));
+/// let user_store = Arc::new(CachedUserStore::load(Path::new("/etc/secure_shell/passwords"))?);
 /// let login = UserLogin::new(
 ///     "login alice mypassword".to_string(),
-///     PathBuf::from("/etc/secure_shell/passwords"),
-///     users.clone()
+///     user_store,
 /// );
 /// 
 /// match login.get_login_status().await {
-///     Ok(username) => println!("User {} authenticated successfully", username),
-///     Err(error_msg) => println!("Authentication failed: {}", error_msg),
+///     Ok((username, account)) => println!("User {} authenticated successfully", username),
+///     Err(error) => println!("Authentication failed: {}", error.output),
 /// }
 /// ```
 pub struct UserLogin {
     input: String,
-    passwords_path: std::path::PathBuf,
-    users_list: Arc<RwLock<Vec<String>>>,
+    user_store: Arc<CachedUserStore>,
+    certificate: CertificateLogin,
+    guard: Option<(Arc<LoginGuard>, IpAddr)>,
+    audit: Option<(Arc<AuditLog>, SocketAddr)>,
 }
 impl UserLogin {
         /// Creates a new `UserLogin` instance for processing authentication.
@@ -63,8 +98,7 @@ impl UserLogin {
     /// # Parameters
     /// 
     /// - `input`: Raw command string from the client (e.g., "login alice password123")
-    /// - `path`: Path to the password file containing user credentials
-    /// - `users`: Thread-safe reference to the list of currently active users
+    /// - `user_store`: The user database shared by the sessions
     /// 
     /// # Returns
     /// 
@@ -73,75 +107,61 @@ impl UserLogin {
     /// # Examples
     /// 
     /// ```rust
-    /// let users = Arc::new(RwLock::new(vec!["existing_user".to_string()]));
     /// let login = UserLogin::new(
     ///     "login newuser secretpass".to_string(),
-    ///     PathBuf::from("/etc/passwords"),
-    ///     users
+    ///     user_store.clone(),
     /// );
     /// ```
-    pub fn new(input: String, path: std::path::PathBuf, users: Arc<RwLock<Vec<String>>>) -> Self {
+    pub fn new(input: String, user_store: Arc<CachedUserStore>) -> Self {
         UserLogin {
             input,
-            passwords_path: path,
-            users_list: users,
+            user_store,
+            certificate: CertificateLogin::default(),
+            guard: None,
+            audit: None,
         }
     }
-        /// Loads and parses the password file into a username-to-hash mapping.
+    /// Sets what the client certificate of the connection allows at login.
+    ///
+    /// Without it, only password logins are accepted.
+    pub fn with_certificate(mut self, certificate: CertificateLogin) -> Self {
+        self.certificate = certificate;
+        self
+    }
+    /// Counts the failed logins of the client at `address` with `guard`.
+    ///
+    /// Without it, failed logins can be retried without delay.
+    pub fn with_guard(mut self, guard: Arc<LoginGuard>, address: IpAddr) -> Self {
+        self.guard = Some((guard, address));
+        self
+    }
+    /// Writes the reasons of the failed logins of the client at `peer` to `audit`.
+    ///
+    /// Without it, the reasons are not recorded anywhere.
+    pub fn with_audit(mut self, audit: Arc<AuditLog>, peer: SocketAddr) -> Self {
+        self.audit = Some((audit, peer));
+        self
+    }
+    /// Returns the accounts of the password file.
     /// 
-    /// This method reads the password file and creates a HashMap where keys are
-    /// usernames and values are their corresponding bcrypt password hashes.
-    /// The file format expects alternating usernames and password hashes
-    /// separated by whitespace.
+    /// The file is only read again when it changed (see `CachedUserStore`), so
+    /// accounts can still be added or changed while the server runs.
     /// 
     /// # Returns
     /// 
-    /// A `HashMap<String, String>` mapping usernames to their bcrypt password hashes.
-    /// 
-    /// # Panics
-    /// 
-    /// Panics if the password file cannot be read. This is intentional to prevent
-    /// the system from operating without proper authentication data.
-    /// 
-    /// # File Format
-    /// 
-    /// The password file should follow this format:
-    /// ```text
-    /// username1 $2b$12$hash1...
-    /// username2 $2b$12$hash2...
-    /// username3 $2b$12$hash3...
-    /// ```
+    /// The `UserStore` holding the accounts of the password file.
     /// 
     /// # Examples
     /// 
     /// ```rust
-    /// // Password file content:
-    /// // alice $2b$12$abcdef...
-    /// // bob $2b$12$ghijkl...
-    /// 
     /// let login = UserLogin::new(/* ... */);
-    /// let password_map = login.get_hashmap_password();
+    /// let store = login.get_user_store().await;
     /// 
-    /// assert!(password_map.contains_key("alice"));
-    /// assert!(password_map.contains_key("bob"));
+    /// assert!(store.get("alice").is_some());
     /// ```
     /// 
-    fn get_hashmap_password(&self) -> HashMap<String, String> {
-        let mut hashmap = HashMap::new();
-        let users_password = std::fs::read_to_string(&self.passwords_path)
-            .unwrap_or_else(|_| panic!("Error , password file"));
-        let split_text: Vec<String> = users_password
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect();
-        let mut i = 0;
-        while i < split_text.len() {
-            if i + 1 < split_text.len() {
-                hashmap.insert(split_text[i].clone(), split_text[i + 1].clone());
-            }
-            i += 2;
-        }
-        hashmap
+    async fn get_user_store(&self) -> Arc<UserStore> {
+        self.user_store.get().await
     }
         /// Processes the login request and returns the authentication result.
     /// 
@@ -149,21 +169,23 @@ impl UserLogin {
     /// 1. Command format validation
     /// 2. Username existence verification
     /// 3. Password verification using bcrypt
-    /// 4. Duplicate session prevention
-    /// 5. User session registration
+    /// 4. Account status check
     /// 
     /// # Returns
     /// 
-    /// - `Ok(String)`: Contains the authenticated username on successful login
-    /// - `Err(String)`: Contains a formatted error message for failed authentication
+    /// - `Ok((String, Account))`: Contains the authenticated username and its account on successful login
+    /// - `Err(Response)`: Contains a formatted error response for failed authentication
     /// 
     /// # Authentication Flow
     /// 
     /// 1. **Command Parsing**: Validates the input follows "login [USERNAME] [PASSWORD]" format
     /// 2. **User Lookup**: Checks if the username exists in the password database
-    /// 3. **Password Verification**: Uses bcrypt to verify the provided password
-    /// 4. **Session Check**: Ensures the user isn't already logged in
-    /// 5. **Success**: Returns the username for session registration
+    /// 3. **Password Verification**: Uses bcrypt to verify the provided password, on
+    ///    the blocking thread pool so the other sessions keep running meanwhile
+    /// 4. **Code Verification**: Checks the one-time code of accounts with a TOTP secret
+    /// 5. **Account Check**: Ensures the account isn't disabled
+    /// 6. **Success**: Returns the username for session registration, where the
+    ///    `SessionLimit` of the account is enforced
     /// 
     /// # Error Conditions
     /// 
@@ -171,16 +193,24 @@ impl UserLogin {
     /// |-----------|---------------|
     /// | Wrong command | "Error: You are not connected. Please connect before sending a command" |
     /// | Invalid format | "Invalid format:>login [USERNAME] [PASSWORD]" |
-    /// | Unknown user | "Invalid username" |
-    /// | Wrong password | "Incorrect password" |
-    /// | Already logged in | "User already logged in" |
+    /// | Unknown user | "Authentication failed" |
+    /// | Wrong password | "Authentication failed" |
+    /// | Account with a public key | "Authentication failed" |
+    /// | Code missing (TOTP account) | "A one-time code is required: login [USERNAME] [PASSWORD] [CODE]" |
+    /// | Wrong code | "Authentication failed" |
+    /// | Certificate required but missing | "Authentication failed" |
+    /// | Certificate of another user | "Authentication failed" |
+    /// | Disabled account | "Account disabled" |
+    /// | Too many failures | "Too many failed logins, try again in N s" |
     /// 
     /// # Security Features
     /// 
     /// - **bcrypt Verification**: Secure password hashing prevents plaintext password storage
-    /// - **Session Management**: Prevents multiple concurrent sessions per user
     /// - **Input Validation**: Strict command format validation prevents injection attacks
-    /// - **Error Messages**: Generic error messages prevent username enumeration
+    /// - **Error Messages**: Every failure that depends on the credentials gets the same
+    ///   reply, and unknown users go through the same bcrypt work, so neither the
+    ///   message nor the timing tells which accounts exist
+    /// - **Brute-force Protection**: Failed logins are delayed and locked out by the `LoginGuard`
     /// 
     /// # Examples
     /// 
@@ -188,16 +218,15 @@ impl UserLogin {
     /// ```rust
     /// let login = UserLogin::new(
     ///     "login alice correctpassword".to_string(),
-    ///     password_path,
-    ///     users_list
+    ///     user_store,
     /// );
     /// 
     /// match login.get_login_status().await {
-    ///     Ok(username) => {
+    ///     Ok((username, _)) => {
     ///         println!("User {} logged in successfully", username);
-    ///         // Add user to active sessions
+    ///         // Register the session within the session limit of the account
     ///     }
-    ///     Err(error) => println!("Login failed: {}", error),
+    ///     Err(error) => println!("Login failed: {}", error.output),
     /// }
     /// ```
     /// 
@@ -205,15 +234,14 @@ impl UserLogin {
     /// ```rust
     /// let login = UserLogin::new(
     ///     "login alice wrongpassword".to_string(),
-    ///     password_path,
-    ///     users_list
+    ///     user_store,
     /// );
     /// 
     /// match login.get_login_status().await {
     ///     Ok(_) => unreachable!(),
     ///     Err(error) => {
-    ///         // error contains: "?&EIncorrect password[-]:[-]\r\n\r\n"
-    ///         println!("Authentication failed: {}", error);
+    ///         // error.encode() is: "?&EAuthentication failed[-]:[-]"
+    ///         println!("Authentication failed: {}", error.output);
     ///     }
     /// }
     /// ```
@@ -222,57 +250,344 @@ impl UserLogin {
     /// ```rust
     /// let login = UserLogin::new(
     ///     "login alice".to_string(), // Missing password
-    ///     password_path,
-    ///     users_list
+    ///     user_store,
     /// );
     /// 
     /// match login.get_login_status().await {
     ///     Err(error) => {
     ///         // error contains format error message
-    ///         println!("Invalid format: {}", error);
+    ///         println!("Invalid format: {}", error.output);
     ///     }
     ///     Ok(_) => unreachable!(),
     /// }
     /// ```
-    pub async fn get_login_status(&self) -> Result<String, String> {
+    pub async fn get_login_status(&self) -> Result<(String, Account), Response> {
         let cmd = get_commands(self.input.clone());
-        let pass_map = self.get_hashmap_password();
-        if cmd[0].cmd[0] == "login" {
-            if cmd[0].cmd.len() == 3 {
+        // an empty frame, an unclosed quote or a leading operator gives no command
+        let name = cmd.first().and_then(|c| c.cmd.first()).map(String::as_str);
+        let _attempt = match name {
+            Some("login") if cmd[0].cmd.len() >= 2 => self.check_guard(&cmd[0].cmd[1])?,
+            _ => None,
+        };
+        let store = self.get_user_store().await;
+        if name == Some("login") {
+            if cmd[0].cmd.len() == 3 || cmd[0].cmd.len() == 4 {
                 let username = cmd[0].cmd[1].clone();
-                if let Some(stored_hash) = pass_map.get(&username) {
-                    let password = cmd[0].cmd[2].clone();
-                    if verify(password, stored_hash).unwrap_or(false) {
-                        let users_lock = self.users_list.read().await;
-                        let user_exists = users_lock
-                            .iter()
-                            .any(|user_entry| user_entry.contains(&username));
-
-                        if user_exists {
-                            Err(format!(
-                                "{}[-]:{}[-]\r\n\r\n",
-                                "?&EUser already logged in", ""
+                let password = cmd[0].cmd[2].clone();
+                let account = store.get(&username);
+                // Unknown users and accounts without a password are checked against
+                // DUMMY_HASH, so every failure costs the same bcrypt work.
+                let hash = match account.and_then(|account| account.hash.clone()) {
+                    Some(hash) => hash,
+                    None => dummy_hash(&store),
+                };
+                let password_ok = verify_password(password, hash).await;
+                match account {
+                    None => Err(self.failure(&username, "Invalid username").await),
+                    Some(account) if account.hash.is_none() => Err(self
+                        .failure(&username, "This user logs in with a public key")
+                        .await),
+                    Some(_)
+                        if self.certificate.require_password
+                            && self.certificate.user.as_ref() != Some(&username) =>
+                    {
+                        Err(self
+                            .failure(&username, "A client certificate is required for this user")
+                            .await)
+                    }
+                    Some(_) if !password_ok => {
+                        Err(self.failure(&username, "Incorrect password").await)
+                    }
+                    Some(account) => match (&account.totp, cmd[0].cmd.get(3)) {
+                        (None, _) => self.accept_account(username, account).await,
+                        (Some(_), None) => Err(UserLogin::error_response(&format!(
+                            "{}: login [USERNAME] [PASSWORD] [CODE]",
+                            CODE_REQUIRED
+                        ))),
+                        (Some(secret), Some(code)) => {
+                            if Totp::from_base32(secret).is_some_and(|totp| totp.verify(code)) {
+                                self.accept_account(username, account).await
+                            } else {
+                                Err(self.failure(&username, "Incorrect one-time code").await)
+                            }
+                        }
+                    },
+                }
+            } else if cmd[0].cmd.len() == 2 && self.certificate.user.is_some() {
+                let username = cmd[0].cmd[1].clone();
+                match store.get(&username) {
+                    Some(account) if self.certificate.user.as_ref() == Some(&username) => {
+                        if self.certificate.require_password {
+                            Err(UserLogin::error_response(
+                                "Invalid format:>login [USERNAME] [PASSWORD] ",
                             ))
                         } else {
-                            Ok(username)
+                            self.accept_account(username, account).await
                         }
-                    } else {
-                        Err(format!("{}[-]:{}[-]\r\n\r\n", "?&EIncorrect password", ""))
                     }
-                } else {
-                    Err(format!("{}[-]:{}[-]\r\n\r\n", "?&EInvalid username", ""))
+                    _ => Err(self
+                        .failure(&username, "Client certificate does not match user")
+                        .await),
                 }
             } else {
-                Err(format!(
-                    "{}[-]:{}[-]\r\n\r\n",
-                    "?&EInvalid format:>login [USERNAME] [PASSWORD] ", ""
+                Err(UserLogin::error_response(
+                    "Invalid format:>login [USERNAME] [PASSWORD] ",
                 ))
             }
         } else {
-            Err(format!(
-                "{}[-]:{}[-]\r\n\r\n",
-                "?&EError: You are not connected. Please connect before sending a command", ""
+            Err(UserLogin::error_response(
+                "Error: You are not connected. Please connect before sending a command",
             ))
         }
     }
+    /// Processes a public key login and returns the authentication result.
+    ///
+    /// `signature` must be the Ed25519 signature of
+    /// `protocol::auth::signed_message(username, challenge)`, made with the private
+    /// key matching the `public_key` of the account of `username`. The command line given to
+    /// `new()` is not used.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((String, Account))`: Contains the authenticated username and its account on successful login
+    /// - `Err(Response)`: Contains a formatted error response for failed authentication
+    ///
+    /// # Error Conditions
+    ///
+    /// | Condition | Error Message |
+    /// |-----------|---------------|
+    /// | Unknown user | "Authentication failed" |
+    /// | Account with a password | "Authentication failed" |
+    /// | Wrong key or challenge | "Authentication failed" |
+    /// | Certificate required but missing | "Authentication failed" |
+    /// | Disabled account | "Account disabled" |
+    /// | Too many failures | "Too many failed logins, try again in N s" |
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let login = UserLogin::new(String::new(), user_store);
+    /// match login.get_key_login_status("dave", &challenge, &signature).await {
+    ///     Ok((username, _)) => println!("User {} logged in with a key", username),
+    ///     Err(error) => println!("Login failed: {}", error.output),
+    /// }
+    /// ```
+    pub async fn get_key_login_status(
+        &self,
+        username: &str,
+        challenge: &[u8],
+        signature: &[u8],
+    ) -> Result<(String, Account), Response> {
+        let _attempt = self.check_guard(username)?;
+        let store = self.get_user_store().await;
+        let Some(account) = store.get(username) else {
+            return Err(self.failure(username, "Invalid username").await);
+        };
+        let Some(public_key) = account.public_key.as_deref().and_then(decode_public_key) else {
+            return Err(self
+                .failure(username, "No public key registered for this user")
+                .await);
+        };
+        if self.certificate.require_password && self.certificate.user.as_deref() != Some(username) {
+            return Err(self
+                .failure(username, "A client certificate is required for this user")
+                .await);
+        }
+        let message = signed_message(username, challenge);
+        if UnparsedPublicKey::new(&ED25519, &public_key)
+            .verify(&message, signature)
+            .is_err()
+        {
+            return Err(self.failure(username, "Invalid signature").await);
+        }
+        self.accept_account(username.to_string(), account).await
+    }
+    /// Accepts the authenticated `username` unless its account is disabled.
+    async fn accept_account(
+        &self,
+        username: String,
+        account: &Account,
+    ) -> Result<(String, Account), Response> {
+        if account.disabled {
+            return Err(self.refusal(&username, "Account disabled"));
+        }
+        if let Some((guard, _)) = &self.guard {
+            guard.succeeded(&username);
+        }
+        Ok((username, account.clone()))
+    }
+    /// Refuses the login of `username` while it or the client address is slowed
+    /// down or locked out by the `LoginGuard`.
+    ///
+    /// The returned attempt has to be kept until the login is over.
+    fn check_guard(&self, username: &str) -> Result<Option<LoginAttempt>, Response> {
+        match &self.guard {
+            Some((guard, address)) => guard
+                .check(username, *address)
+                .map(Some)
+                .map_err(|message| self.refusal(username, &message)),
+            None => Ok(None),
+        }
+    }
+    /// Records a failed login of `username` and returns the generic error response.
+    ///
+    /// `reason` only goes to the audit log, so the reply is the same whether the
+    /// user exists or not.
+    async fn failure(&self, username: &str, reason: &str) -> Response {
+        if let Some((guard, address)) = &self.guard {
+            guard.failed(username, *address).await;
+        }
+        self.audit(username, &format!("login failed: {}", reason));
+        UserLogin::error_response(AUTHENTICATION_FAILED)
+    }
+    /// Refuses the login of `username` for a reason the client may know, because it
+    /// does not depend on the credentials or is only given once they are verified.
+    fn refusal(&self, username: &str, message: &str) -> Response {
+        self.audit(username, &format!("login refused: {}", message));
+        UserLogin::error_response(message)
+    }
+    /// Writes an event of `username` to the audit log, if one is set.
+    fn audit(&self, username: &str, event: &str) {
+        if let Some((audit, peer)) = &self.audit {
+            audit.record(*peer, username, event);
+        }
+    }
+    fn error_response(message: &str) -> Response {
+        Response::new(format!("{}{}", get_format(Format::Error), message), "")
+    }
+}
+
+/// Checks `password` against a bcrypt `hash`.
+///
+/// Bcrypt is slow on purpose, so it runs on the blocking thread pool instead of
+/// the async workers that serve the sessions.
+async fn verify_password(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || verify(password, &hash).unwrap_or(false))
+        .await
+        .unwrap_or(false)
+}
+
+/// Returns `DUMMY_HASH` with the bcrypt cost most accounts of `store` use, so the
+/// login of an unknown user takes as long as a typical wrong password.
+fn dummy_hash(store: &UserStore) -> String {
+    let mut costs: HashMap<&str, usize> = HashMap::new();
+    for (_, account) in store.accounts() {
+        // A bcrypt hash is `$2b$COST$SALTHASH`, with a two-digit cost.
+        let cost = account
+            .hash
+            .as_deref()
+            .and_then(|hash| hash.split('$').nth(2))
+            .filter(|cost| cost.len() == 2 && cost.bytes().all(|b| b.is_ascii_digit()));
+        if let Some(cost) = cost {
+            *costs.entry(cost).or_default() += 1;
+        }
+    }
+    let typical = costs
+        .into_iter()
+        .max_by_key(|(cost, count)| (*count, *cost));
+    match typical {
+        Some((cost, _)) => DUMMY_HASH.replacen("$12$", &format!("${}$", cost), 1),
+        None => DUMMY_HASH.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_system::command_handler::CommandHandler;
+    use crate::session_registry::SessionRegistry;
+    use std::time::{Duration, Instant};
+
+    fn login(input: &str) -> UserLogin {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            "alice $2b$04$.cZ4.nVyTTxcDk4DvliLj.VNsMjQbprRoPjCKn.pxAGQZcSRBMJxm\n",
+        )
+        .unwrap();
+        let store = CachedUserStore::load(file.path()).unwrap();
+        UserLogin::new(input.to_string(), Arc::new(store))
+    }
+
+    #[tokio::test]
+    async fn lines_without_a_command_are_refused() {
+        for input in ["", "   ", "'login alice", "| login alice pw", "&& ls", ";"] {
+            let Err(response) = login(input).get_login_status().await else {
+                panic!("{:?} logged in", input);
+            };
+            assert!(
+                response.output.contains("You are not connected"),
+                "{:?}: {:?}",
+                input,
+                response.output
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn login_without_a_password_is_refused() {
+        let Err(response) = login("login").get_login_status().await else {
+            panic!("logged in");
+        };
+        assert!(
+            response.output.contains("Invalid format"),
+            "{:?}",
+            response.output
+        );
+    }
+
+    /// Times a command of a running session while `LOGINS` bcrypt logins are checked
+    /// at once, on a runtime with as few workers as a small server. Run with
+    /// `cargo test -p server --release concurrent_logins -- --ignored --nocapture`.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[ignore = "benchmark"]
+    async fn concurrent_logins_do_not_stall_commands() {
+        const LOGINS: usize = 16;
+        let hash = bcrypt::hash("secret", 10).unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), format!("alice {}\n", hash)).unwrap();
+        let store = Arc::new(CachedUserStore::load(file.path()).unwrap());
+        let started = Instant::now();
+        verify_password("wrong".to_string(), hash).await;
+        let one_login = started.elapsed();
+
+        let root = tempfile::tempdir().unwrap();
+        let logins: Vec<_> = (0..LOGINS)
+            .map(|_| {
+                let login = UserLogin::new("login alice wrong".to_string(), store.clone());
+                tokio::spawn(async move { login.get_login_status().await.is_err() })
+            })
+            .collect();
+        let mut latencies = Vec::new();
+        while !logins.iter().all(|login| login.is_finished()) {
+            let root = root.path().to_path_buf();
+            let started = Instant::now();
+            tokio::spawn(async move {
+                let sessions = Arc::new(SessionRegistry::default());
+                CommandHandler::new("pwd".to_string(), root.clone(), root, sessions)
+                    .get_output()
+                    .await
+            })
+            .await
+            .unwrap();
+            latencies.push(started.elapsed());
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        for login in logins {
+            assert!(login.await.unwrap());
+        }
+
+        latencies.sort();
+        let median = latencies[latencies.len() / 2];
+        let max = latencies[latencies.len() - 1];
+        println!(
+            "{} logins of {:?} each: {} commands, median {:?}, max {:?}",
+            LOGINS,
+            one_login,
+            latencies.len(),
+            median,
+            max
+        );
+        assert!(max < one_login / 2, "a command waited {:?}", max);
+    }
 }
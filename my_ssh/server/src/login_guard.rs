@@ -0,0 +1,513 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Failed logins after which a user or address is locked out, when none is configured.
+pub const DEFAULT_MAX_FAILURES: u32 = 5;
+/// Delay after the first failed login, when none is configured.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+/// Length of a lockout, when none is configured.
+pub const DEFAULT_LOCKOUT: Duration = Duration::from_secs(900);
+/// Longest delay between two failed logins before the lockout.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How failed logins are throttled.
+///
+/// # Fields
+///
+/// - `max_failures`: Failed logins in a row after which the target is locked out
+/// - `backoff`: Delay after the first failure; it doubles with every failure
+/// - `lockout`: Length of a lockout
+/// - `ban_file`: File the lockouts are saved to, so they survive a restart
+#[derive(Clone, Debug)]
+pub struct GuardSettings {
+    pub max_failures: u32,
+    pub backoff: Duration,
+    pub lockout: Duration,
+    pub ban_file: Option<PathBuf>,
+}
+
+impl Default for GuardSettings {
+    fn default() -> Self {
+        GuardSettings {
+            max_failures: DEFAULT_MAX_FAILURES,
+            backoff: DEFAULT_BACKOFF,
+            lockout: DEFAULT_LOCKOUT,
+            ban_file: None,
+        }
+    }
+}
+
+/// What the failed logins are counted against.
+///
+/// # Variants
+///
+/// - `User`: A user name, whether or not the account exists
+/// - `Address`: The IP address of the client
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Target {
+    User(String),
+    Address(IpAddr),
+}
+
+impl Target {
+    /// Reads the target named by an administrator: an IP address, or a user name.
+    pub fn parse(text: &str) -> Target {
+        match text.parse() {
+            Ok(address) => Target::Address(address),
+            Err(_) => Target::User(text.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::User(user) => write!(f, "user {}", user),
+            Target::Address(address) => write!(f, "address {}", address),
+        }
+    }
+}
+
+/// The failed logins of one target.
+///
+/// - `count`: Failed logins in a row
+/// - `retry_at`: Time before which logins are refused; `None` for a permanent ban
+/// - `locked`: The target is locked out, not just slowed down
+#[derive(Clone, Debug)]
+struct Failures {
+    count: u32,
+    retry_at: Option<SystemTime>,
+    locked: bool,
+}
+
+/// The failed logins and the logins in progress, under one lock.
+///
+/// - `failures`: Failed logins of each target
+/// - `pending`: Logins of each target allowed by `check()` and not over yet
+/// - `version`: Number of the last change of the lockouts, so an older ban file is
+///   never written over a newer one
+#[derive(Default)]
+struct State {
+    failures: HashMap<Target, Failures>,
+    pending: HashMap<Target, u32>,
+    version: u64,
+}
+
+/// Serialized form of the ban file.
+///
+/// Every entry maps a user name or an address to the end of its lockout, in seconds
+/// since the Unix epoch; `0` bans it until an administrator unlocks it.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct BanFile {
+    #[serde(default)]
+    users: BTreeMap<String, u64>,
+    #[serde(default)]
+    addresses: BTreeMap<String, u64>,
+}
+
+/// Protection of the login against password guessing.
+///
+/// `LoginGuard` is shared by every session of the server and counts the failed
+/// logins of each user name and of each client address, so an attacker can neither
+/// retry on the same connection nor spread the attempts over new ones:
+///
+/// - After a failure, the next login of the user or address is refused for
+///   `backoff`, doubled after every further failure (at most one minute)
+/// - After `max_failures` failures in a row, the target is locked out for `lockout`
+/// - A successful login clears the failures of the user; the address keeps its own,
+///   so a valid account cannot be used to reset them
+///
+/// `check()` counts the login as an attempt until the returned `LoginAttempt` is
+/// dropped, so connections opened at once cannot get more tries than the lockout
+/// allows, nor try again together once a backoff is over.
+///
+/// Lockouts are written to the ban file, when one is configured, and read back at
+/// startup. Administrators can list and clear them with the `unlock` command.
+///
+/// # Ban File Format
+///
+/// ```toml
+/// [users]
+/// alice = 1760003600
+///
+/// [addresses]
+/// "203.0.113.7" = 0
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// let guard = LoginGuard::new(GuardSettings::default())?;
+/// let attempt = guard.check("alice", addr.ip())?;
+/// if password_ok {
+///     guard.succeeded("alice");
+/// } else {
+///     guard.failed("alice", addr.ip()).await;
+/// }
+/// drop(attempt);
+/// ```
+pub struct LoginGuard {
+    settings: GuardSettings,
+    state: Mutex<State>,
+    saved: Arc<Mutex<u64>>,
+}
+
+impl Default for LoginGuard {
+    fn default() -> Self {
+        LoginGuard {
+            settings: GuardSettings::default(),
+            state: Mutex::new(State::default()),
+            saved: Arc::new(Mutex::new(0)),
+        }
+    }
+}
+
+impl LoginGuard {
+    /// Creates the guard and reads the lockouts saved in the ban file.
+    ///
+    /// A missing ban file is created on the first lockout; expired entries are
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the ban file exists but cannot be read or is invalid.
+    pub fn new(settings: GuardSettings) -> Result<LoginGuard, Box<dyn Error>> {
+        let mut failures = HashMap::new();
+        if let Some(path) = settings.ban_file.as_deref().filter(|path| path.exists()) {
+            let now = SystemTime::now();
+            for (target, retry_at) in read_ban_file(path)? {
+                if retry_at.is_none_or(|time| time > now) {
+                    let entry = Failures {
+                        count: settings.max_failures,
+                        retry_at,
+                        locked: true,
+                    };
+                    failures.insert(target, entry);
+                }
+            }
+        }
+        Ok(LoginGuard {
+            settings,
+            state: Mutex::new(State {
+                failures,
+                ..State::default()
+            }),
+            saved: Arc::new(Mutex::new(0)),
+        })
+    }
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Checks that `user` may try to log in from `address` now.
+    ///
+    /// The logins in progress count as failures until they are over: a login is
+    /// refused when they could reach `max_failures`, or when the target already
+    /// failed and another login of it is in progress while a backoff applies.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(LoginAttempt)`: The login may be attempted; it counts as in progress
+    ///   until the `LoginAttempt` is dropped
+    /// - `Err(String)`: Why it is refused, to be sent to the client
+    pub fn check(self: &Arc<Self>, user: &str, address: IpAddr) -> Result<LoginAttempt, String> {
+        let mut state = self.state();
+        let now = SystemTime::now();
+        let targets = [Target::User(user.to_string()), Target::Address(address)];
+        let wait = targets
+            .iter()
+            .filter_map(|target| state.failures.get(target))
+            .map(|entry| match entry.retry_at {
+                Some(time) => time.duration_since(now).ok(),
+                None => Some(Duration::MAX),
+            })
+            .max()
+            .flatten();
+        match wait {
+            None => {}
+            Some(Duration::MAX) => return Err("Too many failed logins, login refused".to_string()),
+            Some(wait) => {
+                return Err(format!(
+                    "Too many failed logins, try again in {} s",
+                    wait.as_secs() + 1
+                ));
+            }
+        }
+        for target in &targets {
+            let count = state.failures.get(target).map_or(0, |entry| entry.count);
+            let pending = state.pending.get(target).copied().unwrap_or(0);
+            let backoff = count > 0 && !self.settings.backoff.is_zero();
+            if count + pending >= self.settings.max_failures || (backoff && pending > 0) {
+                return Err("Too many logins at once, try again later".to_string());
+            }
+        }
+        for target in &targets {
+            *state.pending.entry(target.clone()).or_default() += 1;
+        }
+        Ok(LoginAttempt {
+            guard: self.clone(),
+            targets,
+        })
+    }
+    /// Records a failed login of `user` from `address`, and locks out the user or
+    /// the address that reached `max_failures`.
+    pub async fn failed(&self, user: &str, address: IpAddr) {
+        let snapshot = self.count_failure(user, address);
+        self.save(snapshot).await;
+    }
+    /// Counts a failed login for `failed()`, and returns the ban file to save when
+    /// it locked out a target.
+    fn count_failure(&self, user: &str, address: IpAddr) -> Option<(u64, String)> {
+        let mut state = self.state();
+        let now = SystemTime::now();
+        self.forget_expired(&mut state.failures, now);
+        let mut locked = false;
+        for target in [Target::User(user.to_string()), Target::Address(address)] {
+            let entry = state.failures.entry(target.clone()).or_insert(Failures {
+                count: 0,
+                retry_at: Some(now),
+                locked: false,
+            });
+            if entry.locked {
+                continue;
+            }
+            entry.count += 1;
+            if entry.count >= self.settings.max_failures {
+                entry.locked = true;
+                entry.retry_at = Some(now + self.settings.lockout);
+                locked = true;
+                println!(
+                    "{} locked out for {} s after {} failed logins",
+                    target,
+                    self.settings.lockout.as_secs(),
+                    entry.count
+                );
+            } else {
+                let backoff = self
+                    .settings
+                    .backoff
+                    .saturating_mul(2u32.saturating_pow(entry.count - 1))
+                    .min(MAX_BACKOFF);
+                entry.retry_at = Some(now + backoff);
+            }
+        }
+        locked.then(|| self.snapshot(&mut state)).flatten()
+    }
+    /// Clears the failed logins of `user` after a successful login.
+    pub fn succeeded(&self, user: &str) {
+        self.state()
+            .failures
+            .remove(&Target::User(user.to_string()));
+    }
+    /// Clears the failed logins and the lockout of `target`.
+    ///
+    /// # Returns
+    ///
+    /// True if `target` had any failed login.
+    pub async fn unlock(&self, target: &Target) -> bool {
+        let (removed, snapshot) = {
+            let mut state = self.state();
+            let removed = state.failures.remove(target);
+            let snapshot = removed
+                .as_ref()
+                .is_some_and(|entry| entry.locked)
+                .then(|| self.snapshot(&mut state))
+                .flatten();
+            (removed, snapshot)
+        };
+        self.save(snapshot).await;
+        removed.is_some()
+    }
+    /// Returns the targets locked out now, sorted, with the time left of their
+    /// lockout (`None` for a permanent ban).
+    pub fn lockouts(&self) -> Vec<(Target, Option<Duration>)> {
+        let state = self.state();
+        let now = SystemTime::now();
+        let mut lockouts: Vec<(Target, Option<Duration>)> = state
+            .failures
+            .iter()
+            .filter(|(_, entry)| entry.locked)
+            .filter_map(|(target, entry)| match entry.retry_at {
+                Some(time) => time
+                    .duration_since(now)
+                    .ok()
+                    .map(|left| (target.clone(), Some(left))),
+                None => Some((target.clone(), None)),
+            })
+            .collect();
+        lockouts.sort();
+        lockouts
+    }
+    /// Drops the targets that have not failed for a whole lockout, and the lockouts
+    /// that are over, so their count starts again from zero.
+    fn forget_expired(&self, failures: &mut HashMap<Target, Failures>, now: SystemTime) {
+        failures.retain(|_, entry| match entry.retry_at {
+            Some(time) if entry.locked => time > now,
+            Some(time) => time + self.settings.lockout > now,
+            None => true,
+        });
+    }
+    /// Returns the ban file of the current lockouts with its version, if a ban file
+    /// is configured.
+    fn snapshot(&self, state: &mut State) -> Option<(u64, String)> {
+        let path = self.settings.ban_file.as_ref()?;
+        let mut file = BanFile::default();
+        for (target, entry) in state.failures.iter().filter(|(_, entry)| entry.locked) {
+            let until = entry
+                .retry_at
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_secs().max(1))
+                .unwrap_or(0);
+            match target {
+                Target::User(user) => file.users.insert(user.clone(), until),
+                Target::Address(address) => file.addresses.insert(address.to_string(), until),
+            };
+        }
+        match toml::to_string_pretty(&file) {
+            Ok(text) => {
+                state.version += 1;
+                Some((state.version, text))
+            }
+            Err(e) => {
+                eprintln!("cannot write ban file {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+    /// Writes a `snapshot()` to the ban file on the blocking thread pool, unless a
+    /// newer one was written already.
+    ///
+    /// A failure is only logged: the lockouts still hold until the server stops.
+    async fn save(&self, snapshot: Option<(u64, String)>) {
+        let (Some(path), Some((version, text))) = (self.settings.ban_file.clone(), snapshot) else {
+            return;
+        };
+        let saved = self.saved.clone();
+        let written = tokio::task::spawn_blocking(move || {
+            let mut saved = saved.lock().unwrap_or_else(|e| e.into_inner());
+            if *saved < version {
+                std::fs::write(&path, text)
+                    .map_err(|e| format!("cannot write ban file {}: {}", path.display(), e))?;
+                *saved = version;
+            }
+            Ok::<(), String>(())
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|written| written);
+        if let Err(e) = written {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// A login allowed by `LoginGuard::check()`, in progress until it is dropped.
+pub struct LoginAttempt {
+    guard: Arc<LoginGuard>,
+    targets: [Target; 2],
+}
+
+impl Drop for LoginAttempt {
+    fn drop(&mut self) {
+        let mut state = self.guard.state();
+        for target in &self.targets {
+            if let Some(pending) = state.pending.get_mut(target) {
+                *pending -= 1;
+                if *pending == 0 {
+                    state.pending.remove(target);
+                }
+            }
+        }
+    }
+}
+
+/// A locked out target with the end of its lockout (`None` for a permanent ban).
+type Ban = (Target, Option<SystemTime>);
+
+/// Reads the lockouts of a ban file.
+fn read_ban_file(path: &Path) -> Result<Vec<Ban>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read ban file {}: {}", path.display(), e))?;
+    let file: BanFile =
+        toml::from_str(&text).map_err(|e| format!("invalid ban file {}: {}", path.display(), e))?;
+    let until = |secs: u64| (secs != 0).then(|| UNIX_EPOCH + Duration::from_secs(secs));
+    let mut bans: Vec<Ban> = file
+        .users
+        .into_iter()
+        .map(|(user, secs)| (Target::User(user), until(secs)))
+        .collect();
+    for (address, secs) in file.addresses {
+        let address = address.parse().map_err(|_| {
+            format!(
+                "invalid ban file {}: {} is not an IP address",
+                path.display(),
+                address
+            )
+        })?;
+        bans.push((Target::Address(address), until(secs)));
+    }
+    Ok(bans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    fn guard(max_failures: u32, backoff: Duration, ban_file: Option<PathBuf>) -> Arc<LoginGuard> {
+        let settings = GuardSettings {
+            max_failures,
+            backoff,
+            lockout: Duration::from_secs(60),
+            ban_file,
+        };
+        Arc::new(LoginGuard::new(settings).unwrap())
+    }
+
+    #[test]
+    fn logins_in_progress_cannot_exceed_the_lockout() {
+        let guard = guard(3, Duration::ZERO, None);
+        let attempts: Vec<LoginAttempt> = ["alice", "bob", "carol"]
+            .iter()
+            .map(|user| guard.check(user, ADDRESS).unwrap())
+            .collect();
+        assert!(guard.check("dave", ADDRESS).is_err());
+        drop(attempts);
+        assert!(guard.check("dave", ADDRESS).is_ok());
+    }
+
+    #[tokio::test]
+    async fn logins_take_turns_once_a_backoff_applies() {
+        let guard = guard(5, Duration::from_millis(1), None);
+        let attempt = guard.check("alice", ADDRESS).unwrap();
+        guard.failed("alice", ADDRESS).await;
+        drop(attempt);
+        assert!(guard.check("alice", ADDRESS).is_err());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let attempt = guard.check("alice", ADDRESS).unwrap();
+        assert!(guard.check("alice", ADDRESS).is_err());
+        assert!(guard.check("bob", ADDRESS).is_err());
+        drop(attempt);
+        assert!(guard.check("bob", ADDRESS).is_ok());
+    }
+
+    #[tokio::test]
+    async fn lockouts_are_saved_and_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let ban_file = dir.path().join("bans.toml");
+        let first = guard(1, Duration::ZERO, Some(ban_file.clone()));
+        first.failed("alice", ADDRESS).await;
+        assert!(first.unlock(&Target::Address(ADDRESS)).await);
+        let second = guard(1, Duration::ZERO, Some(ban_file.clone()));
+        assert!(second.check("alice", ADDRESS).is_err());
+        assert!(second.check("bob", ADDRESS).is_ok());
+        assert_eq!(second.lockouts().len(), 1);
+    }
+}
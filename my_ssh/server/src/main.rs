@@ -1,20 +1,63 @@
+mod audit;
+mod client_auth;
 mod command_system;
+mod connection_limiter;
 mod login;
+mod login_guard;
 mod secure_shell_server;
 mod server_configure;
+mod session;
+mod session_registry;
+mod totp;
+mod user_store;
+use clap::Parser;
 use secure_shell_server::SecureShellServer;
+use user_store::{CachedUserStore, UserStore};
 
 #[tokio::main]
 async fn main() {
-    let server_config = server_configure::Configure::new();
+    let args = server_configure::Args::parse();
+    if let Some(user) = &args.enroll_totp {
+        let password_file =
+            server_configure::Settings::password_file(&args).unwrap_or_else(|e| panic!("{}", e));
+        let totp = totp::enroll(&password_file, user).unwrap_or_else(|e| panic!("{}", e));
+        println!("TOTP enabled for {}", user);
+        println!("Secret: {}", totp.secret_base32());
+        println!("{}", totp.provisioning_uri(user));
+        return;
+    }
+    if args.migrate_users {
+        let password_file =
+            server_configure::Settings::password_file(&args).unwrap_or_else(|e| panic!("{}", e));
+        let mut store = UserStore::load(&password_file).unwrap_or_else(|e| panic!("{}", e));
+        let backup = store.migrate().unwrap_or_else(|e| panic!("{}", e));
+        println!(
+            "Migrated {} to the TOML format, the original file is kept as {}",
+            password_file.display(),
+            backup.display()
+        );
+        return;
+    }
+    let settings = server_configure::Settings::load(args).unwrap_or_else(|e| panic!("{}", e));
+    let store =
+        CachedUserStore::load(settings.password_file.as_ref()).unwrap_or_else(|e| panic!("{}", e));
+    settings
+        .policy
+        .check_accounts(&*store.get().await)
+        .unwrap_or_else(|e| panic!("{}", e));
 
     let mut server = SecureShellServer::new(
-        &server_config.set_cert_path(),
-        &server_config.set_cert_key_path(),
-        "0.0.0.0:12345",
-        &server_config.set_working_directory(),
-        &server_config.set_password_file(),
-    );
+        &settings.cert_path,
+        &settings.key_path,
+        &settings.listen,
+        &settings.root,
+        store,
+        settings.limits,
+        settings.client_auth,
+    )
+    .with_policy(settings.policy)
+    .with_login_guard(settings.login_guard)
+    .with_audit_log(settings.audit_log);
     server
         .bind_and_listen()
         .await
@@ -1,14 +1,33 @@
-use crate::command_system::command_handler::CommandHandler;
-use crate::login::UserLogin;
+use crate::audit::AuditLog;
+use crate::client_auth::{CertificateLogin, ClientAuth, fingerprint};
+use crate::command_system::permissions::Policy;
+use crate::connection_limiter::{Admission, ConnectionLimiter};
+use crate::login_guard::LoginGuard;
+use crate::server_configure::Limits;
+use crate::session::{ClientSession, FINAL_WRITE_TIMEOUT};
+use crate::session_registry::SessionRegistry;
+use crate::user_store::CachedUserStore;
+use protocol::format::{Format, get_format};
+use protocol::frame::write_frame;
+use protocol::message::Response;
+use std::time::Duration;
 use std::{fs::File, io::BufReader, sync::Arc};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpListener;
-use tokio::sync::RwLock;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout};
 use tokio_rustls::{
     TlsAcceptor,
+    rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient},
     rustls::{Certificate, PrivateKey, ServerConfig},
+    server::TlsStream,
 };
 
+/// Pause after a failed `accept`, e.g. when the process is out of file descriptors,
+/// so the loop does not spin until connections are closed.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
 /// A secure shell server implementation using TLS encryption.
 ///
 /// `SecureShellServer` provides a TLS-encrypted remote shell service that allows
@@ -23,11 +42,13 @@ use tokio_rustls::{
 ///     "/path/to/key.pem",
 ///     "127.0.0.1:8443",
 ///     "/home/secure",
-///     "/etc/passwords.txt"
+///     CachedUserStore::load(Path::new("/etc/passwords.txt"))?,
+///     Limits::default(),
+///     None,
 /// );
 ///
 /// server.bind_and_listen().await?;
-/// server.accept_wait().await?;
+/// server.accept_wait().await?; // returns after SIGINT or SIGTERM
 /// ```
 pub struct SecureShellServer {
     certs: Vec<Certificate>,
@@ -36,8 +57,14 @@ pub struct SecureShellServer {
     listener: Option<TcpListener>,
     acceptor: Option<TlsAcceptor>,
     root_path: std::path::PathBuf,
-    password_path: std::path::PathBuf,
-    users: Arc<RwLock<Vec<String>>>,
+    user_store: Arc<CachedUserStore>,
+    sessions: Arc<SessionRegistry>,
+    connections: Arc<ConnectionLimiter>,
+    limits: Limits,
+    client_auth: Option<ClientAuth>,
+    policy: Arc<Policy>,
+    login_guard: Arc<LoginGuard>,
+    audit: Arc<AuditLog>,
 }
 
 impl SecureShellServer {
@@ -49,7 +76,9 @@ impl SecureShellServer {
     /// - `key_path`: Path to the private key file (PEM format)
     /// - `ip_port`: IP address and port to bind to (e.g., "127.0.0.1:8443")
     /// - `root`: Root directory path for user operations (sandboxing)
-    /// - `password_path`: Path to the password file for authentication
+    /// - `user_store`: The user database, shared by every session
+    /// - `limits`: Resource limits applied to every client session
+    /// - `client_auth`: Client certificate settings, when mutual TLS is enabled
     ///
     /// # Returns
     ///
@@ -69,7 +98,9 @@ impl SecureShellServer {
     ///     "/etc/ssl/private/server.key",
     ///     "0.0.0.0:8443",
     ///     "/home/secure_shell",
-    ///     "/etc/secure_shell/passwords"
+    ///     CachedUserStore::load(Path::new("/etc/secure_shell/passwords"))?,
+    ///     Limits::default(),
+    ///     None,
     /// );
     /// ```
     pub fn new(
@@ -77,7 +108,9 @@ impl SecureShellServer {
         key_path: &str,
         ip_port: &str,
         root: &str,
-        password_path: &str,
+        user_store: CachedUserStore,
+        limits: Limits,
+        client_auth: Option<ClientAuth>,
     ) -> Self {
         let certs = SecureShellServer::load_certs(cert_path)
             .unwrap_or_else(|e| panic!("Error: Certifcate {:?}", e));
@@ -90,10 +123,37 @@ impl SecureShellServer {
             listener: None,
             acceptor: None,
             root_path: std::path::PathBuf::from(root),
-            password_path: std::path::PathBuf::from(password_path),
-            users: Arc::new(RwLock::new(Vec::new())),
+            user_store: Arc::new(user_store),
+            sessions: Arc::new(SessionRegistry::default()),
+            connections: Arc::new(ConnectionLimiter::new(limits)),
+            limits,
+            client_auth,
+            policy: Arc::new(Policy::default()),
+            login_guard: Arc::new(LoginGuard::default()),
+            audit: Arc::new(AuditLog::default()),
         }
     }
+    /// Sets the roles that decide which commands each user may run.
+    ///
+    /// Without it, only the built-in roles of `Policy` are known.
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+    /// Sets the brute-force protection of the logins, shared by every session.
+    ///
+    /// Without it, the default `GuardSettings` are used and no ban file is kept.
+    pub fn with_login_guard(mut self, guard: LoginGuard) -> Self {
+        self.login_guard = Arc::new(guard);
+        self
+    }
+    /// Sets the audit log the login attempts of every session are recorded in.
+    ///
+    /// Without it, they are printed on the standard output.
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Arc::new(audit);
+        self
+    }
     /// Binds the server to the specified IP and port and prepares it for accepting connections.
     ///
     /// This method sets up the TLS configuration using the loaded certificates and private key,
@@ -121,10 +181,18 @@ impl SecureShellServer {
     /// }
     /// ```
     pub async fn bind_and_listen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let config = ServerConfig::builder()
-            .with_safe_defaults()
-            .with_no_client_auth()
-            .with_single_cert(self.certs.clone(), self.key.clone())?;
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_auth {
+            Some(client_auth) if client_auth.require_certificate => builder
+                .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(
+                    client_auth.load_roots()?,
+                )),
+            Some(client_auth) => builder.with_client_cert_verifier(
+                AllowAnyAnonymousOrAuthenticatedClient::new(client_auth.load_roots()?),
+            ),
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder.with_single_cert(self.certs.clone(), self.key.clone())?;
         self.acceptor = Some(TlsAcceptor::from(Arc::new(config)));
 
         self.listener = Some(TcpListener::bind(self.ip_port.clone()).await?);
@@ -132,7 +200,8 @@ impl SecureShellServer {
 
         Ok(())
     }
-        /// Accepts and handles incoming client connections in an infinite loop.
+    /// Accepts and handles incoming client connections until the server is asked
+    /// to stop.
     /// 
     /// This method continuously accepts new TCP connections, performs TLS handshakes,
     /// and spawns separate async tasks to handle each client session. Each client
@@ -140,7 +209,7 @@ impl SecureShellServer {
     /// 
     /// # Returns
     /// 
-    /// - `Ok(())` - This method runs indefinitely and should not return under normal operation
+    /// - `Ok(())` - The server shut down after SIGINT or SIGTERM (Ctrl-C on Windows)
     /// - `Err(Box<dyn std::error::Error>)` if a critical server error occurs
     /// 
     /// # Panics
@@ -150,9 +219,10 @@ impl SecureShellServer {
     /// 
     /// # Client Session Flow
     /// 
-    /// 1. Accept TCP connection and perform TLS handshake
+    /// 1. Accept TCP connection, within the connection limits, and perform TLS handshake
     /// 2. Send welcome message to client
-    /// 3. Wait for login command with username and password
+    /// 3. Wait for a login frame with username and password, at most
+    ///    `Limits::login_timeout` from the connection (TLS handshake included)
     /// 4. Authenticate user against password file
     /// 5. Process commands for authenticated users
     /// 6. Clean up user session on disconnect
@@ -167,11 +237,32 @@ impl SecureShellServer {
     /// server.accept_wait().await?;
     /// ```
     /// 
+    /// # Connection Limits
+    /// 
+    /// Every connection is counted by the `ConnectionLimiter` of the server until its
+    /// task ends. Connections over `Limits::max_connections` or
+    /// `Limits::max_connections_per_ip` get an error reply instead of the welcome
+    /// message once their TLS handshake is done; beyond `Limits::max_handshakes`
+    /// handshakes in progress, new sockets are closed at once. A failed `accept`,
+    /// e.g. out of file descriptors (`EMFILE`, `ENFILE`), is logged and retried
+    /// after `ACCEPT_ERROR_DELAY` instead of stopping the server.
+    /// 
+    /// # Shutdown
+    /// 
+    /// On SIGINT or SIGTERM the listener is closed and every session is told to
+    /// stop: each one finishes the command it is running, sends `SHUTDOWN_NOTICE`
+    /// to its client and closes. Sessions still running after
+    /// `Limits::shutdown_timeout`, or when a second signal arrives, are aborted
+    /// together with the programs they started. A summary is printed at the end.
+    /// 
     /// # Note
     /// 
-    /// Each client connection is handled in a separate async task, allowing
-    /// concurrent sessions from multiple users.
+    /// Each client connection is handled by a `ClientSession` in a separate async
+    /// task, allowing concurrent sessions from multiple users.
     pub async fn accept_wait(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut signals = ShutdownSignals::new()?;
+        let (shutdown, shutdown_receiver) = watch::channel(false);
+        let mut tasks = JoinSet::new();
         let listener = self
             .listener
             .as_mut()
@@ -181,109 +272,101 @@ impl SecureShellServer {
             .as_mut()
             .unwrap_or_else(|| panic!("error , acceptor not configured"));
 
-        loop {
-            let (stream, addr) = listener.accept().await?;
+        let signal = loop {
+            let (stream, addr) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        eprintln!("Accept Error: {}", e);
+                        sleep(ACCEPT_ERROR_DELAY).await;
+                        continue;
+                    }
+                },
+                Some(_) = tasks.join_next() => continue,
+                signal = signals.recv() => break signal,
+            };
+            let (mut permit, refusal) = match self.connections.admit(addr.ip()) {
+                Admission::Accepted(permit) => (permit, None),
+                Admission::Refused(permit, message) => (permit, Some(message)),
+                Admission::Dropped(reason) => {
+                    eprintln!("{} {}", addr, reason);
+                    continue;
+                }
+            };
             let acceptor = acceptor.clone();
             let root_path = self.root_path.clone();
-            let users = self.users.clone();
+            let sessions = self.sessions.clone();
+            let limits = self.limits;
+            let client_auth = self.client_auth.clone();
+            let policy = self.policy.clone();
+            let login_guard = self.login_guard.clone();
+            let audit = self.audit.clone();
+            let shutdown = shutdown_receiver.clone();
+            let connections = self.connections.clone();
 
-            let password_path = self.password_path.clone();
-            tokio::spawn(async move {
-                let mut tls_stream = match acceptor.accept(stream).await {
+            let user_store = self.user_store.clone();
+            tasks.spawn(async move {
+                let handshake = tls_handshake(&acceptor, stream, limits, shutdown.clone()).await;
+                permit.end_handshake();
+                let mut tls_stream = match handshake {
                     Ok(s) => s,
                     Err(e) => {
-                        eprintln!("TLS handshake failed: {:?}", e);
+                        eprintln!("{} {}", addr, e);
                         return;
                     }
                 };
+                if let Some(message) = refusal {
+                    println!("{} refused: {}", addr, message);
+                    refuse(&mut tls_stream, &message).await;
+                    return;
+                }
                 println!("Client TLS :connected {}", addr);
 
-                if let Err(e) = tls_stream
-                    .write_all("?&NWelcome\nThis is a secure shell , use >: login [USERNAME] [PASSWORD][-]".as_bytes())
-                    .await
-                {
-                    eprintln!("Write Error: {:?}", e);
-                }
-                let mut server_path = root_path.clone();
-                let mut user: Option<String> = None;
-                let mut buf = vec![0u8; 1024];
-                loop {
-                    match tls_stream.read(&mut buf).await {
-                        Ok(0) => {
-                            println!("client disconnected {}", addr);
-                            if user.is_some() {
-                                let mut vec_lock = users.write().await;
-                                vec_lock.retain(|u| u != user.as_ref().unwrap());
-                            }
-                            break;
-                        }
-                        Ok(n) => {
-                            let received = String::from_utf8_lossy(&buf[..n]);
-                            println!(
-                                "{}:{} sent: {}",
-                                user.clone().unwrap_or(String::from("")),
-                                addr,
-                                received.trim()
-                            );
-                            if user.is_none() {
-                                let login = UserLogin::new(
-                                    received.to_string(),
-                                    password_path.clone(),
-                                    users.clone(),
-                                );
-                                match login.get_login_status().await {
-                                    Ok(user_name) => {
-                                        dbg!("login succesful");
-                                        let mut vec_lock = users.write().await;
-                                        vec_lock.push(user_name.clone());
-                                        user = Some(user_name);
-                                        if let Err(err) = tls_stream
-                                            .write_all(
-                                                format!(
-                                                    "{}[-]:{}[-]\r\n\r\n",
-                                                    "?&NSuccesful login", ""
-                                                )
-                                                .as_bytes(),
-                                            )
-                                            .await
-                                        {
-                                            eprintln!("Write Error: {:?}", err);
-                                        }
-                                    }
-                                    Err(e) => {
-                                        //dbg!(&e);
-                                        if let Err(err) = tls_stream.write_all(e.as_bytes()).await {
-                                            eprintln!("Write Error: {:?}", err);
-                                        }
-                                    }
-                                }
-                            } else {
-                                let mut command_handler = CommandHandler::new(
-                                    received.to_string(),
-                                    root_path.clone(),
-                                    server_path.clone(),
-                                    users.clone(),
-                                );
-                                let (reply, new_server_path) = command_handler.get_output().await;
-                                server_path = new_server_path;
-                                //dbg!(&reply);
-                                if let Err(e) = tls_stream.write_all(reply.as_bytes()).await {
-                                    eprintln!("Write Error: {:?}", e);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Read Error: {:?}", e);
-                            let mut vec_lock = users.write().await;
-                            if let Some(ref username) = user {
-                                vec_lock.retain(|u| u != username);
-                            }
-                            break;
-                        }
-                    }
+                let peer_certificates = tls_stream.get_ref().1.peer_certificates();
+                if let Some(cert) = peer_certificates.and_then(|chain| chain.first()) {
+                    println!("{} client certificate {}", addr, fingerprint(cert));
                 }
+                let certificate = match &client_auth {
+                    Some(client_auth) => client_auth.certificate_login(peer_certificates),
+                    None => CertificateLogin::default(),
+                };
+
+                let mut session =
+                    ClientSession::new(addr, root_path, user_store, sessions, limits, certificate)
+                        .with_policy(policy)
+                        .with_login_guard(login_guard)
+                        .with_audit_log(audit)
+                        .with_connections(connections)
+                        .with_shutdown(shutdown);
+                session.run(&mut tls_stream).await;
+                drop(permit);
             });
+        };
+        self.listener = None;
+        let open = tasks.len();
+        let logged_in = self.sessions.sessions().len();
+        println!(
+            "Received {}, shutting down: closing {} connections ({} logged in)",
+            signal, open, logged_in
+        );
+        let _ = shutdown.send(true);
+
+        let started = std::time::Instant::now();
+        let wait = self.limits.shutdown_timeout;
+        let drain = async { while tasks.join_next().await.is_some() {} };
+        tokio::select! {
+            _ = timeout(wait, drain) => {}
+            signal = signals.recv() => println!("Received {} again, not waiting", signal),
         }
+        let aborted = tasks.len();
+        tasks.shutdown().await;
+        println!(
+            "Server stopped after {} s: {} connections closed, {} aborted",
+            started.elapsed().as_secs(),
+            open - aborted,
+            aborted
+        );
+        Ok(())
     }
     fn load_certs(path: &str) -> Result<Vec<Certificate>, Box<dyn std::error::Error>> {
         let certfile = File::open(path)?;
@@ -305,3 +388,88 @@ impl SecureShellServer {
         Ok(PrivateKey(keys[0].clone()))
     }
 }
+
+/// Completes the TLS handshake of a new connection.
+///
+/// The handshake counts towards `Limits::login_timeout` and is abandoned when the
+/// server shuts down, so a client that never finishes it cannot hold a task.
+///
+/// # Returns
+///
+/// - `Ok(TlsStream)`: The encrypted stream of the client
+/// - `Err(String)`: Why the handshake did not complete, for the server log
+async fn tls_handshake(
+    acceptor: &TlsAcceptor,
+    stream: TcpStream,
+    limits: Limits,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<TlsStream<TcpStream>, String> {
+    let handshake = async {
+        let accepted = match limits.login_timeout {
+            Some(login_timeout) => timeout(login_timeout, acceptor.accept(stream))
+                .await
+                .map_err(|_| "TLS handshake timed out".to_string())?,
+            None => acceptor.accept(stream).await,
+        };
+        accepted.map_err(|e| format!("TLS handshake failed: {:?}", e))
+    };
+    tokio::select! {
+        accepted = handshake => accepted,
+        true = async { shutdown.wait_for(|down| *down).await.is_ok() } => {
+            Err("TLS handshake interrupted by the shutdown".to_string())
+        }
+    }
+}
+
+/// Tells a client that its connection is refused, then closes the stream.
+///
+/// The message takes the place of the welcome message, so clients report it
+/// instead of starting a session.
+async fn refuse(stream: &mut TlsStream<TcpStream>, message: &str) {
+    let response = Response::new(format!("{}{}", get_format(Format::Error), message), "");
+    let _ = timeout(FINAL_WRITE_TIMEOUT, async {
+        write_frame(stream, response.encode().as_bytes()).await?;
+        stream.shutdown().await
+    })
+    .await;
+}
+
+/// The signals that stop the server: SIGINT and SIGTERM, or Ctrl-C on Windows.
+///
+/// The handlers are installed once, so a signal arriving while the server is busy
+/// is not lost.
+struct ShutdownSignals {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl ShutdownSignals {
+    #[cfg(unix)]
+    fn new() -> std::io::Result<Self> {
+        use tokio::signal::unix::{SignalKind, signal};
+        Ok(ShutdownSignals {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+    #[cfg(not(unix))]
+    fn new() -> std::io::Result<Self> {
+        Ok(ShutdownSignals {})
+    }
+    /// Waits for the next signal and returns its name.
+    #[cfg(unix)]
+    async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.interrupt.recv() => "SIGINT",
+            _ = self.terminate.recv() => "SIGTERM",
+        }
+    }
+    /// Waits for the next signal and returns its name.
+    #[cfg(not(unix))]
+    async fn recv(&mut self) -> &'static str {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
@@ -1,3 +1,508 @@
+use crate::audit::AuditLog;
+use crate::client_auth::ClientAuth;
+use crate::command_system::permissions::{Policy, RolePolicy};
+use crate::login_guard::{GuardSettings, LoginGuard};
+use clap::Parser;
+use protocol::frame::MAX_FRAME_LEN;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Address the server listens on when none is configured.
+pub const DEFAULT_ADDRESS: &str = "0.0.0.0";
+/// Port the server listens on when none is configured.
+pub const DEFAULT_PORT: u16 = 12345;
+/// Time a client has to log in after connecting, when none is configured.
+pub const DEFAULT_LOGIN_TIMEOUT: Duration = Duration::from_secs(120);
+/// Interval of the keepalive pings asked of the clients, when none is configured.
+pub const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(30);
+/// Time running commands get to finish when the server shuts down, when none is
+/// configured.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Connections served at once, when no limit is configured.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
+/// Connections served at once from one source address, when no limit is configured.
+pub const DEFAULT_MAX_CONNECTIONS_PER_IP: usize = 16;
+/// TLS handshakes in progress at once, when no limit is configured.
+pub const DEFAULT_MAX_HANDSHAKES: usize = 32;
+
+/// Command-line flags of the server.
+///
+/// Every flag overrides the matching entry of the configuration file. Paths given
+/// neither on the command line nor in the file are asked for on stdin, so running
+/// the server without any argument keeps the interactive setup.
+///
+/// # Examples
+///
+/// ```text
+/// server --config /etc/secure_shell/server.toml
+/// server --port 2222 --cert cert.pem --key key.pem --root /srv/shell --password-file passwords
+/// server --config server.toml --client-ca client-ca.pem --require-client-cert
+/// server --config server.toml --audit-log /var/log/secure_shell/audit.log
+/// server --config server.toml --login-timeout 60 --idle-timeout 900
+/// server --config server.toml --shutdown-timeout 5
+/// server --config server.toml --max-connections 100 --max-connections-per-ip 4
+/// server --config server.toml --enroll-totp alice
+/// server --password-file passwords --migrate-users
+/// ```
+#[derive(Parser, Debug)]
+#[command(name = "server", version, about = "TLS secure shell server", long_about = None)]
+pub struct Args {
+    /// TOML configuration file
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Address to listen on [default: 0.0.0.0]
+    #[arg(short, long)]
+    pub address: Option<String>,
+    /// Port to listen on [default: 12345]
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    /// TLS certificate file (PEM)
+    #[arg(long)]
+    pub cert: Option<PathBuf>,
+    /// TLS private key file (PKCS#8 PEM)
+    #[arg(long)]
+    pub key: Option<PathBuf>,
+    /// Root directory exposed to the users
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+    /// Password file used to authenticate the users
+    #[arg(long)]
+    pub password_file: Option<PathBuf>,
+    /// Largest request accepted from a client, in bytes
+    #[arg(long)]
+    pub max_frame_len: Option<usize>,
+    /// Seconds a client has to log in, 0 for no limit [default: 120]
+    #[arg(long, value_name = "SECS")]
+    pub login_timeout: Option<u64>,
+    /// Seconds without a request before a session is closed, 0 for no limit [default: 0]
+    #[arg(long, value_name = "SECS")]
+    pub idle_timeout: Option<u64>,
+    /// Seconds running commands get to finish on SIGINT/SIGTERM [default: 30]
+    #[arg(long, value_name = "SECS")]
+    pub shutdown_timeout: Option<u64>,
+    /// Connections served at once, 0 for no limit [default: 256]
+    #[arg(long)]
+    pub max_connections: Option<usize>,
+    /// Connections served at once from one IP address, 0 for no limit [default: 16]
+    #[arg(long)]
+    pub max_connections_per_ip: Option<usize>,
+    /// TLS handshakes in progress at once, 0 for no limit [default: 32]
+    #[arg(long)]
+    pub max_handshakes: Option<usize>,
+    /// CA bundle for client certificates (PEM); enables mutual TLS
+    #[arg(long)]
+    pub client_ca: Option<PathBuf>,
+    /// Refuse connections without a client certificate
+    #[arg(long)]
+    pub require_client_cert: bool,
+    /// Require the password in addition to the client certificate
+    #[arg(long)]
+    pub require_password: bool,
+    /// File the login attempts are recorded in [default: standard output]
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+    /// Enable TOTP for USER in the password file, print the provisioning URI and exit
+    #[arg(long, value_name = "USER")]
+    pub enroll_totp: Option<String>,
+    /// Convert a legacy password file to the TOML user database and exit
+    #[arg(long, conflicts_with = "enroll_totp")]
+    pub migrate_users: bool,
+}
+
+/// Contents of the TOML configuration file.
+///
+/// All entries are optional; relative paths are resolved against the directory of
+/// the configuration file.
+///
+/// # Examples
+///
+/// ```toml
+/// address = "0.0.0.0"
+/// port = 12345
+/// cert = "certs/server.crt"
+/// key = "certs/server.pk8"
+/// root = "/srv/secure_shell"
+/// password_file = "passwords"
+/// audit_log = "audit.log"
+///
+/// [limits]
+/// max_frame_len = 1048576
+/// login_timeout_secs = 120
+/// idle_timeout_secs = 900
+/// keepalive_secs = 30
+/// shutdown_timeout_secs = 30
+/// max_connections = 256
+/// max_connections_per_ip = 16
+/// max_handshakes = 32
+///
+/// [client_auth]
+/// ca = "certs/client-ca.crt"
+/// require_certificate = false
+/// require_password = false
+///
+/// [client_auth.users]
+/// "5f:3a:...:9c" = "alice"
+///
+/// [roles.operator]
+/// commands = ["ls", "cat", "cd", "pwd", "mkdir", "mv"]
+/// redirect = true
+/// execute = false
+///
+/// [login_guard]
+/// max_failures = 5
+/// backoff_secs = 1
+/// lockout_secs = 900
+/// ban_file = "bans.toml"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub root: Option<PathBuf>,
+    pub password_file: Option<PathBuf>,
+    pub audit_log: Option<PathBuf>,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub client_auth: ClientAuthConfig,
+    #[serde(default)]
+    pub roles: HashMap<String, RolePolicy>,
+    #[serde(default)]
+    pub login_guard: LoginGuardConfig,
+}
+
+/// The `[limits]` table of the configuration file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_frame_len: Option<usize>,
+    pub login_timeout_secs: Option<u64>,
+    pub idle_timeout_secs: Option<u64>,
+    pub keepalive_secs: Option<u64>,
+    pub shutdown_timeout_secs: Option<u64>,
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    pub max_handshakes: Option<usize>,
+}
+
+/// The `[client_auth]` table of the configuration file.
+///
+/// `users` maps SHA-256 certificate fingerprints to user names; certificates that
+/// are not listed are mapped through the common name of their subject.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ClientAuthConfig {
+    pub ca: Option<PathBuf>,
+    pub require_certificate: Option<bool>,
+    pub require_password: Option<bool>,
+    #[serde(default)]
+    pub users: HashMap<String, String>,
+}
+
+/// The `[login_guard]` table of the configuration file.
+///
+/// The ban file is only kept when `ban_file` is set.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LoginGuardConfig {
+    pub max_failures: Option<u32>,
+    pub backoff_secs: Option<u64>,
+    pub lockout_secs: Option<u64>,
+    pub ban_file: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Reads and parses the configuration file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or is not a valid configuration.
+    pub fn read(path: &Path) -> Result<ConfigFile, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file {}: {}", path.display(), e))?;
+        let mut config: ConfigFile = toml::from_str(&text)
+            .map_err(|e| format!("invalid config file {}: {}", path.display(), e))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for entry in [
+            &mut config.cert,
+            &mut config.key,
+            &mut config.root,
+            &mut config.password_file,
+            &mut config.audit_log,
+            &mut config.client_auth.ca,
+            &mut config.login_guard.ban_file,
+        ] {
+            if let Some(p) = entry.as_mut() {
+                *p = base.join(&p);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Resource limits applied to every client session.
+///
+/// - `max_frame_len`: Largest request frame accepted from a client
+/// - `login_timeout`: Time a client has to complete the TLS handshake and log in
+/// - `idle_timeout`: Time without a request after which a session is closed
+/// - `keepalive`: Interval of the pings asked of clients that negotiated
+///   `Capability::Keepalive`; they are dropped after `KEEPALIVE_MISSES` missed pings
+/// - `shutdown_timeout`: Time the sessions get to finish their running command when
+///   the server shuts down, before they are aborted
+/// - `max_connections`: Connections served at once
+/// - `max_connections_per_ip`: Connections served at once from one source address
+/// - `max_handshakes`: TLS handshakes in progress at once
+///
+/// `None` disables the matching timeout or limit.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_frame_len: usize,
+    pub login_timeout: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+    pub keepalive: Option<Duration>,
+    pub shutdown_timeout: Duration,
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    pub max_handshakes: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_frame_len: MAX_FRAME_LEN,
+            login_timeout: Some(DEFAULT_LOGIN_TIMEOUT),
+            idle_timeout: None,
+            keepalive: Some(DEFAULT_KEEPALIVE),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            max_connections: Some(DEFAULT_MAX_CONNECTIONS),
+            max_connections_per_ip: Some(DEFAULT_MAX_CONNECTIONS_PER_IP),
+            max_handshakes: Some(DEFAULT_MAX_HANDSHAKES),
+        }
+    }
+}
+
+/// The resolved server configuration.
+///
+/// Each setting is taken from the command line, then from the configuration file,
+/// then from its default value. The certificate, key, root directory and password
+/// file have no default: when they are missing the matching `Configure` prompt is
+/// shown instead.
+///
+/// # Examples
+///
+/// ```rust
+/// let settings = Settings::load(Args::parse())?;
+/// let server = SecureShellServer::new(
+///     &settings.cert_path,
+///     &settings.key_path,
+///     &settings.listen,
+///     &settings.root,
+///     CachedUserStore::load(settings.password_file.as_ref())?,
+///     settings.limits,
+///     settings.client_auth,
+/// )
+/// .with_policy(settings.policy)
+/// .with_login_guard(settings.login_guard)
+/// .with_audit_log(settings.audit_log);
+/// ```
+pub struct Settings {
+    pub listen: String,
+    pub cert_path: String,
+    pub key_path: String,
+    pub root: String,
+    pub password_file: String,
+    pub limits: Limits,
+    pub client_auth: Option<ClientAuth>,
+    pub policy: Policy,
+    pub login_guard: LoginGuard,
+    pub audit_log: AuditLog,
+}
+
+impl Settings {
+    /// Builds the configuration from the parsed arguments and the configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the configuration file is invalid, a configured path does not exist,
+    /// a limit is out of range, a client certificate option is set without a
+    /// client CA, a role lists an unknown command, the ban file is invalid or the
+    /// audit log cannot be opened.
+    pub fn load(args: Args) -> Result<Settings, Box<dyn Error>> {
+        let file = match &args.config {
+            Some(path) => ConfigFile::read(path)?,
+            None => ConfigFile::default(),
+        };
+        Settings::resolve(args, file)
+    }
+    /// Returns the password file given on the command line or in the configuration
+    /// file, for the administration flags that only need that file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the configuration file is invalid or no password file is configured.
+    pub fn password_file(args: &Args) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(path) = &args.password_file {
+            return Ok(path.clone());
+        }
+        let file = match &args.config {
+            Some(path) => ConfigFile::read(path)?,
+            None => ConfigFile::default(),
+        };
+        file.password_file
+            .ok_or("a password file is required (--password-file or --config)".into())
+    }
+    fn resolve(args: Args, file: ConfigFile) -> Result<Settings, Box<dyn Error>> {
+        let prompt = Configure::new();
+        let cert_path = match args.cert.or(file.cert) {
+            Some(path) => check_path(path, "cert", false)?,
+            None => prompt.set_cert_path(),
+        };
+        let key_path = match args.key.or(file.key) {
+            Some(path) => check_path(path, "key", false)?,
+            None => prompt.set_cert_key_path(),
+        };
+        let root = match args.root.or(file.root) {
+            Some(path) => check_path(path, "root", true)?,
+            None => prompt.set_working_directory(),
+        };
+        let password_file = match args.password_file.or(file.password_file) {
+            Some(path) => check_path(path, "password file", false)?,
+            None => prompt.set_password_file(),
+        };
+        let address = args
+            .address
+            .or(file.address)
+            .unwrap_or(DEFAULT_ADDRESS.to_string());
+        let port = args.port.or(file.port).unwrap_or(DEFAULT_PORT);
+        let listen = if address.contains(':') {
+            format!("[{}]:{}", address, port)
+        } else {
+            format!("{}:{}", address, port)
+        };
+        let max_frame_len = args
+            .max_frame_len
+            .or(file.limits.max_frame_len)
+            .unwrap_or(MAX_FRAME_LEN);
+        if max_frame_len == 0 || max_frame_len > MAX_FRAME_LEN {
+            return Err(format!("max_frame_len must be between 1 and {}", MAX_FRAME_LEN).into());
+        }
+        let defaults = Limits::default();
+        let timeout = |secs: Option<u64>, default: Option<Duration>| match secs {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => default,
+        };
+        let cap = |max: Option<usize>, default: Option<usize>| match max {
+            Some(0) => None,
+            Some(max) => Some(max),
+            None => default,
+        };
+        let limits = Limits {
+            max_frame_len,
+            login_timeout: timeout(
+                args.login_timeout.or(file.limits.login_timeout_secs),
+                defaults.login_timeout,
+            ),
+            idle_timeout: timeout(
+                args.idle_timeout.or(file.limits.idle_timeout_secs),
+                defaults.idle_timeout,
+            ),
+            keepalive: timeout(file.limits.keepalive_secs, defaults.keepalive),
+            shutdown_timeout: args
+                .shutdown_timeout
+                .or(file.limits.shutdown_timeout_secs)
+                .map_or(defaults.shutdown_timeout, Duration::from_secs),
+            max_connections: cap(
+                args.max_connections.or(file.limits.max_connections),
+                defaults.max_connections,
+            ),
+            max_connections_per_ip: cap(
+                args.max_connections_per_ip
+                    .or(file.limits.max_connections_per_ip),
+                defaults.max_connections_per_ip,
+            ),
+            max_handshakes: cap(
+                args.max_handshakes.or(file.limits.max_handshakes),
+                defaults.max_handshakes,
+            ),
+        };
+        let require_certificate =
+            args.require_client_cert || file.client_auth.require_certificate.unwrap_or(false);
+        let require_password =
+            args.require_password || file.client_auth.require_password.unwrap_or(false);
+        let client_auth = match args.client_ca.or(file.client_auth.ca) {
+            Some(path) => Some(ClientAuth::new(
+                &check_path(path, "client CA", false)?,
+                require_certificate,
+                require_password,
+                file.client_auth.users,
+            )),
+            None if require_certificate || require_password => {
+                return Err("client certificate options require a client CA".into());
+            }
+            None => None,
+        };
+        let policy = Policy::new(file.roles)?;
+        let login_guard = LoginGuard::new(guard_settings(file.login_guard)?)?;
+        let audit_log = match args.audit_log.or(file.audit_log) {
+            Some(path) => AuditLog::open(&path)?,
+            None => AuditLog::default(),
+        };
+        Ok(Settings {
+            listen,
+            cert_path,
+            key_path,
+            root,
+            password_file,
+            limits,
+            client_auth,
+            policy,
+            login_guard,
+            audit_log,
+        })
+    }
+}
+
+/// Builds the `GuardSettings` from the `[login_guard]` table, with the defaults of
+/// the missing entries.
+fn guard_settings(config: LoginGuardConfig) -> Result<GuardSettings, Box<dyn Error>> {
+    let defaults = GuardSettings::default();
+    let settings = GuardSettings {
+        max_failures: config.max_failures.unwrap_or(defaults.max_failures),
+        backoff: config
+            .backoff_secs
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.backoff),
+        lockout: config
+            .lockout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.lockout),
+        ban_file: config.ban_file,
+    };
+    if settings.max_failures == 0 {
+        return Err("max_failures must be at least 1".into());
+    }
+    if settings.lockout.is_zero() {
+        return Err("lockout_secs must be at least 1".into());
+    }
+    Ok(settings)
+}
+
+/// Checks that a configured path exists and is a directory (`dir`) or a file.
+fn check_path(path: PathBuf, name: &str, dir: bool) -> Result<String, Box<dyn Error>> {
+    let valid = if dir { path.is_dir() } else { path.is_file() };
+    if !valid {
+        return Err(format!("invalid {} path: {}", name, path.display()).into());
+    }
+    Ok(path.to_string_lossy().to_string())
+}
+
 pub struct Configure {}
 impl Configure {
     pub fn new() -> Self {
@@ -0,0 +1,681 @@
+use crate::audit::AuditLog;
+use crate::client_auth::CertificateLogin;
+use crate::command_system::command_handler::CommandHandler;
+use crate::command_system::completion::Completion;
+use crate::command_system::permissions::{Permissions, Policy};
+use crate::connection_limiter::ConnectionLimiter;
+use crate::login::UserLogin;
+use crate::login_guard::LoginGuard;
+use crate::server_configure::Limits;
+use crate::session_registry::{Registration, SessionId, SessionInfo, SessionRegistry};
+use crate::user_store::{Account, CachedUserStore};
+use protocol::auth::{Challenge, KeyLogin, decode_base64};
+use protocol::complete::{CompletionRequest, Completions};
+use protocol::format::{Format, get_format};
+use protocol::frame::{read_frame_limited, write_frame};
+use protocol::handshake::{Capability, Hello};
+use protocol::json::CommandResult;
+use protocol::message::{Request, Response, ResponseMode};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{Notify, watch};
+use tokio::time::{Instant, sleep_until, timeout};
+
+/// Number of random bytes in a public key login challenge.
+const CHALLENGE_LEN: usize = 32;
+/// Keepalive pings a client may miss before its connection is considered dead.
+pub const KEEPALIVE_MISSES: u32 = 3;
+/// Longest time spent sending the last message of a session that is closed by the
+/// server, so a dead peer cannot hold the session open.
+pub const FINAL_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Last message sent to the clients when the server shuts down.
+pub const SHUTDOWN_NOTICE: &str = "Server shutting down";
+/// Last message sent to a client whose session is taken over by a new login.
+pub const TAKEOVER_NOTICE: &str = "Session taken over by a new login";
+
+/// Why a session is closed by the server.
+///
+/// # Variants
+///
+/// - `Login`: The client did not log in within `Limits::login_timeout`
+/// - `Idle`: The user sent no request for `Limits::idle_timeout`
+/// - `Keepalive`: The client stopped sending the pings it negotiated
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Expiry {
+    Login,
+    Idle,
+    Keepalive,
+}
+
+/// The state of a single client connection after the TLS handshake.
+///
+/// `ClientSession` owns everything that belongs to one connected client: the
+/// authenticated user with its home, current directory and permissions, the
+/// negotiated capabilities, the response mode and what its client certificate
+/// allows at login.
+/// It reads request frames from the stream, routes them to the login system or
+/// the command system and writes exactly one reply frame per request.
+///
+/// # Session Flow
+///
+/// 1. Send the welcome message
+/// 2. Answer the client `Hello`, if it sends one, with the server `Hello`
+/// 3. Answer `login [USERNAME] [PASSWORD]` (or `login [USERNAME]` with a client
+///    certificate) requests and public key logins until a login succeeds, while the
+///    `LoginGuard` slows down and locks out repeated failures
+/// 4. Create the home directory of the user if needed and register in the
+///    `SessionRegistry`, within the `SessionLimit` of the account
+/// 5. Move into the home directory
+/// 6. Execute the commands the role of the user allows, inside their home if the
+///    account is `confined`, until the client disconnects or a login of the same
+///    user takes the session over
+/// 7. Remove the session from the `SessionRegistry`, which its `Registration` also
+///    does if the session task panics
+///
+/// # Examples
+///
+/// ```rust
+/// let mut tls_stream = acceptor.accept(stream).await?;
+/// let mut session = ClientSession::new(addr, root_path, user_store, sessions, limits, certificate);
+/// session.run(&mut tls_stream).await;
+/// ```
+pub struct ClientSession {
+    id: SessionId,
+    addr: SocketAddr,
+    root_path: PathBuf,
+    user_store: Arc<CachedUserStore>,
+    sessions: Arc<SessionRegistry>,
+    registration: Option<Registration>,
+    close: Arc<Notify>,
+    shutdown: watch::Receiver<bool>,
+    limits: Limits,
+    certificate: CertificateLogin,
+    server_path: PathBuf,
+    home: PathBuf,
+    confined: bool,
+    policy: Arc<Policy>,
+    permissions: Permissions,
+    login_guard: Arc<LoginGuard>,
+    connections: Option<Arc<ConnectionLimiter>>,
+    audit: Arc<AuditLog>,
+    current_path: String,
+    connected_at: Instant,
+    last_request: Instant,
+    last_frame: Instant,
+    user: Option<String>,
+    mode: ResponseMode,
+    capabilities: Vec<Capability>,
+    client_version: Option<String>,
+    challenge: Option<(String, Vec<u8>)>,
+}
+
+impl ClientSession {
+    /// Creates the session of a newly connected client.
+    ///
+    /// # Parameters
+    ///
+    /// - `addr`: Address of the connected peer, used in server logs
+    /// - `root_path`: Root directory for user operations (sandboxing)
+    /// - `user_store`: The user database shared by all sessions
+    /// - `sessions`: The logged in sessions of the server, which gives this one its
+    ///   identifier
+    /// - `limits`: Resource limits configured for the server
+    /// - `certificate`: What the client certificate of the connection allows at login
+    pub fn new(
+        addr: SocketAddr,
+        root_path: PathBuf,
+        user_store: Arc<CachedUserStore>,
+        sessions: Arc<SessionRegistry>,
+        limits: Limits,
+        certificate: CertificateLogin,
+    ) -> Self {
+        ClientSession {
+            id: sessions.new_id(),
+            addr,
+            server_path: root_path.clone(),
+            home: root_path.clone(),
+            confined: false,
+            policy: Arc::new(Policy::default()),
+            permissions: Permissions::default(),
+            login_guard: Arc::new(LoginGuard::default()),
+            connections: None,
+            audit: Arc::new(AuditLog::default()),
+            root_path,
+            user_store,
+            sessions,
+            registration: None,
+            close: Arc::new(Notify::new()),
+            shutdown: watch::channel(false).1,
+            limits,
+            certificate,
+            current_path: String::new(),
+            connected_at: Instant::now(),
+            last_request: Instant::now(),
+            last_frame: Instant::now(),
+            user: None,
+            mode: ResponseMode::default(),
+            capabilities: Capability::LEGACY.to_vec(),
+            client_version: None,
+            challenge: None,
+        }
+    }
+    /// Sets the roles that decide which commands the user may run.
+    ///
+    /// Without it, only the built-in roles of `Policy` are known.
+    pub fn with_policy(mut self, policy: Arc<Policy>) -> Self {
+        self.policy = policy;
+        self
+    }
+    /// Sets the `LoginGuard` shared by all sessions, which counts the failed logins.
+    ///
+    /// Without it, the session counts the failures of its own connection only.
+    pub fn with_login_guard(mut self, guard: Arc<LoginGuard>) -> Self {
+        self.login_guard = guard;
+        self
+    }
+    /// Sets the audit log the login attempts are recorded in.
+    ///
+    /// Without it, they are printed on the standard output.
+    pub fn with_audit_log(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = audit;
+        self
+    }
+    /// Sets the `ConnectionLimiter` of the server, shown by the `connections` command.
+    pub fn with_connections(mut self, limiter: Arc<ConnectionLimiter>) -> Self {
+        self.connections = Some(limiter);
+        self
+    }
+    /// Sets the signal of a server shutdown: once it turns `true`, the session sends
+    /// `SHUTDOWN_NOTICE` and closes as soon as it is not running a command.
+    ///
+    /// Without it, the session is only ended by the client or its timeouts.
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+    /// Serves the client until it disconnects, the stream fails, the session is
+    /// taken over by another login of the same user, one of its timeouts expires or
+    /// the server shuts down.
+    ///
+    /// Every message in both directions is a length-prefixed frame (see
+    /// `protocol::frame`), so a request is always processed as a whole regardless
+    /// of how TCP splits it. A request larger than `Limits::max_frame_len` ends the
+    /// session.
+    ///
+    /// The timeouts of `Limits` are checked while waiting for a request: the client
+    /// gets a last error message, then the stream is closed. Keepalive pings keep a
+    /// connection alive but do not count as activity for the idle timeout.
+    ///
+    /// A shutdown never interrupts a request: the command running when it starts
+    /// is answered first, then the client gets `SHUTDOWN_NOTICE`.
+    /// A session taken over is closed the same way, with `TAKEOVER_NOTICE`.
+    pub async fn run<S>(&mut self, stream: &mut S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let welcome = Response::new(
+            format!(
+                "{}Welcome\nThis is a secure shell , use >: login [USERNAME] [PASSWORD]",
+                get_format(Format::Normal)
+            ),
+            "",
+        );
+        if let Err(e) = write_frame(stream, welcome.encode().as_bytes()).await {
+            eprintln!("Write Error: {:?}", e);
+        }
+        let close = self.close.clone();
+        let mut shutdown = self.shutdown.clone();
+        loop {
+            let deadline = self.deadline();
+            let frame = tokio::select! {
+                biased;
+                true = async { shutdown.wait_for(|down| *down).await.is_ok() } => {
+                    self.close_with(stream, SHUTDOWN_NOTICE).await;
+                    break;
+                }
+                _ = close.notified() => {
+                    self.close_with(stream, TAKEOVER_NOTICE).await;
+                    break;
+                }
+                expiry = ClientSession::expire_at(deadline) => {
+                    self.close_expired(stream, expiry).await;
+                    break;
+                }
+                frame = read_frame_limited(stream, self.limits.max_frame_len) => frame,
+            };
+            match frame {
+                Ok(None) => {
+                    println!("client disconnected {}", self.addr);
+                    break;
+                }
+                Ok(Some(frame)) => {
+                    let received = String::from_utf8_lossy(&frame).to_string();
+                    let ping = matches!(Request::decode(&received), Ok(Request::Ping));
+                    self.last_frame = Instant::now();
+                    if !ping {
+                        self.last_request = self.last_frame;
+                    }
+                    let reply = self.handle_request(received).await;
+                    if !ping {
+                        self.touch();
+                    }
+                    if let Err(e) = write_frame(stream, reply.as_bytes()).await {
+                        eprintln!("Write Error: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Read Error: {:?}", e);
+                    break;
+                }
+            }
+        }
+        self.logout();
+    }
+    /// Returns the first timeout of the session that can expire now, with the time
+    /// it expires at.
+    ///
+    /// The login timeout runs from the connection until the login; the idle timeout
+    /// from the last request of a logged in user; the keepalive timeout from the
+    /// last frame, once `Capability::Keepalive` was negotiated.
+    fn deadline(&self) -> Option<(Instant, Expiry)> {
+        let mut deadlines = Vec::new();
+        match (
+            &self.user,
+            self.limits.login_timeout,
+            self.limits.idle_timeout,
+        ) {
+            (None, Some(login), _) => deadlines.push((self.connected_at + login, Expiry::Login)),
+            (Some(_), _, Some(idle)) => deadlines.push((self.last_request + idle, Expiry::Idle)),
+            _ => {}
+        }
+        if let Some(keepalive) = self.limits.keepalive
+            && self.capabilities.contains(&Capability::Keepalive)
+        {
+            let missed = keepalive.saturating_mul(KEEPALIVE_MISSES);
+            deadlines.push((self.last_frame + missed, Expiry::Keepalive));
+        }
+        deadlines.into_iter().min_by_key(|(time, _)| *time)
+    }
+    /// Waits until `deadline` and returns why it ends the session; never returns
+    /// without one.
+    async fn expire_at(deadline: Option<(Instant, Expiry)>) -> Expiry {
+        match deadline {
+            Some((time, expiry)) => {
+                sleep_until(time).await;
+                expiry
+            }
+            None => std::future::pending().await,
+        }
+    }
+    /// Closes the session because of `expiry`, telling the client why.
+    async fn close_expired<S>(&self, stream: &mut S, expiry: Expiry)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let secs = |limit: Option<Duration>| limit.unwrap_or_default().as_secs();
+        let message = match expiry {
+            Expiry::Login => format!(
+                "Login timeout: no login within {} s",
+                secs(self.limits.login_timeout)
+            ),
+            Expiry::Idle => format!(
+                "Session closed: idle for {} s",
+                secs(self.limits.idle_timeout)
+            ),
+            Expiry::Keepalive => format!(
+                "Session closed: no keepalive for {} s",
+                secs(self.limits.keepalive) * u64::from(KEEPALIVE_MISSES)
+            ),
+        };
+        self.close_with(stream, &message).await;
+    }
+    /// Sends `message` to the client as a last error message and closes the stream.
+    ///
+    /// The message is written to the audit log when a user is logged in.
+    async fn close_with<S>(&self, stream: &mut S, message: &str)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        println!("{} {}", self.addr, message);
+        if let Some(user) = &self.user {
+            self.audit.record(self.addr, user, message);
+        }
+        let response = Response::new(
+            format!("{}{}", get_format(Format::Error), message),
+            self.current_path.clone(),
+        );
+        let reply = self.encode_reply("", &response, false);
+        let _ = timeout(FINAL_WRITE_TIMEOUT, async {
+            write_frame(stream, reply.as_bytes()).await?;
+            stream.shutdown().await
+        })
+        .await;
+    }
+    /// Decodes one request frame and returns the encoded reply.
+    async fn handle_request(&mut self, received: String) -> String {
+        match Request::decode(&received) {
+            Ok(Request::Command(command)) => match &self.user {
+                None => {
+                    println!("{} sent a login request", self.addr);
+                    self.login(command).await
+                }
+                Some(user) => {
+                    println!("{}:{} sent: {}", user, self.addr, command.trim());
+                    self.run_command(command).await
+                }
+            },
+            Ok(Request::SetMode(ResponseMode::Json))
+                if !self.capabilities.contains(&Capability::Json) =>
+            {
+                let response = Response::new(
+                    format!(
+                        "{}The json response mode was not negotiated",
+                        get_format(Format::Error)
+                    ),
+                    self.current_path.clone(),
+                );
+                self.encode_reply(&received, &response, false)
+            }
+            Ok(Request::SetMode(mode)) => {
+                self.mode = mode;
+                let response = Response::new(
+                    format!(
+                        "{}Response mode: {}",
+                        get_format(Format::Normal),
+                        mode.name()
+                    ),
+                    self.current_path.clone(),
+                );
+                self.encode_reply(&received, &response, true)
+            }
+            Ok(Request::Hello(hello)) => self.handshake(hello),
+            Ok(Request::Complete(request)) => self.complete(&request),
+            Ok(Request::KeyLogin(request)) => self.key_login(request).await,
+            Ok(Request::Ping) => Request::Ping.encode(),
+            Err(e) => {
+                let response = Response::new(
+                    format!("{}{}", get_format(Format::Error), e),
+                    self.current_path.clone(),
+                );
+                self.encode_reply(&received, &response, false)
+            }
+        }
+    }
+    /// Answers the client `Hello` and records the negotiated capabilities.
+    ///
+    /// The reply is the server `Hello`, sent as is whatever the response mode, so
+    /// the client can compute the same capability set on its side.
+    fn handshake(&mut self, hello: Hello) -> String {
+        let mut server_hello = Hello::new(
+            concat!("server/", env!("CARGO_PKG_VERSION")),
+            &Capability::ALL,
+        );
+        if let Some(keepalive) = self.limits.keepalive {
+            server_hello = server_hello.with_keepalive(keepalive.as_secs());
+        }
+        self.capabilities = server_hello.negotiate(&hello);
+        self.client_version = Some(hello.agent.clone());
+        println!(
+            "{} handshake: {} protocol v{} [{}]",
+            self.addr,
+            hello.agent,
+            server_hello.negotiated_version(&hello),
+            self.capabilities
+                .iter()
+                .map(|c| c.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        server_hello.encode()
+    }
+    /// Answers a tab completion request.
+    ///
+    /// Like the `Hello`, the reply is sent as is whatever the response mode. Clients
+    /// that did not negotiate `Capability::Complete` or are not logged in get no
+    /// candidates.
+    fn complete(&self, request: &CompletionRequest) -> String {
+        if self.user.is_none() || !self.capabilities.contains(&Capability::Complete) {
+            return Completions::default().encode();
+        }
+        Completion::new(self.sandbox_root(), self.server_path.clone())
+            .get_completions(request)
+            .encode()
+    }
+    /// Authenticates the client with a `login` command.
+    ///
+    /// The reply never echoes the command line back, so the password cannot leak
+    /// into a json result.
+    async fn login(&mut self, command: String) -> String {
+        let login = UserLogin::new(command, self.user_store.clone())
+            .with_certificate(self.certificate.clone())
+            .with_guard(self.login_guard.clone(), self.addr.ip())
+            .with_audit(self.audit.clone(), self.addr);
+        let (response, succes) = match login.get_login_status().await {
+            Ok((user_name, account)) => self.accept_login(user_name, &account).await,
+            Err(e) => (e, false),
+        };
+        self.encode_reply("login", &response, succes)
+    }
+    /// Runs one step of a public key login.
+    ///
+    /// `KeyLogin::Start` is answered with a fresh random `Challenge`, sent as is
+    /// whatever the response mode, even for unknown users. `KeyLogin::Proof` answers
+    /// the last challenge, which can only be used once, and gets the same reply as a
+    /// `login` command.
+    async fn key_login(&mut self, request: KeyLogin) -> String {
+        if self.user.is_some() || !self.capabilities.contains(&Capability::PublicKey) {
+            let response = Response::new(
+                format!(
+                    "{}A public key login is not possible now",
+                    get_format(Format::Error)
+                ),
+                self.current_path.clone(),
+            );
+            return self.encode_reply("login", &response, false);
+        }
+        match request {
+            KeyLogin::Start { user } => {
+                println!("{} requested a key login challenge for {}", self.addr, user);
+                let mut challenge = vec![0u8; CHALLENGE_LEN];
+                if SystemRandom::new().fill(&mut challenge).is_err() {
+                    self.challenge = None;
+                    let response = Response::new(
+                        format!("{}Error creating the challenge", get_format(Format::Error)),
+                        self.current_path.clone(),
+                    );
+                    return self.encode_reply("login", &response, false);
+                }
+                let reply = Challenge::new(&challenge).encode();
+                self.challenge = Some((user, challenge));
+                reply
+            }
+            KeyLogin::Proof { signature } => {
+                let (status, succes) = match (self.challenge.take(), decode_base64(&signature)) {
+                    (Some((user, challenge)), Some(signature)) => {
+                        let login = UserLogin::new(String::new(), self.user_store.clone())
+                            .with_certificate(self.certificate.clone())
+                            .with_guard(self.login_guard.clone(), self.addr.ip())
+                            .with_audit(self.audit.clone(), self.addr);
+                        match login
+                            .get_key_login_status(&user, &challenge, &signature)
+                            .await
+                        {
+                            Ok((user_name, account)) => {
+                                self.accept_login(user_name, &account).await
+                            }
+                            Err(e) => (e, false),
+                        }
+                    }
+                    _ => (
+                        Response::new(
+                            format!(
+                                "{}No key login challenge to answer",
+                                get_format(Format::Error)
+                            ),
+                            "",
+                        ),
+                        false,
+                    ),
+                };
+                self.encode_reply("login", &status, succes)
+            }
+        }
+    }
+    /// Registers `user_name` as the session user and returns the login reply with
+    /// its status.
+    ///
+    /// The home directory of the account is created on the first login and becomes
+    /// the current directory; the login is refused if it cannot be created, if the
+    /// role of the account is not defined, or if the user already has as many
+    /// sessions as the account allows and its `SessionPolicy` is `reject`. With
+    /// `takeover`, the oldest sessions of the user are closed instead.
+    async fn accept_login(&mut self, user_name: String, account: &Account) -> (Response, bool) {
+        let Some(permissions) = self.policy.permissions(account) else {
+            self.audit.record(
+                self.addr,
+                &user_name,
+                "login refused: the role of the account is not defined",
+            );
+            let response = Response::new(format!("{}Unknown role", get_format(Format::Error)), "");
+            return (response, false);
+        };
+        let home = self.root_path.join(account.home_dir(&user_name));
+        if let Err(e) = std::fs::create_dir_all(&home) {
+            eprintln!(
+                "{} cannot create the home of {}: {}",
+                self.addr, user_name, e
+            );
+            let response = Response::new(
+                format!(
+                    "{}Error creating the home directory",
+                    get_format(Format::Error)
+                ),
+                "",
+            );
+            return (response, false);
+        }
+        let home = std::fs::canonicalize(&home).unwrap_or(home);
+        let now = SystemTime::now();
+        let info = SessionInfo {
+            id: self.id,
+            user: user_name.clone(),
+            peer: self.addr,
+            login_time: now,
+            last_activity: now,
+            directory: self.root_relative(&home),
+            client_version: self.client_version.clone(),
+        };
+        let registered = self
+            .sessions
+            .register(info, self.close.clone(), account.session_limit());
+        let taken_over = match registered {
+            Ok((registration, taken_over)) => {
+                self.registration = Some(registration);
+                taken_over
+            }
+            Err(message) => {
+                self.audit.record(
+                    self.addr,
+                    &user_name,
+                    &format!("login refused: {}", message),
+                );
+                let response =
+                    Response::new(format!("{}{}", get_format(Format::Error), message), "");
+                return (response, false);
+            }
+        };
+        self.audit.record(self.addr, &user_name, "login accepted");
+        for session in taken_over {
+            self.audit.record(
+                self.addr,
+                &user_name,
+                &format!("took over session {} from {}", session.id, session.peer),
+            );
+        }
+        self.home = home;
+        self.confined = account.confined;
+        self.permissions = permissions;
+        self.server_path = self.home.clone();
+        self.current_path = self
+            .server_path
+            .strip_prefix(self.sandbox_root())
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        self.user = Some(user_name);
+        let response = Response::new(
+            format!("{}Succesful login", get_format(Format::Normal)),
+            self.current_path.clone(),
+        );
+        (response, true)
+    }
+    /// Returns the directory the commands of the user cannot leave: their home for
+    /// `confined` accounts, the server root otherwise.
+    fn sandbox_root(&self) -> PathBuf {
+        if self.confined {
+            self.home.clone()
+        } else {
+            std::fs::canonicalize(&self.root_path).unwrap_or(self.root_path.clone())
+        }
+    }
+    /// Executes a command line for the authenticated user.
+    async fn run_command(&mut self, command: String) -> String {
+        let mut command_handler = CommandHandler::new(
+            command.clone(),
+            self.sandbox_root(),
+            self.server_path.clone(),
+            self.sessions.clone(),
+        )
+        .with_home(self.home.clone())
+        .with_permissions(self.permissions.clone())
+        .with_login_guard(self.login_guard.clone());
+        if let Some(limiter) = &self.connections {
+            command_handler = command_handler.with_connections(limiter.clone());
+        }
+        let (response, new_server_path, succes) = command_handler.get_output().await;
+        self.server_path = new_server_path;
+        self.current_path = response.path.clone();
+        //dbg!(&response);
+        self.encode_reply(&command, &response, succes)
+    }
+    /// Returns `path` relative to the server root, whatever the sandbox of the user,
+    /// as listed by `who`.
+    fn root_relative(&self, path: &Path) -> String {
+        let root = std::fs::canonicalize(&self.root_path).unwrap_or(self.root_path.clone());
+        path.strip_prefix(root)
+            .map(|path| path.display().to_string())
+            .unwrap_or_default()
+    }
+    /// Records the activity and the current directory of a logged in session.
+    fn touch(&self) {
+        if self.user.is_some() {
+            let directory = self.root_relative(&self.server_path);
+            self.sessions.touch(self.id, &directory);
+        }
+    }
+    /// Removes the session from the `SessionRegistry` by dropping its `Registration`.
+    fn logout(&mut self) {
+        self.user = None;
+        self.registration = None;
+    }
+    /// Encodes the reply to a request according to the session response mode.
+    ///
+    /// In `ResponseMode::Text` the response is sent as the marker based payload, with
+    /// the exit status appended when `Capability::Status` was negotiated. In
+    /// `ResponseMode::Json` it is converted into a `CommandResult` document for `command`.
+    fn encode_reply(&self, command: &str, response: &Response, succes: bool) -> String {
+        match self.mode {
+            ResponseMode::Text if self.capabilities.contains(&Capability::Status) => {
+                response.clone().with_status(succes).encode()
+            }
+            ResponseMode::Text => response.encode(),
+            ResponseMode::Json => {
+                CommandResult::from_output(command, &response.output, succes, &response.path)
+                    .to_json()
+            }
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::SystemTime;
+use tokio::sync::Notify;
+
+/// Identifier of a client connection, unique for the lifetime of the server.
+pub type SessionId = u64;
+
+/// What a login does when the user already has as many sessions as allowed.
+///
+/// # Variants
+///
+/// - `Reject`: The new login is refused (the default)
+/// - `Takeover`: The oldest session of the user is closed to make room
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionPolicy {
+    #[default]
+    Reject,
+    Takeover,
+}
+
+impl SessionPolicy {
+    /// Returns true for the default policy, which the password file leaves out.
+    pub fn is_reject(&self) -> bool {
+        *self == SessionPolicy::Reject
+    }
+}
+
+/// How many sessions a user may have open at once, and what happens beyond.
+///
+/// - `max_sessions`: Sessions allowed at once; `None` for no limit
+/// - `policy`: What a login beyond the limit does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionLimit {
+    pub max_sessions: Option<usize>,
+    pub policy: SessionPolicy,
+}
+
+/// What the server knows about one logged in session.
+///
+/// # Fields
+///
+/// - `id`: Identifier of the connection
+/// - `user`: Name of the authenticated user
+/// - `peer`: Address of the client
+/// - `login_time`: When the login succeeded
+/// - `last_activity`: When the client last sent a request
+/// - `directory`: Current directory, relative to the server root
+/// - `client_version`: Agent sent in the client `Hello`, if any (e.g. `client/0.1.0`)
+#[derive(Clone, Debug)]
+pub struct SessionInfo {
+    pub id: SessionId,
+    pub user: String,
+    pub peer: SocketAddr,
+    pub login_time: SystemTime,
+    pub last_activity: SystemTime,
+    pub directory: String,
+    pub client_version: Option<String>,
+}
+
+/// A registered session with the signal that closes it.
+struct Entry {
+    info: SessionInfo,
+    close: Arc<Notify>,
+}
+
+/// The logged in sessions of the server, keyed by `SessionId`.
+///
+/// `SessionRegistry` is shared by every session. A connection gets its identifier
+/// with `new_id()` when it is accepted and is registered once its login succeeds.
+/// Registering returns a `Registration`, which removes the session when it is
+/// dropped, so a session leaves the registry however its task ends, panics
+/// included. The same user can appear under several identifiers without the
+/// sessions being mixed up.
+///
+/// Registration enforces the `SessionLimit` of the account under a single lock, so
+/// concurrent logins of the same user cannot both get the last free place. Sessions
+/// taken over are removed at once and told to close through their `Notify`.
+///
+/// # Examples
+///
+/// ```rust
+/// let sessions = Arc::new(SessionRegistry::default());
+/// let id = sessions.new_id();
+/// let close = Arc::new(Notify::new());
+/// let info = SessionInfo {
+///     id,
+///     user: "alice".to_string(),
+///     peer: addr,
+///     login_time: SystemTime::now(),
+///     last_activity: SystemTime::now(),
+///     directory: "alice".to_string(),
+///     client_version: Some("client/0.1.0".to_string()),
+/// };
+/// let limit = SessionLimit { max_sessions: Some(1), policy: SessionPolicy::Takeover };
+/// let (registration, taken_over) = sessions.register(info, close.clone(), limit)?;
+/// // ... close.notified().await ends the session when another login takes over
+/// drop(registration);
+/// ```
+#[derive(Default)]
+pub struct SessionRegistry {
+    next_id: AtomicU64,
+    sessions: RwLock<BTreeMap<SessionId, Entry>>,
+}
+
+impl SessionRegistry {
+    /// Returns a new session identifier; the first one is `1`.
+    pub fn new_id(&self) -> SessionId {
+        self.next_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<SessionId, Entry>> {
+        self.sessions.read().unwrap_or_else(|e| e.into_inner())
+    }
+    fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<SessionId, Entry>> {
+        self.sessions.write().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Registers a logged in session within the `limit` of its user.
+    ///
+    /// Only the sessions of exactly the same user count: `bob` is not limited by the
+    /// sessions of `bobby`. `close` is notified if the session is taken over later.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((Registration, Vec<SessionInfo>))`: The registration of the session, which
+    ///   has to be kept until it ends, and the sessions closed to make room, oldest
+    ///   login first
+    /// - `Err(String)`: Why the login is refused, to be sent to the client
+    pub fn register(
+        self: &Arc<Self>,
+        info: SessionInfo,
+        close: Arc<Notify>,
+        limit: SessionLimit,
+    ) -> Result<(Registration, Vec<SessionInfo>), String> {
+        let mut sessions = self.write();
+        let mut open: Vec<&SessionInfo> = sessions
+            .values()
+            .map(|entry| &entry.info)
+            .filter(|session| session.user == info.user && session.id != info.id)
+            .collect();
+        open.sort_by_key(|session| (session.login_time, session.id));
+        let excess = match limit.max_sessions {
+            Some(max) => (open.len() + 1).saturating_sub(max),
+            None => 0,
+        };
+        let oldest: Vec<SessionId> = open.iter().take(excess).map(|session| session.id).collect();
+        if excess > 0 && limit.policy == SessionPolicy::Reject {
+            return Err(match limit.max_sessions {
+                Some(1) => "User already logged in".to_string(),
+                _ => format!("User already has {} sessions open", open.len()),
+            });
+        }
+        let mut taken_over = Vec::new();
+        for id in oldest {
+            if let Some(entry) = sessions.remove(&id) {
+                entry.close.notify_one();
+                taken_over.push(entry.info);
+            }
+        }
+        let registration = Registration {
+            registry: self.clone(),
+            id: info.id,
+        };
+        sessions.insert(info.id, Entry { info, close });
+        Ok((registration, taken_over))
+    }
+    /// Records a request of session `id` and its current `directory`.
+    pub fn touch(&self, id: SessionId, directory: &str) {
+        if let Some(Entry { info: session, .. }) = self.write().get_mut(&id) {
+            session.last_activity = SystemTime::now();
+            if session.directory != directory {
+                session.directory = directory.to_string();
+            }
+        }
+    }
+    /// Returns the logged in sessions, in the order of their identifiers.
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        self.read()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+}
+
+/// The place of a logged in session in a `SessionRegistry`, removed on drop.
+///
+/// A session taken over is already removed by `register()`; dropping its
+/// registration later leaves the new entries alone.
+pub struct Registration {
+    registry: Arc<SessionRegistry>,
+    id: SessionId,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.registry.write().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: SessionId, user: &str) -> SessionInfo {
+        SessionInfo {
+            id,
+            user: user.to_string(),
+            peer: "127.0.0.1:40000".parse().unwrap(),
+            login_time: SystemTime::now(),
+            last_activity: SystemTime::now(),
+            directory: user.to_string(),
+            client_version: None,
+        }
+    }
+
+    fn register(
+        sessions: &Arc<SessionRegistry>,
+        user: &str,
+        limit: SessionLimit,
+    ) -> Result<(Registration, Vec<SessionInfo>), String> {
+        let id = sessions.new_id();
+        sessions.register(info(id, user), Arc::new(Notify::new()), limit)
+    }
+
+    const ONE: SessionLimit = SessionLimit {
+        max_sessions: Some(1),
+        policy: SessionPolicy::Takeover,
+    };
+
+    #[test]
+    fn dropping_the_registration_removes_the_session() {
+        let sessions = Arc::new(SessionRegistry::default());
+        let (registration, _) = register(&sessions, "alice", ONE).unwrap();
+        assert_eq!(sessions.sessions().len(), 1);
+        drop(registration);
+        assert!(sessions.sessions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_panicking_session_is_removed() {
+        let sessions = Arc::new(SessionRegistry::default());
+        let task = tokio::spawn({
+            let sessions = sessions.clone();
+            async move {
+                let _registration = register(&sessions, "alice", ONE).unwrap();
+                panic!("session task panicked");
+            }
+        });
+        assert!(task.await.is_err());
+        assert!(sessions.sessions().is_empty());
+    }
+
+    #[test]
+    fn a_session_taken_over_leaves_the_new_one_registered() {
+        let sessions = Arc::new(SessionRegistry::default());
+        let (old, _) = register(&sessions, "alice", ONE).unwrap();
+        let (_new, taken_over) = register(&sessions, "alice", ONE).unwrap();
+        assert_eq!(taken_over.len(), 1);
+        drop(old);
+        let open = sessions.sessions();
+        assert_eq!(open.len(), 1);
+        assert_ne!(open[0].id, taken_over[0].id);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::user_store::UserStore;
+use data_encoding::BASE32_NOPAD;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::error::Error;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Issuer shown by authenticator apps.
+const ISSUER: &str = "SecureShell";
+/// Number of digits of a code.
+const DIGITS: u32 = 6;
+/// Seconds during which a code is valid.
+const PERIOD: u64 = 30;
+/// Number of periods before and after the current one that are still accepted, to
+/// allow for clock drift between the server and the phone.
+const SKEW: u64 = 1;
+/// Bytes of random data in a new secret (160 bits, as RFC 4226 recommends).
+const SECRET_LEN: usize = 20;
+
+/// A time-based one-time password generator (RFC 6238).
+///
+/// Codes are 6 digits, change every 30 seconds and are computed with HMAC-SHA1,
+/// the parameters every authenticator app supports. The secret of an account is
+/// stored in base32 in the `totp` entry of its `user_store::Account`.
+///
+/// # Examples
+///
+/// ```rust
+/// let totp = Totp::from_base32("JBSWY3DPEHPK3PXP").unwrap();
+/// println!("{}", totp.provisioning_uri("alice"));
+/// // otpauth://totp/SecureShell:alice?secret=JBSWY3DPEHPK3PXP&issuer=SecureShell&...
+/// if totp.verify(&code) { /* second factor accepted */ }
+/// ```
+pub struct Totp {
+    secret: Vec<u8>,
+}
+
+impl Totp {
+    /// Creates a generator with a new random secret.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the system random source is unavailable.
+    pub fn generate() -> Result<Self, Box<dyn Error>> {
+        let mut secret = vec![0u8; SECRET_LEN];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .map_err(|_| "no random source for the TOTP secret")?;
+        Ok(Totp { secret })
+    }
+    /// Reads a base32 secret, as stored in the password file or shown by apps.
+    pub fn from_base32(secret: &str) -> Option<Self> {
+        let secret = secret.trim().trim_end_matches('=').to_ascii_uppercase();
+        let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+        (!secret.is_empty()).then_some(Totp { secret })
+    }
+    /// Returns the secret in base32.
+    pub fn secret_base32(&self) -> String {
+        BASE32_NOPAD.encode(&self.secret)
+    }
+    /// Returns the `otpauth://` URI that authenticator apps import, usually as a
+    /// QR code.
+    pub fn provisioning_uri(&self, user: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD}",
+            issuer = ISSUER,
+            user = percent_encode(user),
+            secret = self.secret_base32(),
+        )
+    }
+    /// Checks a code against the current time, accepting the neighbouring periods.
+    pub fn verify(&self, code: &str) -> bool {
+        let code = code.trim();
+        if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+            return false;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        let step = now / PERIOD;
+        (step.saturating_sub(SKEW)..=step + SKEW)
+            .any(|step| format!("{:0width$}", self.code_at(step), width = DIGITS as usize) == code)
+    }
+    /// Computes the code of a time step (HOTP of RFC 4226).
+    fn code_at(&self, step: u64) -> u32 {
+        let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &self.secret);
+        let tag = hmac::sign(&key, &step.to_be_bytes());
+        let digest = tag.as_ref();
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let value = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+        value % 10u32.pow(DIGITS)
+    }
+}
+
+/// Enables TOTP for `user` by storing a new secret in the user database.
+///
+/// Any previous secret of `user` is replaced; the file keeps its format.
+///
+/// # Errors
+///
+/// Fails if the file cannot be read or written, `user` is not in it or has no
+/// password to protect.
+pub fn enroll(password_file: &Path, user: &str) -> Result<Totp, Box<dyn Error>> {
+    let mut store = UserStore::load(password_file)?;
+    let account = store.get_mut(user).ok_or(format!(
+        "user {} not found in {}",
+        user,
+        password_file.display()
+    ))?;
+    if account.hash.is_none() {
+        return Err(format!("{} has no password to protect", user).into());
+    }
+    let totp = Totp::generate()?;
+    account.totp = Some(totp.secret_base32());
+    store.save()?;
+    Ok(totp)
+}
+
+/// Encodes the characters of `text` that are not allowed in a URI path segment.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,544 @@
+use crate::session_registry::{SessionLimit, SessionPolicy};
+use protocol::auth::PUBLIC_KEY_PREFIX;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::RwLock;
+
+/// Prefix of the TOTP option in a legacy password file record.
+const LEGACY_TOTP_PREFIX: &str = "totp:";
+/// Separator between the hash of a legacy record and its options.
+const LEGACY_OPTION_SEPARATOR: char = ',';
+
+/// One user account of the user database.
+///
+/// # Fields
+///
+/// - `hash`: Bcrypt hash of the password, for password logins
+/// - `public_key`: Ed25519 public key (`ed25519:...`), for public key logins
+/// - `totp`: Base32 TOTP secret; the account then needs a one-time code
+/// - `home`: Home directory, relative to the server root (the user name by default)
+/// - `confined`: The user cannot leave their home directory
+/// - `role`: Role of the user (e.g. `admin`, `user`, `read-only`)
+/// - `disabled`: The account cannot log in
+/// - `allowed_commands`: Commands the user may run, when restricted
+/// - `max_sessions`: Sessions the user may have open at once (1 by default, 0 for
+///   no limit)
+/// - `session_policy`: What a login beyond `max_sessions` does, see `SessionPolicy`
+///
+/// Every field is optional, so an account only lists what it uses.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub totp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub home: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub confined: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_commands: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_sessions: Option<usize>,
+    #[serde(default, skip_serializing_if = "SessionPolicy::is_reject")]
+    pub session_policy: SessionPolicy,
+}
+
+impl Account {
+    /// Returns the home directory of the account of `username`, relative to the
+    /// server root.
+    ///
+    /// Accounts without a `home` live in a directory named after the user.
+    pub fn home_dir(&self, username: &str) -> PathBuf {
+        self.home.clone().unwrap_or_else(|| PathBuf::from(username))
+    }
+    /// Returns how many sessions the user may have open at once.
+    ///
+    /// Accounts without `max_sessions` get a single session.
+    pub fn session_limit(&self) -> SessionLimit {
+        SessionLimit {
+            max_sessions: match self.max_sessions {
+                None => Some(1),
+                Some(0) => None,
+                Some(max) => Some(max),
+            },
+            policy: self.session_policy,
+        }
+    }
+}
+
+/// On-disk format of a user database file.
+///
+/// # Variants
+///
+/// - `Toml`: One `[users.NAME]` table per account
+/// - `Legacy`: The original `USERNAME RECORD` lines, where the record is a bcrypt
+///   hash (optionally followed by `,totp:SECRET`) or an `ed25519:` public key
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoreFormat {
+    Toml,
+    Legacy,
+}
+
+/// Serialized form of a TOML user database.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct UserFile {
+    #[serde(default)]
+    users: BTreeMap<String, Account>,
+}
+
+/// The user database read from the password file.
+///
+/// The store reads both the structured TOML format and the legacy whitespace
+/// format, and writes accounts back in the format they were read from, so existing
+/// password files keep working until they are migrated with `--migrate-users`.
+///
+/// # TOML Format
+///
+/// ```toml
+/// [users.alice]
+/// hash = "$2b$12$hash1..."
+/// totp = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP"
+/// home = "alice"
+/// role = "admin"
+///
+/// [users.bob]
+/// hash = "$2b$12$hash2..."
+/// confined = true
+/// max_sessions = 2
+/// session_policy = "takeover"
+///
+/// [users.dave]
+/// public_key = "ed25519:Lz6KYrjdWHnmqJ8w1yTSyH3+4RNhT8B9P7hf1kWcQnM="
+/// allowed_commands = ["ls", "cat", "cd", "pwd"]
+///
+/// [users.mallory]
+/// hash = "$2b$12$hash3..."
+/// disabled = true
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// let store = UserStore::load(Path::new("/etc/secure_shell/users.toml"))?;
+/// if let Some(account) = store.get("alice") {
+///     println!("alice has role {:?}", account.role);
+/// }
+/// ```
+pub struct UserStore {
+    path: PathBuf,
+    format: StoreFormat,
+    accounts: BTreeMap<String, Account>,
+}
+
+impl UserStore {
+    /// Reads the user database at `path`, detecting its format.
+    ///
+    /// A file whose first entry is a TOML table (`[users...]`) is read as TOML,
+    /// anything else as legacy lines. Empty lines and lines starting with `#` are
+    /// ignored in both formats.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or contains an invalid entry; the error names
+    /// the offending line.
+    pub fn load(path: &Path) -> Result<UserStore, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read password file {}: {}", path.display(), e))?;
+        let is_toml = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .is_some_and(|line| line.starts_with('['));
+        let (format, accounts) = if is_toml {
+            let file: UserFile = toml::from_str(&text)
+                .map_err(|e| format!("invalid password file {}: {}", path.display(), e))?;
+            (StoreFormat::Toml, file.users)
+        } else {
+            let accounts = parse_legacy(&text)
+                .map_err(|e| format!("invalid password file {}: {}", path.display(), e))?;
+            (StoreFormat::Legacy, accounts)
+        };
+        for (name, account) in &accounts {
+            if !is_inside(&account.home_dir(name)) {
+                return Err(format!(
+                    "invalid password file {}: the home of {} must be a relative path inside the root",
+                    path.display(),
+                    name
+                )
+                .into());
+            }
+        }
+        Ok(UserStore {
+            path: path.to_path_buf(),
+            format,
+            accounts,
+        })
+    }
+    /// Returns the account of `username`, if it exists.
+    pub fn get(&self, username: &str) -> Option<&Account> {
+        self.accounts.get(username)
+    }
+    /// Returns every account with its user name, sorted by name.
+    pub fn accounts(&self) -> impl Iterator<Item = (&String, &Account)> {
+        self.accounts.iter()
+    }
+    /// Returns the account of `username` for modification, if it exists.
+    pub fn get_mut(&mut self, username: &str) -> Option<&mut Account> {
+        self.accounts.get_mut(username)
+    }
+    /// Writes the accounts back to the file they were read from, in its format.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be written, or if an account uses a setting the
+    /// legacy format cannot store; migrate the file to TOML first in that case.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let text = match self.format {
+            StoreFormat::Toml => self.to_toml()?,
+            StoreFormat::Legacy => self.to_legacy()?,
+        };
+        std::fs::write(&self.path, text)?;
+        Ok(())
+    }
+    /// Converts a legacy password file to the TOML format.
+    ///
+    /// The original file is kept next to it with a `.legacy` extension.
+    ///
+    /// # Returns
+    ///
+    /// The path of the backup of the original file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file is already in the TOML format or cannot be written.
+    pub fn migrate(&mut self) -> Result<PathBuf, Box<dyn Error>> {
+        if self.format == StoreFormat::Toml {
+            return Err(format!("{} already uses the TOML format", self.path.display()).into());
+        }
+        let mut backup = self.path.clone().into_os_string();
+        backup.push(".legacy");
+        let backup = PathBuf::from(backup);
+        std::fs::copy(&self.path, &backup)?;
+        self.format = StoreFormat::Toml;
+        self.save()?;
+        Ok(backup)
+    }
+    fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+        let file = UserFile {
+            users: self.accounts.clone(),
+        };
+        Ok(toml::to_string_pretty(&file)?)
+    }
+    fn to_legacy(&self) -> Result<String, Box<dyn Error>> {
+        let mut text = String::new();
+        for (name, account) in &self.accounts {
+            let record = match account {
+                Account {
+                    hash: Some(hash),
+                    public_key: None,
+                    totp,
+                    home: None,
+                    confined: false,
+                    role: None,
+                    disabled: false,
+                    allowed_commands: None,
+                    max_sessions: None,
+                    session_policy: SessionPolicy::Reject,
+                } => match totp {
+                    Some(secret) => format!(
+                        "{}{}{}{}",
+                        hash, LEGACY_OPTION_SEPARATOR, LEGACY_TOTP_PREFIX, secret
+                    ),
+                    None => hash.clone(),
+                },
+                Account {
+                    hash: None,
+                    public_key: Some(key),
+                    totp: None,
+                    home: None,
+                    confined: false,
+                    role: None,
+                    disabled: false,
+                    allowed_commands: None,
+                    max_sessions: None,
+                    session_policy: SessionPolicy::Reject,
+                } => key.clone(),
+                _ => {
+                    return Err(format!(
+                        "the settings of {} need the TOML format, run the server with --migrate-users",
+                        name
+                    )
+                    .into());
+                }
+            };
+            text.push_str(&format!("{} {}\n", name, record));
+        }
+        Ok(text)
+    }
+}
+
+/// Modification time and length of the password file, to notice that it changed.
+type FileStamp = (Option<SystemTime>, u64);
+
+/// The loaded accounts with the stamp of the file they were read from.
+struct CachedAccounts {
+    store: Arc<UserStore>,
+    stamp: FileStamp,
+}
+
+/// The user database shared by every session.
+///
+/// The password file is read once at startup. Each login only checks the
+/// modification time and length of the file, and reads it again when they changed,
+/// so accounts can still be added or changed while the server runs. A file that
+/// becomes unreadable or invalid is reported and the previous accounts stay in use
+/// until it is fixed.
+///
+/// # Examples
+///
+/// ```rust
+/// let users = CachedUserStore::load(Path::new("/etc/secure_shell/users.toml"))?;
+/// let store = users.get().await;
+/// if let Some(account) = store.get("alice") {
+///     println!("alice has role {:?}", account.role);
+/// }
+/// ```
+pub struct CachedUserStore {
+    path: PathBuf,
+    cache: RwLock<CachedAccounts>,
+}
+
+impl CachedUserStore {
+    /// Reads the user database at `path`, like `UserStore::load`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or contains an invalid entry.
+    pub fn load(path: &Path) -> Result<CachedUserStore, Box<dyn Error>> {
+        let stamp = std::fs::metadata(path)
+            .map(|meta| stamp(&meta))
+            .unwrap_or_default();
+        let store = UserStore::load(path)?;
+        Ok(CachedUserStore {
+            path: path.to_path_buf(),
+            cache: RwLock::new(CachedAccounts {
+                store: Arc::new(store),
+                stamp,
+            }),
+        })
+    }
+    /// Returns the current accounts, reading the file again if it changed.
+    ///
+    /// The file is read on the blocking thread pool, so a reload never holds up the
+    /// other sessions.
+    pub async fn get(&self) -> Arc<UserStore> {
+        let stamp = tokio::fs::metadata(&self.path)
+            .await
+            .map(|meta| stamp(&meta))
+            .unwrap_or_default();
+        {
+            let cache = self.cache.read().await;
+            if cache.stamp == stamp {
+                return cache.store.clone();
+            }
+        }
+        let mut cache = self.cache.write().await;
+        if cache.stamp != stamp {
+            let path = self.path.clone();
+            let loaded = tokio::task::spawn_blocking(move || {
+                UserStore::load(&path).map_err(|e| e.to_string())
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+            match loaded {
+                Ok(store) => {
+                    println!("password file {} reloaded", self.path.display());
+                    cache.store = Arc::new(store);
+                }
+                Err(e) => eprintln!("keeping the previous accounts: {}", e),
+            }
+            cache.stamp = stamp;
+        }
+        cache.store.clone()
+    }
+}
+
+/// Returns the `FileStamp` of a file from its metadata.
+fn stamp(meta: &std::fs::Metadata) -> FileStamp {
+    (meta.modified().ok(), meta.len())
+}
+
+/// Returns true if `path` is a relative path that stays below the directory it is
+/// joined to (no root, prefix or `..` component).
+fn is_inside(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Parses the legacy `USERNAME RECORD` lines.
+///
+/// Each line holds exactly one account, so a malformed line is reported instead of
+/// shifting the pairs that follow it.
+fn parse_legacy(text: &str) -> Result<BTreeMap<String, Account>, String> {
+    let mut accounts = BTreeMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let [name, record] = tokens.as_slice() else {
+            return Err(format!("line {}: expected USERNAME HASH", number + 1));
+        };
+        let mut account = Account::default();
+        if record.starts_with(PUBLIC_KEY_PREFIX) {
+            account.public_key = Some(record.to_string());
+        } else {
+            let mut parts = record.split(LEGACY_OPTION_SEPARATOR);
+            account.hash = parts.next().map(str::to_string);
+            account.totp = parts
+                .find_map(|option| option.strip_prefix(LEGACY_TOTP_PREFIX))
+                .map(str::to_string);
+        }
+        if accounts.insert(name.to_string(), account).is_some() {
+            return Err(format!("line {}: duplicate user {}", number + 1, name));
+        }
+    }
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "$2b$12$abcdefghijklmnopqrstuu5xQSDmZG1xUE7vCdX4vC.wKMz4IuSdC";
+    const KEY: &str = "ed25519:Lz6KYrjdWHnmqJ8w1yTSyH3+4RNhT8B9P7hf1kWcQnM=";
+
+    fn load(text: &str) -> (tempfile::TempDir, Result<UserStore, Box<dyn Error>>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users");
+        std::fs::write(&path, text).unwrap();
+        let store = UserStore::load(&path);
+        (dir, store)
+    }
+
+    #[test]
+    fn legacy_records_are_parsed() {
+        let text =
+            format!("# accounts\n\nalice {HASH}\nbob {HASH},totp:JBSWY3DPEHPK3PXP\ndave {KEY}\n");
+        let (_dir, store) = load(&text);
+        let store = store.unwrap();
+        assert_eq!(store.format, StoreFormat::Legacy);
+        let alice = store.get("alice").unwrap();
+        assert_eq!(alice.hash.as_deref(), Some(HASH));
+        assert_eq!(alice.totp, None);
+        let bob = store.get("bob").unwrap();
+        assert_eq!(bob.hash.as_deref(), Some(HASH));
+        assert_eq!(bob.totp.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+        let dave = store.get("dave").unwrap();
+        assert_eq!(dave.hash, None);
+        assert_eq!(dave.public_key.as_deref(), Some(KEY));
+        assert_eq!(store.accounts().count(), 3);
+    }
+
+    #[test]
+    fn invalid_legacy_lines_are_reported() {
+        for (text, error) in [
+            (
+                format!("alice {HASH}\nbob\n"),
+                "line 2: expected USERNAME HASH",
+            ),
+            (
+                format!("alice {HASH} extra\n"),
+                "line 1: expected USERNAME HASH",
+            ),
+            (
+                format!("alice {HASH}\nalice {KEY}\n"),
+                "line 2: duplicate user alice",
+            ),
+        ] {
+            let (_dir, store) = load(&text);
+            let error_text = store.err().unwrap().to_string();
+            assert!(error_text.contains(error), "{}", error_text);
+        }
+    }
+
+    #[test]
+    fn toml_files_are_detected() {
+        let text = format!(
+            "# accounts\n[users.alice]\nhash = \"{HASH}\"\nrole = \"admin\"\nmax_sessions = 0\n\n[users.bob]\npublic_key = \"{KEY}\"\nhome = \"shared/bob\"\nconfined = true\n"
+        );
+        let (_dir, store) = load(&text);
+        let store = store.unwrap();
+        assert_eq!(store.format, StoreFormat::Toml);
+        let alice = store.get("alice").unwrap();
+        assert_eq!(alice.role.as_deref(), Some("admin"));
+        assert_eq!(alice.session_limit().max_sessions, None);
+        assert_eq!(alice.home_dir("alice"), PathBuf::from("alice"));
+        let bob = store.get("bob").unwrap();
+        assert!(bob.confined);
+        assert_eq!(bob.home_dir("bob"), PathBuf::from("shared/bob"));
+        assert_eq!(bob.session_limit().max_sessions, Some(1));
+        let (_dir, store) = load("[users.alice]\npassword = \"secret\"\n");
+        assert!(store.is_err());
+    }
+
+    #[test]
+    fn homes_must_stay_inside_the_root() {
+        for home in ["/etc", "../alice", "alice/../../etc", ""] {
+            let (_dir, store) = load(&format!("[users.alice]\nhome = \"{home}\"\n"));
+            assert!(store.is_err(), "{}", home);
+        }
+        for name in ["..", "/root"] {
+            let (_dir, store) = load(&format!("[users.\"{name}\"]\nhash = \"{HASH}\"\n"));
+            assert!(store.is_err(), "{}", name);
+        }
+        let (_dir, store) = load("[users.alice]\nhome = \"./shared/alice\"\n");
+        assert!(store.is_ok());
+    }
+
+    #[test]
+    fn migrate_keeps_a_legacy_backup() {
+        let text = format!("alice {HASH},totp:JBSWY3DPEHPK3PXP\ndave {KEY}\n");
+        let (dir, store) = load(&text);
+        let mut store = store.unwrap();
+        let backup = store.migrate().unwrap();
+        assert_eq!(backup, dir.path().join("users.legacy"));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), text);
+        assert!(store.migrate().is_err());
+        let migrated = UserStore::load(&dir.path().join("users")).unwrap();
+        assert_eq!(migrated.format, StoreFormat::Toml);
+        assert_eq!(migrated.accounts, store.accounts);
+    }
+
+    #[test]
+    fn legacy_files_refuse_settings_they_cannot_store() {
+        let text = format!("alice {HASH},totp:JBSWY3DPEHPK3PXP\ndave {KEY}\n");
+        let (_dir, store) = load(&text);
+        let mut store = store.unwrap();
+        assert_eq!(store.to_legacy().unwrap(), text);
+        store.get_mut("alice").unwrap().role = Some("admin".to_string());
+        assert!(store.save().is_err());
+        store.get_mut("alice").unwrap().role = None;
+        store.get_mut("dave").unwrap().totp = Some("JBSWY3DPEHPK3PXP".to_string());
+        assert!(store.to_legacy().is_err());
+        store.get_mut("dave").unwrap().totp = None;
+        store.get_mut("dave").unwrap().disabled = true;
+        assert!(store.to_legacy().is_err());
+    }
+}